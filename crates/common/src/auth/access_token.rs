@@ -108,6 +108,7 @@ impl Server {
                 .take_str_array(PrincipalField::Emails)
                 .unwrap_or_default(),
             quota: principal.quota(),
+            spam_filter_opt_out: principal.is_spam_filter_opt_out(),
             permissions,
         })
     }
@@ -32,6 +32,7 @@ pub struct AccessToken {
     pub quota: u64,
     pub permissions: Permissions,
     pub tenant: Option<TenantInfo>,
+    pub spam_filter_opt_out: bool,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
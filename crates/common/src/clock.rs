@@ -0,0 +1,80 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+#[cfg(feature = "test_mode")]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::Server;
+
+// Source of the current UNIX time for time-dependent features such as decay and
+// cooldowns. Outside of test mode it always returns the system time, in test mode
+// the clock can be frozen and advanced deterministically.
+#[derive(Debug, Default)]
+pub struct Clock {
+    #[cfg(feature = "test_mode")]
+    frozen: AtomicU64,
+}
+
+impl Clock {
+    pub fn now(&self) -> u64 {
+        #[cfg(feature = "test_mode")]
+        {
+            let frozen = self.frozen.load(Ordering::Relaxed);
+            if frozen != 0 {
+                return frozen;
+            }
+        }
+
+        store::write::now()
+    }
+
+    // Freezes the clock at the given UNIX time
+    #[cfg(feature = "test_mode")]
+    pub fn set(&self, timestamp: u64) {
+        self.frozen.store(timestamp, Ordering::Relaxed);
+    }
+
+    // Moves the clock forward, freezing it first if it was following the system time
+    #[cfg(feature = "test_mode")]
+    pub fn advance(&self, duration: std::time::Duration) -> u64 {
+        let timestamp = self.now() + duration.as_secs();
+        self.set(timestamp);
+        timestamp
+    }
+
+    // Goes back to the system time
+    #[cfg(feature = "test_mode")]
+    pub fn reset(&self) {
+        self.frozen.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Server {
+    pub fn now(&self) -> u64 {
+        self.inner.data.clock.now()
+    }
+}
+
+#[cfg(all(test, feature = "test_mode"))]
+mod tests {
+    use std::time::Duration;
+
+    use super::Clock;
+
+    #[test]
+    fn test_clock() {
+        let clock = Clock::default();
+        assert!(clock.now() >= store::write::now() - 1);
+
+        clock.set(1_000_000);
+        assert_eq!(clock.now(), 1_000_000);
+        assert_eq!(clock.advance(Duration::from_secs(3600)), 1_003_600);
+        assert_eq!(clock.now(), 1_003_600);
+
+        clock.reset();
+        assert!(clock.now() > 1_003_600);
+    }
+}
@@ -12,6 +12,7 @@ use dashmap::DashMap;
 use mail_send::smtp::tls::build_tls_connector;
 use nlp::bayes::cache::BayesTokenCache;
 use parking_lot::RwLock;
+use tokio::sync::broadcast;
 use utils::{
     config::Config,
     lru_cache::{LruCache, LruCached},
@@ -21,7 +22,7 @@ use utils::{
 
 use crate::{
     listener::blocked::BlockedIps, manager::webadmin::WebAdminManager, Data,
-    ThrottleKeyHasherBuilder, TlsConnectors,
+    ThrottleKeyHasherBuilder, TlsConnectors, IPC_CHANNEL_BUFFER,
 };
 
 use super::server::tls::{build_self_signed_cert, parse_certificates};
@@ -62,6 +63,11 @@ impl Data {
             .map(Arc::new),
             access_tokens: TtlDashMap::with_capacity(capacity, shard_amount),
             http_auth_cache: TtlDashMap::with_capacity(capacity, shard_amount),
+            remote_classify_cache: TtlDashMap::with_capacity(capacity, shard_amount),
+            redirect_cache: TtlDashMap::with_capacity(capacity, shard_amount),
+            bimi_cache: TtlDashMap::with_capacity(capacity, shard_amount),
+            bayes_sender_cache: TtlDashMap::with_capacity(capacity, shard_amount),
+            bayes_divergence_cache: TtlDashMap::with_capacity(capacity, shard_amount),
             blocked_ips: RwLock::new(BlockedIps::parse(config).blocked_ip_addresses),
             blocked_ips_version: 0.into(),
             permissions: Default::default(),
@@ -109,6 +115,9 @@ impl Data {
                 config
                     .property_or_default("cache.bayes.capacity", "8192")
                     .unwrap_or(8192),
+                config
+                    .property_or_default("cache.bayes.shard", "16")
+                    .unwrap_or(16),
                 config
                     .property_or_default("cache.bayes.ttl.positive", "1h")
                     .unwrap_or_else(|| Duration::from_secs(3600)),
@@ -116,6 +125,13 @@ impl Data {
                     .property_or_default("cache.bayes.ttl.negative", "1h")
                     .unwrap_or_else(|| Duration::from_secs(3600)),
             ),
+            bayes_metadata: TtlDashMap::with_capacity(capacity, shard_amount),
+            bayes_cached_models: Default::default(),
+            bayes_trained: TtlDashMap::with_capacity(capacity, shard_amount),
+            bayes_pending: Default::default(),
+            bayes_flush: Default::default(),
+            clock: Default::default(),
+            bayes_live: broadcast::channel(IPC_CHANNEL_BUFFER).0,
             remote_lists: Default::default(),
         }
     }
@@ -128,10 +144,22 @@ impl Default for Data {
             tls_self_signed_cert: Default::default(),
             access_tokens: Default::default(),
             http_auth_cache: Default::default(),
+            remote_classify_cache: Default::default(),
+            redirect_cache: Default::default(),
+            bimi_cache: Default::default(),
+            bayes_sender_cache: Default::default(),
+            bayes_divergence_cache: Default::default(),
             blocked_ips: Default::default(),
             blocked_ips_version: 0.into(),
             permissions: Default::default(),
             permissions_version: 0.into(),
+            bayes_metadata: Default::default(),
+            bayes_cached_models: Default::default(),
+            bayes_trained: Default::default(),
+            bayes_pending: Default::default(),
+            bayes_flush: Default::default(),
+            clock: Default::default(),
+            bayes_live: broadcast::channel(IPC_CHANNEL_BUFFER).0,
             remote_lists: Default::default(),
             jmap_id_gen: Default::default(),
             queue_id_gen: Default::default(),
@@ -149,6 +177,7 @@ impl Default for Data {
             smtp_connectors: Default::default(),
             bayes_cache: BayesTokenCache::new(
                 8192,
+                16,
                 Duration::from_secs(3600),
                 Duration::from_secs(3600),
             ),
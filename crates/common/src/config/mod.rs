@@ -25,7 +25,7 @@ use crate::{
 
 use self::{
     imap::ImapConfig, jmap::settings::JmapConfig, scripts::Scripting, smtp::SmtpConfig,
-    storage::Storage,
+    spamfilter::SpamFilterConfig, storage::Storage,
 };
 
 pub mod imap;
@@ -35,6 +35,7 @@ pub mod network;
 pub mod scripts;
 pub mod server;
 pub mod smtp;
+pub mod spamfilter;
 pub mod storage;
 pub mod telemetry;
 
@@ -181,6 +182,7 @@ impl Core {
             oauth: OAuthConfig::parse(config),
             acme: AcmeProviders::parse(config),
             metrics: Metrics::parse(config),
+            spam: SpamFilterConfig::parse(config),
             storage: Storage {
                 data,
                 blob,
@@ -0,0 +1,1236 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use ahash::{AHashMap, AHashSet};
+use nlp::bayes::{
+    headers::HeaderFeature, normalize::TokenClass, tokenize::CaseFolding, BayesClassifier,
+};
+use utils::config::{utils::AsKey, Config, Rate};
+
+#[derive(Debug, Clone, Default)]
+pub struct SpamFilterConfig {
+    pub bayes: BayesConfig,
+    pub asn: AsnReputationConfig,
+    pub obfuscation: ObfuscationConfig,
+    pub bulk: BulkConfig,
+    pub remote: Option<RemoteClassifierConfig>,
+    pub cluster: ClusterConfig,
+    pub sending_pattern: SendingPatternConfig,
+    pub unwrap: UnwrapConfig,
+    pub sent_profile: SentProfileConfig,
+    pub offense: OffenseConfig,
+    pub redirect: RedirectConfig,
+    pub bimi: BimiConfig,
+    pub mixed_script: MixedScriptConfig,
+    pub opt_out: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BayesConfig {
+    pub default: Arc<BayesModelConfig>,
+    pub models: AHashMap<String, Arc<BayesModelConfig>>,
+    pub retry: BayesRetryConfig,
+    pub live_content: bool,
+    pub max_text_size: usize,
+    pub truncate_text: bool,
+    pub counts_read: BayesCountsRead,
+    pub persist: Option<BayesPersistConfig>,
+    pub write_behind: Option<BayesWriteBehindConfig>,
+    pub ignore_authserv_ids: Vec<String>,
+    pub outbound: Option<BayesOutboundConfig>,
+    pub divergence: BayesDivergenceConfig,
+    // Metadata updated by other nodes is picked up once the cached copy expires
+    pub metadata_ttl: Duration,
+}
+
+// Divergences are computed over a sample of the tokens of the first model and
+// cached, as scripts may request them for every message
+#[derive(Debug, Clone, Default)]
+pub struct BayesDivergenceConfig {
+    pub sample: usize,
+    pub cache_ttl: Duration,
+}
+
+// Messages sent by authenticated users of the listed domains are classified with
+// a model of their own, as outgoing mail differs from the mail being received.
+#[derive(Debug, Clone)]
+pub struct BayesOutboundConfig {
+    pub model: String,
+    pub domains: AHashSet<String>,
+    pub threshold: f64,
+}
+
+// Location of the token cache warm set, which is saved on shutdown and loaded on startup
+#[derive(Debug, Clone)]
+pub struct BayesPersistConfig {
+    pub path: PathBuf,
+    pub max_size: usize,
+}
+
+// Training updates are accumulated in memory and written in batches, classify
+// merges the pending updates with the stored weights. Pending updates are flushed
+// on shutdown but are lost if the server terminates unexpectedly.
+#[derive(Debug, Clone)]
+pub struct BayesWriteBehindConfig {
+    pub interval: Duration,
+    pub max_pending: usize,
+}
+
+// How classify reads the training counts row, which training updates concurrently
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BayesCountsRead {
+    // Served from the token cache
+    Cached,
+    // Served from the token cache, re-read from the store before failing the minimum learns check
+    #[default]
+    Verify,
+    // Always read from the store
+    Direct,
+}
+
+#[derive(Debug, Clone)]
+pub struct BayesRetryConfig {
+    pub attempts: u32,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct BayesModelConfig {
+    pub case_folding: CaseFolding,
+    pub normalize: Vec<TokenClass>,
+    pub headers: Vec<HeaderFeature>,
+    pub untrain_strict: bool,
+    pub trained_hash_expiry: Duration,
+    pub replica: Option<String>,
+    pub replica_max_lag: Option<Duration>,
+    pub change_log_expiry: Option<Duration>,
+    pub train_weight: u32,
+    pub correction_weight: u32,
+    pub correction_sender_limit: u32,
+    pub correction_sender_window: Duration,
+    pub prune_threshold: f64,
+    pub prune_min_documents: u32,
+    pub prune_max_tokens: usize,
+    pub min_token_hits: u32,
+    pub min_tokens: u32,
+    pub min_prob_strength: f64,
+    pub min_learns: u32,
+    pub max_tokens: u32,
+    pub min_matched_tokens: u32,
+    pub label_spam: f64,
+    pub label_ham: f64,
+    pub top_tokens: usize,
+    pub sender_rate: Option<Rate>,
+    pub empty_score: Option<f64>,
+    pub provenance_expiry: Option<Duration>,
+    pub train_cooldown: Option<Duration>,
+    pub train_sample: Option<BayesSampleConfig>,
+    pub decay: Option<BayesDecayConfig>,
+    pub split: Option<BayesSplitConfig>,
+}
+
+// Fraction of the trained messages that update the model, messages are selected
+// from the hash of their normalized text so the same import always trains the
+// same subset for a given seed.
+#[derive(Debug, Clone)]
+pub struct BayesSampleConfig {
+    pub rate: f64,
+    pub seed: u64,
+}
+
+// Token weights and training counts are multiplied by the factor at each interval,
+// so old training gradually loses influence. Runs are delayed by a random jitter
+// to avoid decaying all models at the same time.
+#[derive(Debug, Clone)]
+pub struct BayesDecayConfig {
+    pub factor: f64,
+    pub interval: Duration,
+    pub jitter: Duration,
+    pub batch_size: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct BayesSplitConfig {
+    pub subject_model: String,
+    pub body_model: String,
+    pub subject_weight: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct AsnReputationConfig {
+    pub zone_ipv4: String,
+    pub zone_ipv6: String,
+    pub half_life: Duration,
+    pub neutral: f64,
+    pub prior_weight: f64,
+    pub expire: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct ObfuscationConfig {
+    pub bidi: f64,
+    pub zero_width: f64,
+    pub styled: f64,
+    pub homoglyph: f64,
+    pub homoglyph_min_density: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BulkIndicator {
+    ListId,
+    FeedbackId,
+    Unsubscribe,
+    UnsubscribeAligned,
+    OneClick,
+    UnsubscribeMissing,
+    UnsubscribeInvalid,
+    UnsubscribeInsecure,
+    UnsubscribeMisaligned,
+    OneClickInvalid,
+}
+
+#[derive(Debug, Clone)]
+pub struct BulkConfig {
+    pub weights: [f64; BulkIndicator::ALL.len()],
+}
+
+#[derive(Debug, Clone)]
+pub struct ClusterConfig {
+    pub window: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct SendingPatternConfig {
+    pub half_life: Duration,
+    pub min_messages: f64,
+    pub expire: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct SentProfileConfig {
+    pub half_life: Duration,
+    pub min_messages: f64,
+    pub expire: Duration,
+}
+
+// Offense counts at which repeat offenders are tagged, quarantined and rejected,
+// escalation levels without a threshold are skipped.
+#[derive(Debug, Clone)]
+pub struct OffenseConfig {
+    pub half_life: Duration,
+    pub expire: Duration,
+    pub thresholds: [Option<f64>; OffenseLevel::ALL.len()],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OffenseLevel {
+    None = 0,
+    Tag = 1,
+    Quarantine = 2,
+    Reject = 3,
+}
+
+// Redirects are only followed over the network when enabled, and only for hosts
+// included in the redirector list passed by the script.
+#[derive(Debug, Clone)]
+pub struct RedirectConfig {
+    pub follow: bool,
+    pub max_depth: usize,
+    pub timeout: Duration,
+    pub user_agent: String,
+    pub cache_ttl: Duration,
+}
+
+// Certificates are only downloaded when VMC verification is enabled, otherwise
+// the presence of a BIMI record with an evidence document is reported. Roots are
+// the DER encoded certificates of the trusted mark verifying authorities.
+#[derive(Debug, Clone)]
+pub struct BimiConfig {
+    pub verify_vmc: bool,
+    pub roots: Vec<Vec<u8>>,
+    pub timeout: Duration,
+    pub max_size: usize,
+    pub cache_ttl: Duration,
+}
+
+// Words allowed to mix scripts, such as brand names, are stored in lowercase
+#[derive(Debug, Clone)]
+pub struct MixedScriptConfig {
+    pub allow: AHashSet<String>,
+    pub max_words: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct UnwrapConfig {
+    pub max_depth: usize,
+    pub max_messages: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct RemoteClassifierConfig {
+    pub url: String,
+    pub auth_token: Option<String>,
+    pub timeout: Duration,
+    pub max_size: usize,
+    pub policy: RemoteClassifierPolicy,
+    pub cache_ttl: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoteClassifierPolicy {
+    PreferRemote,
+    PreferLocal,
+    Average,
+}
+
+impl SpamFilterConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        SpamFilterConfig {
+            bayes: BayesConfig::parse(config),
+            asn: AsnReputationConfig::parse(config),
+            obfuscation: ObfuscationConfig::parse(config),
+            bulk: BulkConfig::parse(config),
+            remote: RemoteClassifierConfig::parse(config),
+            cluster: ClusterConfig::parse(config),
+            sending_pattern: SendingPatternConfig::parse(config),
+            unwrap: UnwrapConfig::parse(config),
+            sent_profile: SentProfileConfig::parse(config),
+            offense: OffenseConfig::parse(config),
+            redirect: RedirectConfig::parse(config),
+            bimi: BimiConfig::parse(config),
+            mixed_script: MixedScriptConfig::parse(config),
+            opt_out: config
+                .property("spam-filter.opt-out.enable")
+                .unwrap_or(false),
+        }
+    }
+}
+
+impl AsnReputationConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let default = AsnReputationConfig::default();
+
+        AsnReputationConfig {
+            zone_ipv4: config
+                .value("spam-filter.asn.zone.ipv4")
+                .map(|s| s.trim_matches('.').to_string())
+                .unwrap_or(default.zone_ipv4),
+            zone_ipv6: config
+                .value("spam-filter.asn.zone.ipv6")
+                .map(|s| s.trim_matches('.').to_string())
+                .unwrap_or(default.zone_ipv6),
+            half_life: config
+                .property_or_default("spam-filter.asn.reputation.half-life", "7d")
+                .unwrap_or(default.half_life),
+            neutral: config
+                .property_or_default::<f64>("spam-filter.asn.reputation.neutral", "0.5")
+                .unwrap_or(default.neutral)
+                .clamp(0.0, 1.0),
+            prior_weight: config
+                .property_or_default::<f64>("spam-filter.asn.reputation.prior-weight", "5.0")
+                .unwrap_or(default.prior_weight)
+                .max(0.0),
+            expire: config
+                .property_or_default("spam-filter.asn.reputation.expire", "90d")
+                .unwrap_or(default.expire),
+        }
+    }
+}
+
+impl Default for AsnReputationConfig {
+    fn default() -> Self {
+        Self {
+            zone_ipv4: "origin.asn.cymru.com".to_string(),
+            zone_ipv6: "origin6.asn.cymru.com".to_string(),
+            half_life: Duration::from_secs(7 * 86400),
+            neutral: 0.5,
+            prior_weight: 5.0,
+            expire: Duration::from_secs(90 * 86400),
+        }
+    }
+}
+
+impl ObfuscationConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let default = ObfuscationConfig::default();
+
+        // A weight of zero disables the check
+        ObfuscationConfig {
+            bidi: config
+                .property_or_default::<f64>("spam-filter.obfuscation.weight.bidi", "3.0")
+                .unwrap_or(default.bidi)
+                .max(0.0),
+            zero_width: config
+                .property_or_default::<f64>("spam-filter.obfuscation.weight.zero-width", "2.0")
+                .unwrap_or(default.zero_width)
+                .max(0.0),
+            styled: config
+                .property_or_default::<f64>("spam-filter.obfuscation.weight.styled", "2.0")
+                .unwrap_or(default.styled)
+                .max(0.0),
+            homoglyph: config
+                .property_or_default::<f64>("spam-filter.obfuscation.weight.homoglyph", "3.0")
+                .unwrap_or(default.homoglyph)
+                .max(0.0),
+            homoglyph_min_density: config
+                .property_or_default::<f64>("spam-filter.obfuscation.homoglyph.min-density", "0.1")
+                .unwrap_or(default.homoglyph_min_density)
+                .clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl Default for ObfuscationConfig {
+    fn default() -> Self {
+        Self {
+            bidi: 3.0,
+            zero_width: 2.0,
+            styled: 2.0,
+            homoglyph: 3.0,
+            homoglyph_min_density: 0.1,
+        }
+    }
+}
+
+impl BulkIndicator {
+    pub const ALL: [BulkIndicator; 10] = [
+        BulkIndicator::ListId,
+        BulkIndicator::FeedbackId,
+        BulkIndicator::Unsubscribe,
+        BulkIndicator::UnsubscribeAligned,
+        BulkIndicator::OneClick,
+        BulkIndicator::UnsubscribeMissing,
+        BulkIndicator::UnsubscribeInvalid,
+        BulkIndicator::UnsubscribeInsecure,
+        BulkIndicator::UnsubscribeMisaligned,
+        BulkIndicator::OneClickInvalid,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BulkIndicator::ListId => "list-id",
+            BulkIndicator::FeedbackId => "feedback-id",
+            BulkIndicator::Unsubscribe => "unsubscribe",
+            BulkIndicator::UnsubscribeAligned => "unsubscribe-aligned",
+            BulkIndicator::OneClick => "one-click",
+            BulkIndicator::UnsubscribeMissing => "unsubscribe-missing",
+            BulkIndicator::UnsubscribeInvalid => "unsubscribe-invalid",
+            BulkIndicator::UnsubscribeInsecure => "unsubscribe-insecure",
+            BulkIndicator::UnsubscribeMisaligned => "unsubscribe-misaligned",
+            BulkIndicator::OneClickInvalid => "one-click-invalid",
+        }
+    }
+
+    // Negative weights indicate legitimate bulk mail
+    pub fn default_weight(&self) -> f64 {
+        match self {
+            BulkIndicator::ListId => -1.0,
+            BulkIndicator::FeedbackId => -0.5,
+            BulkIndicator::Unsubscribe => -1.0,
+            BulkIndicator::UnsubscribeAligned => -1.0,
+            BulkIndicator::OneClick => -1.5,
+            BulkIndicator::UnsubscribeMissing => 2.0,
+            BulkIndicator::UnsubscribeInvalid => 2.0,
+            BulkIndicator::UnsubscribeInsecure => 0.5,
+            BulkIndicator::UnsubscribeMisaligned => 1.0,
+            BulkIndicator::OneClickInvalid => 2.0,
+        }
+    }
+}
+
+impl BulkConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let mut bulk = BulkConfig::default();
+
+        for indicator in BulkIndicator::ALL {
+            if let Some(weight) =
+                config.property::<f64>(("spam-filter.bulk.weight", indicator.as_str()))
+            {
+                bulk.weights[indicator as usize] = weight;
+            }
+        }
+
+        bulk
+    }
+}
+
+impl Default for BulkConfig {
+    fn default() -> Self {
+        Self {
+            weights: BulkIndicator::ALL.map(|indicator| indicator.default_weight()),
+        }
+    }
+}
+
+impl ClusterConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        ClusterConfig {
+            window: config
+                .property_or_default("spam-filter.cluster.window", "1h")
+                .unwrap_or(Duration::from_secs(3600)),
+        }
+    }
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl SentProfileConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let default = SentProfileConfig::default();
+
+        SentProfileConfig {
+            half_life: config
+                .property_or_default("spam-filter.sent-profile.half-life", "90d")
+                .unwrap_or(default.half_life),
+            min_messages: config
+                .property_or_default::<f64>("spam-filter.sent-profile.min-messages", "5")
+                .unwrap_or(default.min_messages)
+                .max(0.0),
+            expire: config
+                .property_or_default("spam-filter.sent-profile.expire", "365d")
+                .unwrap_or(default.expire),
+        }
+    }
+}
+
+impl Default for SentProfileConfig {
+    fn default() -> Self {
+        Self {
+            half_life: Duration::from_secs(90 * 86400),
+            min_messages: 5.0,
+            expire: Duration::from_secs(365 * 86400),
+        }
+    }
+}
+
+impl OffenseConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let default = OffenseConfig::default();
+        let mut thresholds = default.thresholds;
+        let mut previous = 0.0;
+        for (threshold, level) in thresholds.iter_mut().zip(OffenseLevel::ALL) {
+            let key = ("spam-filter.offense.threshold", level.as_str());
+            if let Some(value) = config.value(key) {
+                *threshold = if value.is_empty() || value == "false" {
+                    None
+                } else {
+                    config.property::<f64>(key)
+                };
+            }
+
+            // The ladder can only escalate
+            if let Some(value) = *threshold {
+                if value <= previous {
+                    config
+                        .new_parse_error(key, format!("Threshold must be greater than {previous}"));
+                    *threshold = None;
+                } else {
+                    previous = value;
+                }
+            }
+        }
+
+        OffenseConfig {
+            half_life: config
+                .property_or_default("spam-filter.offense.half-life", "7d")
+                .unwrap_or(default.half_life),
+            expire: config
+                .property_or_default("spam-filter.offense.expire", "30d")
+                .unwrap_or(default.expire),
+            thresholds,
+        }
+    }
+
+    // Returns the highest escalation level reached by the offense count
+    pub fn level(&self, offenses: f64) -> OffenseLevel {
+        self.thresholds
+            .iter()
+            .zip(OffenseLevel::ALL)
+            .rev()
+            .find(|(threshold, _)| threshold.is_some_and(|threshold| offenses >= threshold))
+            .map_or(OffenseLevel::None, |(_, level)| level)
+    }
+}
+
+impl Default for OffenseConfig {
+    fn default() -> Self {
+        Self {
+            half_life: Duration::from_secs(7 * 86400),
+            expire: Duration::from_secs(30 * 86400),
+            thresholds: [Some(1.0), Some(3.0), Some(6.0)],
+        }
+    }
+}
+
+impl OffenseLevel {
+    // Escalation levels, in order
+    pub const ALL: [OffenseLevel; 3] = [
+        OffenseLevel::Tag,
+        OffenseLevel::Quarantine,
+        OffenseLevel::Reject,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OffenseLevel::None => "none",
+            OffenseLevel::Tag => "tag",
+            OffenseLevel::Quarantine => "quarantine",
+            OffenseLevel::Reject => "reject",
+        }
+    }
+}
+
+impl RedirectConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let default = RedirectConfig::default();
+
+        RedirectConfig {
+            follow: config
+                .property_or_default("spam-filter.redirect.follow", "false")
+                .unwrap_or(default.follow),
+            max_depth: config
+                .property_or_default("spam-filter.redirect.max-depth", "5")
+                .unwrap_or(default.max_depth),
+            timeout: config
+                .property_or_default("spam-filter.redirect.timeout", "3s")
+                .unwrap_or(default.timeout),
+            user_agent: config
+                .value("spam-filter.redirect.user-agent")
+                .map(|s| s.to_string())
+                .unwrap_or(default.user_agent),
+            cache_ttl: config
+                .property_or_default("spam-filter.redirect.cache.ttl", "1h")
+                .unwrap_or(default.cache_ttl),
+        }
+    }
+}
+
+impl Default for RedirectConfig {
+    fn default() -> Self {
+        Self {
+            follow: false,
+            max_depth: 5,
+            timeout: Duration::from_secs(3),
+            user_agent: "Mozilla/5.0 (X11; Linux i686; rv:109.0) Gecko/20100101 Firefox/118.0"
+                .to_string(),
+            cache_ttl: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl BimiConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let default = BimiConfig::default();
+
+        BimiConfig {
+            verify_vmc: config
+                .property_or_default("spam-filter.bimi.vmc.verify", "false")
+                .unwrap_or(default.verify_vmc),
+            roots: config
+                .values("spam-filter.bimi.vmc.roots")
+                .flat_map(|(_, pem)| {
+                    rustls_pemfile::certs(&mut std::io::Cursor::new(pem.as_bytes()))
+                        .filter_map(|der| der.ok().map(|der| der.as_ref().to_vec()))
+                        .collect::<Vec<_>>()
+                })
+                .collect(),
+            timeout: config
+                .property_or_default("spam-filter.bimi.vmc.timeout", "5s")
+                .unwrap_or(default.timeout),
+            max_size: config
+                .property_or_default("spam-filter.bimi.vmc.max-size", "102400")
+                .unwrap_or(default.max_size),
+            cache_ttl: config
+                .property_or_default("spam-filter.bimi.cache.ttl", "1d")
+                .unwrap_or(default.cache_ttl),
+        }
+    }
+}
+
+impl Default for BimiConfig {
+    fn default() -> Self {
+        Self {
+            verify_vmc: false,
+            roots: Vec::new(),
+            timeout: Duration::from_secs(5),
+            max_size: 102400,
+            cache_ttl: Duration::from_secs(86400),
+        }
+    }
+}
+
+impl MixedScriptConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let default = MixedScriptConfig::default();
+
+        MixedScriptConfig {
+            allow: config
+                .values("spam-filter.mixed-script.allow")
+                .map(|(_, value)| value.trim().to_lowercase())
+                .filter(|value| !value.is_empty())
+                .collect(),
+            max_words: config
+                .property_or_default("spam-filter.mixed-script.max-words", "10000")
+                .unwrap_or(default.max_words),
+        }
+    }
+}
+
+impl Default for MixedScriptConfig {
+    fn default() -> Self {
+        Self {
+            allow: AHashSet::new(),
+            max_words: 10000,
+        }
+    }
+}
+
+impl UnwrapConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let default = UnwrapConfig::default();
+
+        UnwrapConfig {
+            max_depth: config
+                .property_or_default("spam-filter.unwrap.max-depth", "3")
+                .unwrap_or(default.max_depth),
+            max_messages: config
+                .property_or_default("spam-filter.unwrap.max-messages", "5")
+                .unwrap_or(default.max_messages),
+        }
+    }
+}
+
+impl Default for UnwrapConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: 3,
+            max_messages: 5,
+        }
+    }
+}
+
+impl SendingPatternConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let default = SendingPatternConfig::default();
+
+        SendingPatternConfig {
+            half_life: config
+                .property_or_default("spam-filter.sending-pattern.half-life", "30d")
+                .unwrap_or(default.half_life),
+            min_messages: config
+                .property_or_default::<f64>("spam-filter.sending-pattern.min-messages", "10")
+                .unwrap_or(default.min_messages)
+                .max(1.0),
+            expire: config
+                .property_or_default("spam-filter.sending-pattern.expire", "90d")
+                .unwrap_or(default.expire),
+        }
+    }
+}
+
+impl Default for SendingPatternConfig {
+    fn default() -> Self {
+        Self {
+            half_life: Duration::from_secs(30 * 86400),
+            min_messages: 10.0,
+            expire: Duration::from_secs(90 * 86400),
+        }
+    }
+}
+
+impl RemoteClassifierConfig {
+    pub fn parse(config: &mut Config) -> Option<Self> {
+        let url = config
+            .value("spam-filter.classifier.remote.url")?
+            .trim()
+            .to_string();
+        let policy = match config
+            .value("spam-filter.classifier.remote.policy")
+            .unwrap_or("prefer-remote")
+        {
+            "prefer-remote" => RemoteClassifierPolicy::PreferRemote,
+            "prefer-local" => RemoteClassifierPolicy::PreferLocal,
+            "average" => RemoteClassifierPolicy::Average,
+            value => {
+                let err = format!("Invalid remote classifier policy {value:?}");
+                config.new_parse_error("spam-filter.classifier.remote.policy", err);
+                RemoteClassifierPolicy::PreferRemote
+            }
+        };
+
+        Some(RemoteClassifierConfig {
+            url,
+            auth_token: config
+                .value("spam-filter.classifier.remote.auth.token")
+                .map(|s| s.to_string()),
+            timeout: config
+                .property_or_default("spam-filter.classifier.remote.timeout", "2s")
+                .unwrap_or(Duration::from_secs(2)),
+            max_size: config
+                .property_or_default("spam-filter.classifier.remote.max-size", "4096")
+                .unwrap_or(4096),
+            policy,
+            cache_ttl: config
+                .property_or_default("spam-filter.classifier.remote.cache.ttl", "5m")
+                .unwrap_or(Duration::from_secs(300)),
+        })
+    }
+}
+
+impl BayesConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let default = BayesModelConfig::parse(config, "spam-filter.bayes", &Default::default());
+
+        // Per-model (lookup store) overrides
+        let mut models = AHashMap::new();
+        for model_id in config
+            .sub_keys("spam-filter.bayes.model", "")
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+        {
+            let model = BayesModelConfig::parse(
+                config,
+                ("spam-filter.bayes.model", model_id.as_str()),
+                &default,
+            );
+            models.insert(model_id, Arc::new(model));
+        }
+
+        BayesConfig {
+            default: Arc::new(default),
+            models,
+            retry: BayesRetryConfig::parse(config),
+            live_content: config
+                .property("spam-filter.bayes.live.include-content")
+                .unwrap_or(false),
+            max_text_size: config
+                .property_or_default::<Option<usize>>("spam-filter.bayes.text.max-size", "10485760")
+                .unwrap_or_default()
+                .unwrap_or(0),
+            truncate_text: config
+                .value("spam-filter.bayes.text.oversized")
+                .is_some_and(|v| v.eq_ignore_ascii_case("truncate")),
+            counts_read: match config
+                .value("spam-filter.bayes.counts.read")
+                .unwrap_or("verify")
+            {
+                "cached" => BayesCountsRead::Cached,
+                "verify" => BayesCountsRead::Verify,
+                "direct" => BayesCountsRead::Direct,
+                value => {
+                    let err = format!("Invalid counts read mode {value:?}");
+                    config.new_parse_error("spam-filter.bayes.counts.read", err);
+                    BayesCountsRead::default()
+                }
+            },
+            persist: config
+                .value("cache.bayes.persist.path")
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+                .map(|path| BayesPersistConfig {
+                    path,
+                    max_size: config
+                        .property_or_default("cache.bayes.persist.max-size", "16777216")
+                        .unwrap_or(16777216),
+                }),
+            write_behind: config
+                .property_or_default::<bool>("spam-filter.bayes.write-behind.enable", "false")
+                .unwrap_or(false)
+                .then(|| BayesWriteBehindConfig {
+                    interval: config
+                        .property_or_default("spam-filter.bayes.write-behind.interval", "10s")
+                        .unwrap_or(Duration::from_secs(10)),
+                    max_pending: config
+                        .property_or_default("spam-filter.bayes.write-behind.max-pending", "10000")
+                        .unwrap_or(10000),
+                }),
+            // Results added by this server are only present once the message was delivered
+            ignore_authserv_ids: {
+                let ids = config
+                    .values("spam-filter.bayes.headers.ignore-authserv-id")
+                    .map(|(_, id)| id.to_string())
+                    .collect::<Vec<_>>();
+                if !ids.is_empty() {
+                    ids
+                } else {
+                    config
+                        .value("lookup.default.hostname")
+                        .map(|hostname| vec![hostname.to_string()])
+                        .unwrap_or_default()
+                }
+            },
+            outbound: parse_outbound(config),
+            metadata_ttl: config
+                .property_or_default("spam-filter.bayes.metadata.cache-ttl", "5m")
+                .unwrap_or(Duration::from_secs(300)),
+            divergence: BayesDivergenceConfig {
+                sample: config
+                    .property_or_default("spam-filter.bayes.divergence.sample", "10000")
+                    .unwrap_or(10000),
+                cache_ttl: config
+                    .property_or_default("spam-filter.bayes.divergence.cache-ttl", "1h")
+                    .unwrap_or(Duration::from_secs(3600)),
+            },
+        }
+    }
+
+    pub fn model(&self, id: &str) -> &Arc<BayesModelConfig> {
+        self.models.get(id).unwrap_or(&self.default)
+    }
+}
+
+impl BayesRetryConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let default = BayesRetryConfig::default();
+
+        BayesRetryConfig {
+            attempts: config
+                .property_or_default("spam-filter.bayes.retry.attempts", "3")
+                .unwrap_or(default.attempts),
+            backoff: config
+                .property_or_default("spam-filter.bayes.retry.backoff", "50ms")
+                .unwrap_or(default.backoff),
+            max_backoff: config
+                .property_or_default("spam-filter.bayes.retry.max-backoff", "1s")
+                .unwrap_or(default.max_backoff),
+        }
+    }
+}
+
+impl Default for BayesRetryConfig {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl BayesModelConfig {
+    fn parse(config: &mut Config, prefix: impl AsKey, defaults: &BayesModelConfig) -> Self {
+        let prefix = prefix.as_key();
+
+        BayesModelConfig {
+            case_folding: parse_case_folding(config, (prefix.as_str(), "case-folding"))
+                .unwrap_or(defaults.case_folding),
+            normalize: parse_token_classes(config, (prefix.as_str(), "normalize"))
+                .unwrap_or_else(|| defaults.normalize.clone()),
+            headers: parse_header_features(config, (prefix.as_str(), "headers"))
+                .unwrap_or_else(|| defaults.headers.clone()),
+            untrain_strict: config
+                .property((prefix.as_str(), "untrain.strict"))
+                .unwrap_or(defaults.untrain_strict),
+            trained_hash_expiry: config
+                .property((prefix.as_str(), "untrain.hash-expiry"))
+                .unwrap_or(defaults.trained_hash_expiry),
+            replica: config
+                .value((prefix.as_str(), "replica.store"))
+                .map(|s| s.to_string())
+                .or_else(|| defaults.replica.clone()),
+            replica_max_lag: config
+                .property((prefix.as_str(), "replica.max-lag"))
+                .or(defaults.replica_max_lag),
+            change_log_expiry: config
+                .property((prefix.as_str(), "change-log.expire"))
+                .or(defaults.change_log_expiry),
+            train_weight: config
+                .property((prefix.as_str(), "train.weight"))
+                .unwrap_or(defaults.train_weight)
+                .max(1),
+            correction_weight: config
+                .property((prefix.as_str(), "correction.weight"))
+                .unwrap_or(defaults.correction_weight)
+                .max(1),
+            correction_sender_limit: config
+                .property((prefix.as_str(), "correction.sender.limit"))
+                .unwrap_or(defaults.correction_sender_limit),
+            correction_sender_window: config
+                .property((prefix.as_str(), "correction.sender.window"))
+                .unwrap_or(defaults.correction_sender_window),
+            prune_threshold: config
+                .property::<f64>((prefix.as_str(), "prune.threshold"))
+                .unwrap_or(defaults.prune_threshold)
+                .clamp(0.0, 1.0),
+            prune_min_documents: config
+                .property((prefix.as_str(), "prune.min-documents"))
+                .unwrap_or(defaults.prune_min_documents),
+            prune_max_tokens: config
+                .property((prefix.as_str(), "prune.max-tokens"))
+                .unwrap_or(defaults.prune_max_tokens),
+            min_token_hits: config
+                .property((prefix.as_str(), "classify.min-token-hits"))
+                .unwrap_or(defaults.min_token_hits),
+            min_tokens: config
+                .property((prefix.as_str(), "classify.min-tokens"))
+                .unwrap_or(defaults.min_tokens),
+            min_prob_strength: config
+                .property::<f64>((prefix.as_str(), "classify.min-prob-strength"))
+                .unwrap_or(defaults.min_prob_strength)
+                .clamp(0.0, 0.5),
+            min_learns: config
+                .property((prefix.as_str(), "classify.min-learns"))
+                .unwrap_or(defaults.min_learns),
+            max_tokens: config
+                .property((prefix.as_str(), "classify.max-tokens"))
+                .unwrap_or(defaults.max_tokens),
+            min_matched_tokens: config
+                .property((prefix.as_str(), "classify.min-matched-tokens"))
+                .unwrap_or(defaults.min_matched_tokens),
+            label_spam: config
+                .property::<f64>((prefix.as_str(), "classify.label.spam"))
+                .unwrap_or(defaults.label_spam)
+                .clamp(0.0, 1.0),
+            label_ham: config
+                .property::<f64>((prefix.as_str(), "classify.label.ham"))
+                .unwrap_or(defaults.label_ham)
+                .clamp(0.0, 1.0),
+            top_tokens: config
+                .property((prefix.as_str(), "classify.top-tokens"))
+                .unwrap_or(defaults.top_tokens),
+            sender_rate: config
+                .property::<Option<Rate>>((prefix.as_str(), "classify.sender-rate"))
+                .unwrap_or_else(|| defaults.sender_rate.clone()),
+            empty_score: config
+                .property::<Option<f64>>((prefix.as_str(), "classify.empty-score"))
+                .unwrap_or(defaults.empty_score)
+                .map(|score| score.clamp(0.0, 1.0)),
+            provenance_expiry: if config
+                .property((prefix.as_str(), "provenance.enable"))
+                .unwrap_or(defaults.provenance_expiry.is_some())
+            {
+                config
+                    .property::<Duration>((prefix.as_str(), "provenance.expiry"))
+                    .or(defaults.provenance_expiry)
+                    .unwrap_or(Duration::from_secs(90 * 86400))
+                    .into()
+            } else {
+                None
+            },
+            train_cooldown: config
+                .property((prefix.as_str(), "train.sender-cooldown"))
+                .or(defaults.train_cooldown),
+            train_sample: match config.property::<f64>((prefix.as_str(), "train.sample.rate")) {
+                Some(rate) if rate < 1.0 => Some(BayesSampleConfig {
+                    rate: rate.max(0.0),
+                    seed: config
+                        .property((prefix.as_str(), "train.sample.seed"))
+                        .or(defaults.train_sample.as_ref().map(|sample| sample.seed))
+                        .unwrap_or(0),
+                }),
+                Some(_) => None,
+                None => defaults.train_sample.clone(),
+            },
+            decay: match config.property::<f64>((prefix.as_str(), "decay.factor")) {
+                Some(factor) if factor < 1.0 => {
+                    let defaults = defaults.decay.as_ref();
+                    Some(BayesDecayConfig {
+                        factor: factor.max(0.0),
+                        interval: config
+                            .property((prefix.as_str(), "decay.interval"))
+                            .or(defaults.map(|decay| decay.interval))
+                            .unwrap_or(Duration::from_secs(7 * 86400)),
+                        jitter: config
+                            .property((prefix.as_str(), "decay.jitter"))
+                            .or(defaults.map(|decay| decay.jitter))
+                            .unwrap_or(Duration::from_secs(3600)),
+                        batch_size: config
+                            .property((prefix.as_str(), "decay.batch-size"))
+                            .or(defaults.map(|decay| decay.batch_size))
+                            .unwrap_or(1000)
+                            .max(1),
+                    })
+                }
+                Some(_) => None,
+                None => defaults.decay.clone(),
+            },
+            split: parse_split(config, prefix.as_str()).or_else(|| defaults.split.clone()),
+        }
+    }
+}
+
+impl Default for BayesModelConfig {
+    fn default() -> Self {
+        Self {
+            case_folding: CaseFolding::default(),
+            normalize: vec![],
+            headers: vec![],
+            untrain_strict: false,
+            trained_hash_expiry: Duration::from_secs(90 * 86400),
+            replica: None,
+            replica_max_lag: None,
+            change_log_expiry: None,
+            train_weight: 1,
+            correction_weight: 3,
+            correction_sender_limit: 10,
+            correction_sender_window: Duration::from_secs(86400),
+            prune_threshold: 0.95,
+            prune_min_documents: 5,
+            prune_max_tokens: 10000,
+            min_token_hits: 2,
+            min_tokens: 11,
+            min_prob_strength: 0.05,
+            min_learns: 200,
+            max_tokens: 0,
+            min_matched_tokens: 0,
+            label_spam: 0.7,
+            label_ham: 0.5,
+            top_tokens: 5,
+            sender_rate: None,
+            empty_score: None,
+            provenance_expiry: None,
+            train_cooldown: None,
+            train_sample: None,
+            decay: None,
+            split: None,
+        }
+    }
+}
+
+impl BayesModelConfig {
+    // Classifier thresholds of the model, Sieve scripts may override all but the
+    // maximum number of tokens
+    pub fn classifier(&self) -> BayesClassifier {
+        BayesClassifier {
+            min_token_hits: self.min_token_hits,
+            min_tokens: self.min_tokens,
+            min_prob_strength: self.min_prob_strength,
+            min_learns: self.min_learns,
+            max_tokens: self.max_tokens,
+        }
+    }
+}
+
+impl BayesSampleConfig {
+    pub fn is_sampled(&self, text_hash: u128) -> bool {
+        (xxhash_rust::xxh3::xxh3_64_with_seed(&text_hash.to_be_bytes(), self.seed) as f64)
+            < self.rate * u64::MAX as f64
+    }
+}
+
+fn parse_split(config: &mut Config, prefix: &str) -> Option<BayesSplitConfig> {
+    let subject_model = config.value((prefix, "split.subject-model"))?.to_string();
+    let body_model = config.value((prefix, "split.body-model"))?.to_string();
+    if subject_model == body_model {
+        config.new_parse_error(
+            (prefix, "split.body-model"),
+            "Subject and body models must use different stores",
+        );
+        return None;
+    }
+
+    Some(BayesSplitConfig {
+        subject_model,
+        body_model,
+        subject_weight: config
+            .property::<f64>((prefix, "split.subject-weight"))
+            .unwrap_or(0.3)
+            .clamp(0.0, 1.0),
+    })
+}
+
+fn parse_outbound(config: &mut Config) -> Option<BayesOutboundConfig> {
+    let model = config
+        .value("spam-filter.bayes.outbound.model")?
+        .to_string();
+    if model.is_empty() {
+        // The default store holds the model used for incoming mail
+        config.new_parse_error(
+            "spam-filter.bayes.outbound.model",
+            "The outbound model must use its own store",
+        );
+        return None;
+    }
+
+    Some(BayesOutboundConfig {
+        model,
+        domains: config
+            .values("spam-filter.bayes.outbound.domains")
+            .map(|(_, domain)| domain.trim().to_lowercase())
+            .filter(|domain| !domain.is_empty())
+            .collect(),
+        threshold: config
+            .property::<f64>("spam-filter.bayes.outbound.threshold")
+            .unwrap_or(0.9)
+            .clamp(0.0, 1.0),
+    })
+}
+
+fn parse_case_folding(config: &mut Config, key: impl AsKey) -> Option<CaseFolding> {
+    let key = key.as_key();
+    let value = config.value(key.as_str())?;
+    match CaseFolding::parse(value) {
+        Some(case_folding) => Some(case_folding),
+        None => {
+            let err = format!("Invalid case folding {value:?}");
+            config.new_parse_error(key, err);
+            None
+        }
+    }
+}
+
+fn parse_header_features(config: &mut Config, key: impl AsKey) -> Option<Vec<HeaderFeature>> {
+    let key = key.as_key();
+    let values = config
+        .values(key.as_str())
+        .map(|(_, value)| value.to_string())
+        .collect::<Vec<_>>();
+    if values.is_empty() {
+        return None;
+    }
+
+    let mut features = Vec::with_capacity(values.len());
+    for value in values {
+        match HeaderFeature::parse(&value) {
+            Some(feature) => {
+                if !features.contains(&feature) {
+                    features.push(feature);
+                }
+            }
+            None => {
+                let err = format!("Invalid header feature {value:?}");
+                config.new_parse_error(key.as_str(), err);
+            }
+        }
+    }
+    Some(features)
+}
+
+fn parse_token_classes(config: &mut Config, key: impl AsKey) -> Option<Vec<TokenClass>> {
+    let key = key.as_key();
+    let values = config
+        .values(key.as_str())
+        .map(|(_, value)| value.to_string())
+        .collect::<Vec<_>>();
+    if values.is_empty() {
+        return None;
+    }
+
+    let mut classes = Vec::with_capacity(values.len());
+    for value in values {
+        match TokenClass::parse(&value) {
+            Some(class) => {
+                if !classes.contains(&class) {
+                    classes.push(class);
+                }
+            }
+            None => {
+                let err = format!("Invalid token class {value:?}");
+                config.new_parse_error(key.as_str(), err);
+            }
+        }
+    }
+    Some(classes)
+}
@@ -120,6 +120,7 @@ pub struct Tracers {
 pub struct Metrics {
     pub prometheus: Option<PrometheusMetrics>,
     pub otel: Option<Arc<OtelMetrics>>,
+    pub statsd: Option<Arc<StatsdMetrics>>,
     pub log_path: Option<String>,
 }
 
@@ -128,6 +129,13 @@ pub struct PrometheusMetrics {
     pub auth: Option<String>,
 }
 
+#[derive(Debug, Clone)]
+pub struct StatsdMetrics {
+    pub endpoint: String,
+    pub prefix: String,
+    pub interval: Duration,
+}
+
 impl Telemetry {
     pub fn parse(config: &mut Config, stores: &Stores) -> Self {
         let mut telemetry = Telemetry {
@@ -597,6 +605,7 @@ impl Metrics {
         let mut metrics = Metrics {
             prometheus: None,
             otel: None,
+            statsd: None,
             log_path: None,
         };
 
@@ -636,6 +645,28 @@ impl Metrics {
             });
         }
 
+        if config
+            .property_or_default("metrics.statsd.enable", "false")
+            .unwrap_or(false)
+        {
+            if let Some(endpoint) = config
+                .value_require("metrics.statsd.endpoint")
+                .map(|s| s.to_string())
+            {
+                metrics.statsd = Some(Arc::new(StatsdMetrics {
+                    endpoint,
+                    prefix: config
+                        .value("metrics.statsd.prefix")
+                        .unwrap_or("stalwart")
+                        .trim_end_matches('.')
+                        .to_string(),
+                    interval: config
+                        .property_or_default("metrics.statsd.interval", "1m")
+                        .unwrap_or_else(|| Duration::from_secs(60)),
+                }));
+            }
+        }
+
         let otel_enabled = match config
             .value("metrics.open-telemetry.transport")
             .unwrap_or("disable")
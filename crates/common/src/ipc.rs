@@ -60,6 +60,10 @@ pub enum HousekeeperEvent {
         provider_id: String,
         renew_at: Instant,
     },
+    BayesDecayReschedule {
+        model_id: String,
+        due: Instant,
+    },
     Purge(PurgeType),
     ReloadSettings,
     Exit,
@@ -14,12 +14,14 @@ use std::{
 use ahash::{AHashMap, AHashSet, RandomState};
 use arc_swap::ArcSwap;
 use auth::{oauth::config::OAuthConfig, roles::RolePermissions, AccessToken};
+use clock::Clock;
 use config::{
     imap::ImapConfig,
     jmap::settings::JmapConfig,
     network::Network,
     scripts::{RemoteList, Scripting},
     smtp::SmtpConfig,
+    spamfilter::SpamFilterConfig,
     storage::Storage,
     telemetry::Metrics,
 };
@@ -30,12 +32,17 @@ use imap_proto::protocol::list::Attribute;
 use ipc::{DeliveryEvent, HousekeeperEvent, QueueEvent, ReportingEvent, StateEvent};
 use listener::{blocked::Security, limiter::ConcurrencyLimiter, tls::AcmeProviders};
 
-use manager::webadmin::{Resource, WebAdminManager};
-use nlp::bayes::cache::BayesTokenCache;
+use manager::{
+    bayes_live::ClassifyDiagnostics,
+    bayes_pending::BayesPending,
+    webadmin::{Resource, WebAdminManager},
+};
+use nlp::bayes::{cache::BayesTokenCache, BayesMetadata, TokenHash};
 use parking_lot::{Mutex, RwLock};
 use reqwest::Response;
 use rustls::sign::CertifiedKey;
-use tokio::sync::{mpsc, Notify};
+use scripts::plugins::{bimi::BimiTrust, redirect::RedirectChain};
+use tokio::sync::{broadcast, mpsc, Notify};
 use tokio_rustls::TlsConnector;
 use utils::{
     lru_cache::LruCache,
@@ -45,6 +52,7 @@ use utils::{
 
 pub mod addresses;
 pub mod auth;
+pub mod clock;
 pub mod config;
 pub mod core;
 #[cfg(feature = "enterprise")]
@@ -88,7 +96,20 @@ pub struct Data {
     pub permissions: ADashMap<u32, Arc<RolePermissions>>,
     pub permissions_version: AtomicU8,
 
+    pub clock: Clock,
+
     pub bayes_cache: BayesTokenCache,
+    pub bayes_metadata: TtlDashMap<String, Arc<BayesMetadata>>,
+    pub bayes_cached_models: RwLock<AHashSet<String>>,
+    pub bayes_trained: TtlDashMap<TokenHash, ()>,
+    pub bayes_pending: Mutex<BayesPending>,
+    pub bayes_flush: tokio::sync::Mutex<()>,
+    pub remote_classify_cache: TtlDashMap<u128, f64>,
+    pub redirect_cache: TtlDashMap<u128, RedirectChain>,
+    pub bimi_cache: TtlDashMap<u128, BimiTrust>,
+    pub bayes_sender_cache: TtlDashMap<u128, f64>,
+    pub bayes_divergence_cache: TtlDashMap<u128, Option<f64>>,
+    pub bayes_live: broadcast::Sender<Arc<ClassifyDiagnostics>>,
     pub remote_lists: RwLock<AHashMap<String, RemoteList>>,
 
     pub jmap_id_gen: SnowflakeIdGenerator,
@@ -207,6 +228,7 @@ pub struct Core {
     pub jmap: JmapConfig,
     pub imap: ImapConfig,
     pub metrics: Metrics,
+    pub spam: SpamFilterConfig,
     #[cfg(feature = "enterprise")]
     pub enterprise: Option<enterprise::Enterprise>,
 }
@@ -0,0 +1,236 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ahash::AHashSet;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use nlp::bayes::{block::TokenBlock, TokenHash, Weights};
+use serde::{Deserialize, Serialize};
+use store::{
+    write::{
+        key::{DeserializeBigEndian, KeySerializer},
+        Bincode, LookupClass, ValueClass,
+    },
+    Deserialize as _, IterateParams, LookupStore, ValueKey, U64_LEN,
+};
+use trc::AddContext;
+
+use crate::{
+    scripts::plugins::bayes::{model_seed, token_key, token_keys, with_retry, CHANGES_PREFIX},
+    Server,
+};
+
+// Token weights are stored as absolute values, which allows restoring a full backup
+// followed by any number of overlapping incremental backups in order. Compact
+// backups store the tokens with weights in token blocks, leaving only the removed
+// tokens of incremental backups in the token list.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BayesBackup {
+    pub since: u64,
+    pub until: u64,
+    pub tokens: Vec<BayesBackupToken>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<BayesBackupBlock>,
+}
+
+// Base64 encoded token block of the tokens sharing the hash prefix
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BayesBackupBlock {
+    pub prefix: u16,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BayesBackupToken {
+    pub h1: u64,
+    pub h2: u64,
+    pub spam: u32,
+    pub ham: u32,
+}
+
+impl Server {
+    // Exports the token weights of a model, either in full or only those changed
+    // since the given timestamp (requires the change log to be enabled).
+    pub async fn bayes_backup(
+        &self,
+        model_id: &str,
+        since: u64,
+        compact: bool,
+    ) -> trc::Result<BayesBackup> {
+        // Pending training updates are not visible in the store until flushed
+        self.bayes_flush().await?;
+
+        let store = self.bayes_store(model_id)?;
+        let retry = &self.core.spam.bayes.retry;
+        let until = self.now();
+
+        let hashes = if since == 0 {
+            token_keys(store)
+                .await?
+                .into_iter()
+                .map(|key| token_hash(&key))
+                .collect::<trc::Result<Vec<_>>>()?
+        } else {
+            changed_tokens(store, since, until).await?
+        };
+
+        let mut tokens = Vec::with_capacity(hashes.len());
+        let mut weighted = Vec::new();
+        for hash in hashes {
+            let weights = Weights::from(
+                with_retry(retry, || store.counter_get(token_key(&hash)))
+                    .await
+                    .caused_by(trc::location!())?,
+            );
+
+            // Removed tokens are only relevant to incremental backups
+            if compact && weights != Weights::default() {
+                weighted.push((hash, weights));
+            } else if since != 0 || weights != Weights::default() {
+                tokens.push(BayesBackupToken {
+                    h1: hash.h1,
+                    h2: hash.h2,
+                    spam: weights.spam,
+                    ham: weights.ham,
+                });
+            }
+        }
+
+        Ok(BayesBackup {
+            since,
+            until,
+            tokens,
+            blocks: TokenBlock::group(weighted)
+                .into_iter()
+                .map(|(prefix, block)| BayesBackupBlock {
+                    prefix,
+                    data: STANDARD.encode(block),
+                })
+                .collect(),
+        })
+    }
+
+    // Applies a full or incremental backup, returning the number of updated tokens
+    pub async fn bayes_restore(&self, model_id: &str, backup: BayesBackup) -> trc::Result<usize> {
+        // Pending training updates would otherwise be added to the restored weights
+        self.bayes_flush().await?;
+
+        let store = self.bayes_store(model_id)?;
+        let retry = &self.core.spam.bayes.retry;
+        let bayes_cache = &self.inner.data.bayes_cache;
+        let mut updated = 0;
+
+        let mut blocks = Vec::with_capacity(backup.blocks.len());
+        for block in backup.blocks {
+            let data = STANDARD
+                .decode(block.data.as_bytes())
+                .ok()
+                .filter(|data| TokenBlock::is_valid(data))
+                .ok_or_else(|| {
+                    trc::ResourceEvent::BadParameters
+                        .into_err()
+                        .details("Invalid token block")
+                        .ctx(trc::Key::Id, block.prefix)
+                })?;
+            blocks.push((block.prefix, data));
+        }
+        let tokens = backup
+            .tokens
+            .into_iter()
+            .map(|token| {
+                (
+                    TokenHash {
+                        h1: token.h1,
+                        h2: token.h2,
+                    },
+                    Weights {
+                        spam: token.spam,
+                        ham: token.ham,
+                    },
+                )
+            })
+            .chain(
+                blocks
+                    .iter()
+                    .flat_map(|(prefix, data)| TokenBlock::iter(data, *prefix)),
+            );
+
+        for (hash, weights) in tokens {
+            let key = token_key(&hash);
+            let current = with_retry(retry, || store.counter_get(key.clone()))
+                .await
+                .caused_by(trc::location!())?;
+            let target = i64::from(weights);
+
+            if current != target {
+                with_retry(retry, || {
+                    store.counter_incr(key.clone(), target - current, None, false)
+                })
+                .await
+                .caused_by(trc::location!())?;
+                bayes_cache.invalidate(&hash.for_model(model_seed(model_id)));
+                updated += 1;
+            }
+        }
+
+        Ok(updated)
+    }
+}
+
+// Skips the change log entries expired at the given time, as read from the server clock
+async fn changed_tokens(store: &LookupStore, since: u64, now: u64) -> trc::Result<Vec<TokenHash>> {
+    let LookupStore::Store(change_store) = store else {
+        trc::bail!(trc::StoreEvent::NotSupported
+            .into_err()
+            .details("Incremental Bayes backups require a data store"));
+    };
+
+    let mut changes = Vec::new();
+    change_store
+        .iterate(
+            IterateParams::new(
+                ValueKey::from(ValueClass::Lookup(LookupClass::Key(
+                    KeySerializer::new(CHANGES_PREFIX.len() + U64_LEN)
+                        .write(CHANGES_PREFIX)
+                        .write(since)
+                        .finalize(),
+                ))),
+                ValueKey::from(ValueClass::Lookup(LookupClass::Key(
+                    KeySerializer::new(CHANGES_PREFIX.len() + (U64_LEN * 2))
+                        .write(CHANGES_PREFIX)
+                        .write(u64::MAX)
+                        .write(u64::MAX)
+                        .finalize(),
+                ))),
+            ),
+            |_, value| {
+                // Values are prefixed with their expiration time
+                if value.deserialize_be_u64(0)? > now {
+                    changes.push(value.get(U64_LEN..).unwrap_or_default().to_vec());
+                }
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+    let mut hashes = AHashSet::new();
+    for change in changes {
+        hashes.extend(
+            Bincode::<Vec<TokenHash>>::deserialize(&change)
+                .caused_by(trc::location!())?
+                .inner,
+        );
+    }
+
+    Ok(hashes.into_iter().collect())
+}
+
+fn token_hash(key: &[u8]) -> trc::Result<TokenHash> {
+    Ok(TokenHash {
+        h1: key.deserialize_be_u64(0)?,
+        h2: key.deserialize_be_u64(U64_LEN)?,
+    })
+}
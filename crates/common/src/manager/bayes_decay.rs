@@ -0,0 +1,175 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::{Duration, Instant};
+
+use nlp::bayes::{TokenHash, Weights};
+use serde::Serialize;
+use store::{
+    write::{key::DeserializeBigEndian, Bincode},
+    Serialize as _, U64_LEN,
+};
+use trc::AddContext;
+
+use crate::{
+    config::spamfilter::BayesDecayConfig,
+    scripts::plugins::bayes::{model_seed, token_keys, with_retry},
+    Server,
+};
+
+const DECAY_KEY: &[u8] = b"bayes:decay";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct BayesDecayReport {
+    pub tokens: usize,
+    pub removed: usize,
+    pub last_run: u64,
+}
+
+impl Server {
+    // Multiplies the token weights and training counts of a model by the decay factor.
+    // Tokens are processed in batches, yielding in between so that classification
+    // is not held up while a large model is decayed.
+    pub async fn bayes_decay(
+        &self,
+        model_id: &str,
+        decay: &BayesDecayConfig,
+    ) -> trc::Result<BayesDecayReport> {
+        // Pending training updates are not visible in the store until flushed
+        self.bayes_flush().await?;
+
+        let store = self.bayes_store(model_id)?;
+        let bayes_cache = &self.inner.data.bayes_cache;
+        let retry = &self.core.spam.bayes.retry;
+        let model_seed = model_seed(model_id);
+        let time = Instant::now();
+        let now = self.now();
+        let mut report = BayesDecayReport {
+            last_run: now,
+            ..Default::default()
+        };
+
+        for batch in token_keys(store).await?.chunks(decay.batch_size) {
+            for key in batch {
+                let current = with_retry(retry, || store.counter_get(key.clone()))
+                    .await
+                    .caused_by(trc::location!())?;
+                if current == 0 {
+                    continue;
+                }
+
+                // Counts are rounded up or down at random, so that rarely seen tokens
+                // decay on average instead of being kept or dropped at once
+                let weights = Weights::from(current);
+                let seed = xxhash_rust::xxh3::xxh3_64_with_seed(key, now);
+                let decayed = Weights {
+                    spam: decay_count(weights.spam, decay.factor, seed as u32),
+                    ham: decay_count(weights.ham, decay.factor, (seed >> 32) as u32),
+                };
+
+                // Applied as an increment to preserve concurrent training updates
+                let target = i64::from(decayed);
+                if target != current {
+                    with_retry(retry, || {
+                        store.counter_incr(key.clone(), target - current, None, false)
+                    })
+                    .await
+                    .caused_by(trc::location!())?;
+                }
+                let hash = TokenHash {
+                    h1: key.as_slice().deserialize_be_u64(0)?,
+                    h2: key.as_slice().deserialize_be_u64(U64_LEN)?,
+                };
+                bayes_cache.invalidate(&hash.for_model(model_seed));
+
+                if hash != TokenHash::default() {
+                    report.tokens += 1;
+                    if decayed == Weights::default() {
+                        report.removed += 1;
+                    }
+                }
+            }
+            tokio::task::yield_now().await;
+        }
+
+        with_retry(retry, || {
+            store.key_set(DECAY_KEY.to_vec(), Bincode::new(now).serialize(), None)
+        })
+        .await
+        .caused_by(trc::location!())?;
+
+        trc::event!(
+            Spam(trc::SpamEvent::Train),
+            Id = model_id.to_string(),
+            Details = "Decayed model",
+            Total = report.tokens,
+            Elapsed = time.elapsed(),
+        );
+
+        Ok(report)
+    }
+
+    // Returns the time of the last decay of a model, as recorded in its store
+    pub async fn bayes_decay_last_run(&self, model_id: &str) -> trc::Result<Option<u64>> {
+        self.bayes_store(model_id)?
+            .key_get::<Bincode<u64>>(DECAY_KEY.to_vec())
+            .await
+            .caused_by(trc::location!())
+            .map(|last_run| last_run.map(|last_run| last_run.inner))
+    }
+
+    // Returns the time remaining until the next scheduled decay of a model, or
+    // None if decay is not configured for the model.
+    pub async fn bayes_decay_due(&self, model_id: &str) -> trc::Result<Option<Duration>> {
+        let Some(decay) = &self.core.spam.bayes.model(model_id).decay else {
+            return Ok(None);
+        };
+        Ok(Some(match self.bayes_decay_last_run(model_id).await? {
+            Some(last_run) => Duration::from_secs(
+                (last_run + decay.interval.as_secs()).saturating_sub(self.now()),
+            ),
+            // Models are first decayed one interval after decay was enabled
+            None => {
+                self.bayes_store(model_id)?
+                    .key_set(
+                        DECAY_KEY.to_vec(),
+                        Bincode::new(self.now()).serialize(),
+                        None,
+                    )
+                    .await
+                    .caused_by(trc::location!())?;
+                decay.interval
+            }
+        }))
+    }
+
+    // Runs a scheduled decay, which is skipped if the model was decayed within the
+    // interval by another node or a manual request. Returns false if decay is no
+    // longer configured for the model.
+    pub async fn bayes_decay_scheduled(&self, model_id: &str) -> trc::Result<bool> {
+        let Some(decay) = self.core.spam.bayes.model(model_id).decay.clone() else {
+            return Ok(false);
+        };
+        if self
+            .bayes_decay_due(model_id)
+            .await?
+            .is_some_and(|due| due.is_zero())
+        {
+            self.bayes_decay(model_id, &decay).await?;
+        }
+
+        Ok(true)
+    }
+}
+
+fn decay_count(count: u32, factor: f64, random: u32) -> u32 {
+    let decayed = count as f64 * factor;
+    let mut result = decayed.floor();
+    if decayed - result > random as f64 / u32::MAX as f64 {
+        result += 1.0;
+    }
+    result as u32
+}
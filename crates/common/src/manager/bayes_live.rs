@@ -0,0 +1,153 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use nlp::bayes::{TokenHash, Weights};
+use serde::{Deserialize, Serialize};
+
+use crate::Server;
+
+// Number of tokens with the most extreme weights included in each diagnostic
+pub(crate) const MAX_TOKENS: usize = 10;
+
+// Published to live classification subscribers, tokens are identified by their
+// hashes and the message text is only included when explicitly enabled.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClassifyDiagnostics {
+    pub model_id: String,
+    pub session_id: u64,
+    pub score: Option<f64>,
+    pub raw_score: Option<f64>,
+    pub spam_learns: u32,
+    pub ham_learns: u32,
+    pub total_tokens: usize,
+    pub cache_hits: u32,
+    pub cache_misses: u32,
+    pub elapsed_us: u64,
+    pub tokens: Vec<TokenDiagnostics>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<SourceContribution>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TokenDiagnostics {
+    pub h1: u64,
+    pub h2: u64,
+    pub spam: u32,
+    pub ham: u32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<TrainingSource>,
+}
+
+// Training of a message or batch of messages, only recorded when provenance
+// tracking is enabled. The batch is empty for messages trained individually.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrainingSource {
+    pub source: String,
+    pub batch: String,
+    pub spam: bool,
+    pub trained_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceContribution {
+    pub source: String,
+    pub tokens: usize,
+}
+
+impl Server {
+    pub fn has_bayes_live_subscribers(&self) -> bool {
+        self.inner.data.bayes_live.receiver_count() > 0
+    }
+
+    pub fn publish_bayes_live(&self, diagnostics: ClassifyDiagnostics) {
+        let _ = self.inner.data.bayes_live.send(diagnostics.into());
+    }
+}
+
+impl TokenDiagnostics {
+    // Selects up to max_tokens tokens whose spam probability is furthest from neutral
+    pub fn most_extreme(
+        tokens: impl IntoIterator<Item = (TokenHash, Weights)>,
+        spam_learns: u32,
+        ham_learns: u32,
+        max_tokens: usize,
+    ) -> Vec<Self> {
+        let mut tokens = tokens
+            .into_iter()
+            .filter(|(_, weights)| weights.spam + weights.ham > 0)
+            .map(|(hash, weights)| {
+                let token = TokenDiagnostics {
+                    h1: hash.h1,
+                    h2: hash.h2,
+                    spam: weights.spam,
+                    ham: weights.ham,
+                    sources: Vec::new(),
+                };
+                (
+                    (token.probability(spam_learns, ham_learns) - 0.5).abs(),
+                    token,
+                )
+            })
+            .collect::<Vec<_>>();
+        tokens.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+        tokens.dedup_by(|a, b| a.1.h1 == b.1.h1 && a.1.h2 == b.1.h2);
+        tokens
+            .into_iter()
+            .take(max_tokens)
+            .map(|(_, token)| token)
+            .collect()
+    }
+
+    // Spam probability of the token, from its frequency in spam and ham messages
+    pub fn probability(&self, spam_learns: u32, ham_learns: u32) -> f64 {
+        let spam_freq = self.spam as f64 / f64::max(1.0, spam_learns as f64);
+        let ham_freq = self.ham as f64 / f64::max(1.0, ham_learns as f64);
+        spam_freq / (spam_freq + ham_freq)
+    }
+}
+
+impl TrainingSource {
+    // The address of a correction source is replaced with its hash, so subscribers
+    // can group corrections by reporter without learning who reported them
+    pub fn redact(&mut self) {
+        if let Some(sender) = self.source.strip_prefix("correction:") {
+            self.source = format!(
+                "correction:{:016x}",
+                xxhash_rust::xxh3::xxh3_64(sender.as_bytes())
+            );
+        }
+    }
+}
+
+impl SourceContribution {
+    // Groups the most extreme tokens by the sources that trained them, a token
+    // trained by several batches of the same source is counted once
+    pub fn from_tokens(tokens: &[TokenDiagnostics]) -> Vec<Self> {
+        let mut sources: Vec<SourceContribution> = Vec::new();
+        for token in tokens {
+            for (pos, source) in token.sources.iter().enumerate() {
+                if token.sources[..pos]
+                    .iter()
+                    .any(|prev| prev.source == source.source)
+                {
+                    continue;
+                }
+                if let Some(contribution) = sources.iter_mut().find(|c| c.source == source.source) {
+                    contribution.tokens += 1;
+                } else {
+                    sources.push(SourceContribution {
+                        source: source.source.clone(),
+                        tokens: 1,
+                    });
+                }
+            }
+        }
+        sources.sort_by_key(|contribution| std::cmp::Reverse(contribution.tokens));
+        sources
+    }
+}
@@ -0,0 +1,137 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ahash::AHashMap;
+use nlp::bayes::{BayesMetadata, TokenHash, Weights};
+use serde::Serialize;
+use store::{write::key::DeserializeBigEndian, U64_LEN};
+use trc::AddContext;
+
+use crate::{
+    scripts::plugins::bayes::{model_metadata, model_seed, token_key, token_keys},
+    Server,
+};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct BayesMergeReport {
+    pub vocabulary: usize,
+    pub spam_learns: u32,
+    pub ham_learns: u32,
+}
+
+impl Server {
+    // Writes the summed token weights and training counts of two models into an
+    // empty destination model. Trained message ids and provenance are not merged.
+    pub async fn bayes_merge(
+        &self,
+        sources: [&str; 2],
+        destination: &str,
+    ) -> trc::Result<BayesMergeReport> {
+        if sources[0] == sources[1] || sources.contains(&destination) {
+            trc::bail!(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Source and destination models must be different"));
+        }
+
+        // Pending training updates are not visible in the store until flushed
+        self.bayes_flush().await?;
+
+        // Token hashes are only comparable if both models use the same tokenizer settings
+        let mut metadata = Vec::with_capacity(sources.len());
+        for model_id in sources {
+            metadata
+                .push(model_metadata(self, model_id, self.bayes_store(model_id)?, false).await?);
+        }
+        if metadata[0].case_folding != metadata[1].case_folding
+            || metadata[0].normalize != metadata[1].normalize
+            || metadata[0].headers != metadata[1].headers
+        {
+            trc::bail!(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Source models use different tokenizer settings"));
+        }
+
+        let store = self.bayes_store(destination)?;
+        if Weights::from(store.counter_get(token_key(&TokenHash::default())).await?)
+            != Weights::default()
+        {
+            trc::bail!(trc::ResourceEvent::BadParameters
+                .into_err()
+                .ctx(trc::Key::Id, destination.to_string())
+                .details("Destination model is not empty"));
+        }
+
+        // Union of both vocabularies, the training counts are stored as the default token
+        let mut tokens: AHashMap<TokenHash, Weights> = AHashMap::new();
+        for model_id in sources {
+            let source = self.bayes_store(model_id)?;
+            for key in token_keys(source).await? {
+                let hash = TokenHash {
+                    h1: key.as_slice().deserialize_be_u64(0)?,
+                    h2: key.as_slice().deserialize_be_u64(U64_LEN)?,
+                };
+                let weights = Weights::from(source.counter_get(key).await?);
+                let merged = tokens.entry(hash).or_default();
+                merged.spam = merged.spam.saturating_add(weights.spam);
+                merged.ham = merged.ham.saturating_add(weights.ham);
+            }
+        }
+
+        // Tokens are only pruned from the destination if both sources pruned them
+        let pruned = metadata[0]
+            .pruned
+            .iter()
+            .filter(|hash| metadata[1].is_pruned(hash))
+            .copied()
+            .collect();
+        let merged_metadata = BayesMetadata {
+            case_folding: metadata[0].case_folding,
+            normalize: metadata[0].normalize.clone(),
+            headers: metadata[0].headers.clone(),
+            calibration: None,
+            logistic: None,
+            pruned,
+        };
+        self.bayes_store_metadata(destination, store, merged_metadata)
+            .await?;
+
+        let bayes_cache = &self.inner.data.bayes_cache;
+        let model_seed = model_seed(destination);
+        let mut report = BayesMergeReport::default();
+        for (hash, weights) in tokens {
+            if weights == Weights::default() {
+                continue;
+            }
+            if hash == TokenHash::default() {
+                report.spam_learns = weights.spam;
+                report.ham_learns = weights.ham;
+            } else {
+                report.vocabulary += 1;
+            }
+
+            // Written as absolute values in case the destination has leftover tokens
+            let key = token_key(&hash);
+            let current = store.counter_get(key.clone()).await?;
+            let target = i64::from(weights);
+            if current != target {
+                store
+                    .counter_incr(key, target - current, None, false)
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+            bayes_cache.invalidate(&hash.for_model(model_seed));
+        }
+
+        trc::event!(
+            Spam(trc::SpamEvent::Train),
+            Id = destination.to_string(),
+            Details = "Merged models",
+            Total = report.vocabulary,
+        );
+
+        Ok(report)
+    }
+}
@@ -0,0 +1,233 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Instant;
+
+use ahash::AHashMap;
+use nlp::bayes::{TokenHash, Weights};
+use trc::AddContext;
+
+use utils::map::ttl_dashmap::TtlMap;
+
+use crate::{
+    scripts::plugins::bayes::{model_seed, token_key, with_retry},
+    Server,
+};
+
+// Training updates not written to the store yet, by model. Tokens being written by
+// a flush are tracked with the weights they will have once written, as the store
+// might or might not include the update while the write is in progress.
+#[derive(Debug, Default)]
+pub struct BayesPending {
+    pub deltas: AHashMap<String, AHashMap<TokenHash, i64>>,
+    pub in_flight: AHashMap<String, AHashMap<TokenHash, Weights>>,
+}
+
+impl Server {
+    // Adds training updates to the pending updates of a model, returns true when
+    // the number of pending tokens reached the flush threshold.
+    pub(crate) fn bayes_pending_add(
+        &self,
+        model_id: &str,
+        deltas: impl IntoIterator<Item = (TokenHash, i64)>,
+    ) -> bool {
+        let mut pending = self.inner.data.bayes_pending.lock();
+        let model = pending.deltas.entry(model_id.to_string()).or_default();
+        for (hash, delta) in deltas {
+            let value = model.entry(hash).or_default();
+            *value += delta;
+            if *value == 0 {
+                model.remove(&hash);
+            }
+        }
+
+        let max_pending = self
+            .core
+            .spam
+            .bayes
+            .write_behind
+            .as_ref()
+            .map_or(0, |config| config.max_pending);
+        pending
+            .deltas
+            .values()
+            .map(|model| model.len())
+            .sum::<usize>()
+            >= max_pending
+    }
+
+    // Adds the pending updates of a token to the weights read from the store, or to
+    // the weights the token will have once written when a flush is writing it
+    pub(crate) fn bayes_pending_apply(
+        &self,
+        model_id: &str,
+        hash: &TokenHash,
+        weights: Weights,
+    ) -> Weights {
+        if self.core.spam.bayes.write_behind.is_none() {
+            return weights;
+        }
+
+        let pending = self.inner.data.bayes_pending.lock();
+        let weights = pending
+            .in_flight
+            .get(model_id)
+            .and_then(|model| model.get(hash))
+            .copied()
+            .unwrap_or(weights);
+        match pending
+            .deltas
+            .get(model_id)
+            .and_then(|model| model.get(hash))
+        {
+            Some(delta) => Weights::from(i64::from(weights) + delta),
+            None => weights,
+        }
+    }
+
+    // Writes the pending training updates to the store, one batch per model, and
+    // returns the number of updated tokens. While a batch is written, the updates of
+    // cached tokens move from the pending deltas to the in-flight weights, so classify
+    // neither misses nor double counts them. Updates of tokens not in the cache stay
+    // pending until written, a classification reading them from the store while the
+    // batch commits may count them twice. Model maintenance operations flush first,
+    // as they read the store directly.
+    pub async fn bayes_flush(&self) -> trc::Result<usize> {
+        let _flush = self.inner.data.bayes_flush.lock().await;
+        let models = self
+            .inner
+            .data
+            .bayes_pending
+            .lock()
+            .deltas
+            .iter()
+            .map(|(model_id, model)| {
+                (
+                    model_id.clone(),
+                    model
+                        .iter()
+                        .map(|(hash, delta)| (*hash, *delta))
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        if models.is_empty() {
+            return Ok(0);
+        }
+
+        let time = Instant::now();
+        let bayes_cache = &self.inner.data.bayes_cache;
+        let retry = &self.core.spam.bayes.retry;
+        let mut total = 0;
+        for (model_id, deltas) in models {
+            let store = self.bayes_store(&model_id)?;
+            let model_seed = model_seed(&model_id);
+
+            // The updates of cached tokens are read from the in-flight weights until written
+            {
+                let mut pending = self.inner.data.bayes_pending.lock();
+                let BayesPending {
+                    deltas: pending_deltas,
+                    in_flight,
+                } = &mut *pending;
+                for (hash, delta) in &deltas {
+                    let Some(weights) = bayes_cache.get(&hash.for_model(model_seed)) else {
+                        continue;
+                    };
+                    if let Some(model) = pending_deltas.get_mut(&model_id) {
+                        if let Some(value) = model.get_mut(hash) {
+                            *value -= delta;
+                            if *value == 0 {
+                                model.remove(hash);
+                            }
+                        }
+                        if model.is_empty() {
+                            pending_deltas.remove(&model_id);
+                        }
+                    }
+                    in_flight.entry(model_id.clone()).or_default().insert(
+                        *hash,
+                        Weights::from(i64::from(weights.unwrap_or_default()) + delta),
+                    );
+                }
+            }
+
+            let result = with_retry(retry, || {
+                store.counter_incr_many(
+                    deltas
+                        .iter()
+                        .map(|(hash, delta)| (token_key(hash), *delta))
+                        .collect(),
+                )
+            })
+            .await
+            .caused_by(trc::location!());
+
+            // Once written, the updates are read from the store. Failed updates
+            // are pending again, to be retried by the next flush.
+            {
+                let mut pending = self.inner.data.bayes_pending.lock();
+                let BayesPending {
+                    deltas: pending_deltas,
+                    in_flight,
+                } = &mut *pending;
+                let in_flight = in_flight.remove(&model_id).unwrap_or_default();
+                for (hash, delta) in &deltas {
+                    let is_in_flight = in_flight.contains_key(hash);
+                    if result.is_err() == is_in_flight {
+                        // Written updates left pending are removed, failed in-flight
+                        // updates are added back
+                        let model = pending_deltas.entry(model_id.clone()).or_default();
+                        let value = model.entry(*hash).or_default();
+                        *value += if is_in_flight { *delta } else { -*delta };
+                        if *value == 0 {
+                            model.remove(hash);
+                        }
+                        if model.is_empty() {
+                            pending_deltas.remove(&model_id);
+                        }
+                    }
+                    bayes_cache.invalidate(&hash.for_model(model_seed));
+                }
+            }
+            result?;
+            let flushed = deltas.into_iter().map(|(hash, _)| hash).collect::<Vec<_>>();
+
+            total += flushed.len();
+            self.bayes_track_replica(&model_id, flushed);
+        }
+
+        trc::event!(
+            Spam(trc::SpamEvent::Train),
+            Details = "Flushed pending training updates",
+            Total = total,
+            Elapsed = time.elapsed(),
+        );
+
+        Ok(total)
+    }
+
+    // Keeps track of the tokens that might not have reached the read replica yet,
+    // expired entries are removed by the housekeeper
+    pub(crate) fn bayes_track_replica(
+        &self,
+        model_id: &str,
+        hashes: impl IntoIterator<Item = TokenHash>,
+    ) {
+        let config = self.core.spam.bayes.model(model_id);
+        if let (Some(_), Some(max_lag)) = (&config.replica, config.replica_max_lag) {
+            let valid_until = Instant::now() + max_lag;
+            let model_seed = model_seed(model_id);
+            for hash in hashes {
+                self.inner.data.bayes_trained.insert_with_ttl(
+                    hash.for_model(model_seed),
+                    (),
+                    valid_until,
+                );
+            }
+        }
+    }
+}
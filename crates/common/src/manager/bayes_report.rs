@@ -0,0 +1,218 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::fmt::Write;
+
+use mail_parser::DateTime;
+use nlp::bayes::{TokenHash, Weights};
+use serde::Serialize;
+use store::{write::key::DeserializeBigEndian, U64_LEN};
+use trc::AddContext;
+
+use crate::{
+    scripts::plugins::bayes::{token_keys_sample, with_retry},
+    Server,
+};
+
+use super::bayes_snapshot::BayesSnapshot;
+
+// Overview of a model intended for periodic review by operators. Token weights
+// are read from a sample of the vocabulary, so the top tokens and the vocabulary
+// size of large models are approximate.
+#[derive(Debug, Clone, Serialize)]
+pub struct BayesReport {
+    pub snapshot: BayesSnapshot,
+    pub sampled_tokens: usize,
+    pub estimated_vocabulary: bool,
+    pub spam_tokens: Vec<ReportToken>,
+    pub ham_tokens: Vec<ReportToken>,
+}
+
+// Tokens are identified by their hash, as the text of the tokens is not stored
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportToken {
+    pub hash: String,
+    pub spam: u32,
+    pub ham: u32,
+    pub probability: f64,
+}
+
+impl Server {
+    pub async fn bayes_report(
+        &self,
+        model_id: &str,
+        max_sample: usize,
+        max_tokens: usize,
+    ) -> trc::Result<BayesReport> {
+        // Pending training updates are not visible in the store until flushed
+        self.bayes_flush().await?;
+
+        let store = self.bayes_store(model_id)?;
+        let retry = &self.core.spam.bayes.retry;
+
+        // Keys are ordered by hash, so the first keys are a random sample. When the
+        // sample is full, the vocabulary is estimated from the fraction of the hash
+        // space it covers.
+        let max_sample = max_sample.max(1);
+        let keys = token_keys_sample(store, max_sample).await?;
+        let estimated_vocabulary = keys.len() >= max_sample;
+        let vocabulary = match keys.last() {
+            Some(key) if estimated_vocabulary => {
+                let covered = (key.as_slice().deserialize_be_u64(0)? as f64 + 1.0) / 2f64.powi(64);
+                (keys.len() as f64 / covered).round() as usize
+            }
+            _ => keys.len(),
+        };
+        let snapshot = self
+            .bayes_snapshot_with_tokens(model_id, Some(vocabulary))
+            .await?;
+        let spam_learns = snapshot.stats.spam_learns;
+        let ham_learns = snapshot.stats.ham_learns;
+
+        let min_hits = self.core.spam.bayes.model(model_id).min_token_hits;
+        let mut tokens = Vec::new();
+        let mut sampled_tokens = 0;
+        for key in &keys {
+            let weights = Weights::from(
+                with_retry(retry, || store.counter_get(key.clone()))
+                    .await
+                    .caused_by(trc::location!())?,
+            );
+            sampled_tokens += 1;
+            if weights.spam + weights.ham < min_hits {
+                continue;
+            }
+
+            let spam_freq = weights.spam as f64 / f64::max(1.0, spam_learns as f64);
+            let ham_freq = weights.ham as f64 / f64::max(1.0, ham_learns as f64);
+            let hash = TokenHash {
+                h1: key.as_slice().deserialize_be_u64(0)?,
+                h2: key.as_slice().deserialize_be_u64(U64_LEN)?,
+            };
+            tokens.push(ReportToken {
+                hash: format!("{:016x}{:016x}", hash.h1, hash.h2),
+                spam: weights.spam,
+                ham: weights.ham,
+                probability: spam_freq / (spam_freq + ham_freq),
+            });
+        }
+
+        // Ties are broken by the number of messages the token was seen in
+        tokens.sort_unstable_by(|a, b| {
+            b.probability
+                .total_cmp(&a.probability)
+                .then_with(|| (b.spam + b.ham).cmp(&(a.spam + a.ham)))
+        });
+        let spam_tokens = tokens
+            .iter()
+            .filter(|token| token.probability > 0.5)
+            .take(max_tokens)
+            .cloned()
+            .collect();
+        tokens.sort_unstable_by(|a, b| {
+            a.probability
+                .total_cmp(&b.probability)
+                .then_with(|| (b.spam + b.ham).cmp(&(a.spam + a.ham)))
+        });
+        let ham_tokens = tokens
+            .into_iter()
+            .filter(|token| token.probability < 0.5)
+            .take(max_tokens)
+            .collect();
+
+        Ok(BayesReport {
+            snapshot,
+            sampled_tokens,
+            estimated_vocabulary,
+            spam_tokens,
+            ham_tokens,
+        })
+    }
+}
+
+impl BayesReport {
+    pub fn to_markdown(&self) -> String {
+        let snapshot = &self.snapshot;
+        let stats = &snapshot.stats;
+        let mut report = String::new();
+        let _ = writeln!(
+            report,
+            "# Bayes model report: {}\n\nGenerated on {}.\n",
+            if snapshot.model_id.is_empty() {
+                "default"
+            } else {
+                snapshot.model_id.as_str()
+            },
+            timestamp(Some(snapshot.created_at))
+        );
+
+        let _ = writeln!(report, "## Training\n");
+        let _ = writeln!(report, "- Spam messages learned: {}", stats.spam_learns);
+        let _ = writeln!(report, "- Ham messages learned: {}", stats.ham_learns);
+        if let Some(tokens) = stats.tokens {
+            if self.estimated_vocabulary {
+                let _ = writeln!(report, "- Vocabulary: about {tokens} tokens (estimated)");
+            } else {
+                let _ = writeln!(report, "- Vocabulary: {tokens} tokens");
+            }
+        }
+        let _ = writeln!(report, "- Tokens sampled: {}", self.sampled_tokens);
+        let _ = writeln!(report, "- Last trained: {}", timestamp(stats.last_trained));
+        let _ = writeln!(report, "- Last decayed: {}", timestamp(stats.last_decay));
+        if stats.sampled > 0 || stats.skipped > 0 {
+            let _ = writeln!(
+                report,
+                "- Messages sampled for training: {} (skipped {})",
+                stats.sampled, stats.skipped
+            );
+        }
+
+        for (title, tokens) in [
+            ("Top spam tokens", &self.spam_tokens),
+            ("Top ham tokens", &self.ham_tokens),
+        ] {
+            let _ = writeln!(report, "\n## {title}\n");
+            if tokens.is_empty() {
+                let _ = writeln!(report, "No tokens found.");
+                continue;
+            }
+            let _ = writeln!(
+                report,
+                "Tokens are shown as hashes, the text of the tokens is not stored.\n"
+            );
+            let _ = writeln!(report, "| Token hash | Spam | Ham | Probability |");
+            let _ = writeln!(report, "|---|---:|---:|---:|");
+            for token in tokens {
+                let _ = writeln!(
+                    report,
+                    "| `{}` | {} | {} | {:.4} |",
+                    token.hash, token.spam, token.ham, token.probability
+                );
+            }
+        }
+
+        let _ = writeln!(
+            report,
+            "\n## Configuration\n\n```json\n{}\n```",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "tokenizer": snapshot.tokenizer,
+                "classifier": snapshot.classifier,
+                "training": snapshot.training,
+                "storage": snapshot.storage,
+            }))
+            .unwrap_or_default()
+        );
+
+        report
+    }
+}
+
+fn timestamp(timestamp: Option<u64>) -> String {
+    timestamp.map_or_else(
+        || "never".to_string(),
+        |timestamp| DateTime::from_timestamp(timestamp as i64).to_rfc3339(),
+    )
+}
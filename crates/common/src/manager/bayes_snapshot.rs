@@ -0,0 +1,225 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use nlp::bayes::{headers::HeaderFeature, normalize::TokenClass, tokenize::CaseFolding, Weights};
+use serde::Serialize;
+use store::{
+    write::{key::KeySerializer, Bincode},
+    U64_LEN,
+};
+use trc::AddContext;
+
+use crate::{
+    scripts::plugins::bayes::{model_metadata, sample_key, token_keys, LAST_TRAINED_KEY},
+    Server,
+};
+
+// Number of previous tokens combined with each token by the OSB tokenizer
+const OSB_WINDOW: usize = 5;
+
+// Effective settings and training statistics of a model, intended for diagnostics
+// and bug reports. Durations are expressed in seconds, except for the retry
+// backoff which is expressed in milliseconds.
+#[derive(Debug, Clone, Serialize)]
+pub struct BayesSnapshot {
+    pub model_id: String,
+    pub created_at: u64,
+    pub tokenizer: TokenizerSnapshot,
+    pub classifier: ClassifierSnapshot,
+    pub training: TrainingSnapshot,
+    pub storage: StorageSnapshot,
+    pub stats: StatsSnapshot,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenizerSnapshot {
+    pub case_folding: CaseFolding,
+    pub normalize: Vec<TokenClass>,
+    pub headers: Vec<HeaderFeature>,
+    pub osb_window: usize,
+    pub max_text_size: usize,
+    pub truncate_text: bool,
+    pub pruned_tokens: usize,
+    pub prune_threshold: f64,
+    pub prune_min_documents: u32,
+    pub prune_max_tokens: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClassifierSnapshot {
+    pub combiner: &'static str,
+    pub smoothing: &'static str,
+    // Model thresholds, which Sieve scripts passing classification parameters override
+    pub min_token_hits: u32,
+    pub min_tokens: u32,
+    pub min_prob_strength: f64,
+    pub min_learns: u32,
+    pub max_tokens: u32,
+    pub min_matched_tokens: u32,
+    pub label_spam: f64,
+    pub label_ham: f64,
+    pub top_tokens: usize,
+    pub empty_score: Option<f64>,
+    pub calibration_points: Option<usize>,
+    // Features combined with the Bayes score by the logistic ensemble, if fitted
+    pub logistic_features: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrainingSnapshot {
+    pub untrain_strict: bool,
+    pub train_weight: u32,
+    pub correction_weight: u32,
+    pub correction_sender_limit: u32,
+    pub correction_sender_window: u64,
+    pub trained_hash_expiry: u64,
+    pub change_log_expiry: Option<u64>,
+    pub provenance_expiry: Option<u64>,
+    pub sample_rate: Option<f64>,
+    pub sample_seed: Option<u64>,
+    pub decay_factor: Option<f64>,
+    pub decay_interval: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageSnapshot {
+    pub replica: Option<String>,
+    pub replica_max_lag: Option<u64>,
+    pub retry_attempts: u32,
+    pub retry_backoff_ms: u64,
+    pub retry_max_backoff_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsSnapshot {
+    pub spam_learns: u32,
+    pub ham_learns: u32,
+    // Messages selected and skipped by training sampling
+    pub sampled: i64,
+    pub skipped: i64,
+    pub last_trained: Option<u64>,
+    pub last_decay: Option<u64>,
+    // Only available when the model is stored in a data store
+    pub tokens: Option<usize>,
+}
+
+impl Server {
+    pub async fn bayes_snapshot(&self, model_id: &str) -> trc::Result<BayesSnapshot> {
+        let tokens = match token_keys(self.bayes_store(model_id)?).await {
+            // The training counts are stored along with the token weights
+            Ok(keys) => Some(keys.len().saturating_sub(1)),
+            Err(err) if err.matches(trc::EventType::Store(trc::StoreEvent::NotSupported)) => None,
+            Err(err) => return Err(err),
+        };
+
+        self.bayes_snapshot_with_tokens(model_id, tokens).await
+    }
+
+    // Builds the snapshot from a token count obtained by the caller, which avoids
+    // iterating the tokens twice when the caller already did
+    pub(crate) async fn bayes_snapshot_with_tokens(
+        &self,
+        model_id: &str,
+        tokens: Option<usize>,
+    ) -> trc::Result<BayesSnapshot> {
+        let store = self.bayes_store(model_id)?;
+        let bayes = &self.core.spam.bayes;
+        let config = bayes.model(model_id);
+        let metadata = model_metadata(self, model_id, store, false).await?;
+        let classifier = config.classifier();
+
+        let training = Weights::from(
+            store
+                .counter_get(
+                    KeySerializer::new(U64_LEN)
+                        .write(0u64)
+                        .write(0u64)
+                        .finalize(),
+                )
+                .await
+                .caused_by(trc::location!())?,
+        );
+
+        Ok(BayesSnapshot {
+            model_id: model_id.to_string(),
+            created_at: self.now(),
+            tokenizer: TokenizerSnapshot {
+                case_folding: metadata.case_folding,
+                normalize: metadata.normalize.clone(),
+                headers: metadata.headers.clone(),
+                osb_window: OSB_WINDOW,
+                max_text_size: bayes.max_text_size,
+                truncate_text: bayes.truncate_text,
+                pruned_tokens: metadata.pruned.len(),
+                prune_threshold: config.prune_threshold,
+                prune_min_documents: config.prune_min_documents,
+                prune_max_tokens: config.prune_max_tokens,
+            },
+            classifier: ClassifierSnapshot {
+                combiner: "fisher-inverse-chi-square",
+                smoothing: "robinson",
+                min_token_hits: classifier.min_token_hits,
+                min_tokens: classifier.min_tokens,
+                min_prob_strength: classifier.min_prob_strength,
+                min_learns: classifier.min_learns,
+                max_tokens: classifier.max_tokens,
+                min_matched_tokens: config.min_matched_tokens,
+                label_spam: config.label_spam,
+                label_ham: config.label_ham,
+                top_tokens: config.top_tokens,
+                empty_score: config.empty_score,
+                calibration_points: metadata
+                    .calibration
+                    .as_ref()
+                    .map(|calibration| calibration.points.len()),
+                logistic_features: metadata
+                    .logistic
+                    .as_ref()
+                    .map(|logistic| logistic.features.clone()),
+            },
+            training: TrainingSnapshot {
+                untrain_strict: config.untrain_strict,
+                train_weight: config.train_weight,
+                correction_weight: config.correction_weight,
+                correction_sender_limit: config.correction_sender_limit,
+                correction_sender_window: config.correction_sender_window.as_secs(),
+                trained_hash_expiry: config.trained_hash_expiry.as_secs(),
+                change_log_expiry: config.change_log_expiry.map(|d| d.as_secs()),
+                provenance_expiry: config.provenance_expiry.map(|d| d.as_secs()),
+                sample_rate: config.train_sample.as_ref().map(|sample| sample.rate),
+                sample_seed: config.train_sample.as_ref().map(|sample| sample.seed),
+                decay_factor: config.decay.as_ref().map(|decay| decay.factor),
+                decay_interval: config.decay.as_ref().map(|decay| decay.interval.as_secs()),
+            },
+            storage: StorageSnapshot {
+                replica: config.replica.clone(),
+                replica_max_lag: config.replica_max_lag.map(|d| d.as_secs()),
+                retry_attempts: bayes.retry.attempts,
+                retry_backoff_ms: bayes.retry.backoff.as_millis() as u64,
+                retry_max_backoff_ms: bayes.retry.max_backoff.as_millis() as u64,
+            },
+            stats: StatsSnapshot {
+                spam_learns: training.spam,
+                ham_learns: training.ham,
+                sampled: store
+                    .counter_get(sample_key(true))
+                    .await
+                    .caused_by(trc::location!())?,
+                skipped: store
+                    .counter_get(sample_key(false))
+                    .await
+                    .caused_by(trc::location!())?,
+                last_trained: store
+                    .key_get::<Bincode<u64>>(LAST_TRAINED_KEY.to_vec())
+                    .await
+                    .caused_by(trc::location!())?
+                    .map(|last_trained| last_trained.inner),
+                last_decay: self.bayes_decay_last_run(model_id).await?,
+                tokens,
+            },
+        })
+    }
+}
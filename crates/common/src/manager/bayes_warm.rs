@@ -0,0 +1,187 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use nlp::bayes::{TokenHash, Weights};
+use serde::{Deserialize, Serialize};
+use store::{U32_LEN, U64_LEN};
+
+use crate::{scripts::plugins::bayes::model_metadata, Server};
+
+const WARM_SET_VERSION: u32 = 2;
+
+// Serialized size of the header and of each token
+const HEADER_SIZE: usize = U32_LEN + 3 * U64_LEN;
+const TOKEN_SIZE: usize = 2 * U64_LEN + 2 * U32_LEN;
+
+// Positive entries of the token cache, keys are already salted with the model seed.
+// The fingerprint covers the stored metadata that determines the tokens of each
+// model the tokens might belong to, warm sets saved with different metadata are
+// discarded.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BayesWarmSet {
+    pub version: u32,
+    pub fingerprint: u64,
+    pub saved_at: u64,
+    pub models: Vec<String>,
+    pub tokens: Vec<(TokenHash, Weights)>,
+}
+
+impl Server {
+    // Writes the most recently used token weights to disk, returns the number of
+    // saved tokens or None if persistence is disabled.
+    pub async fn bayes_warm_save(&self) -> trc::Result<Option<usize>> {
+        let Some(persist) = &self.core.spam.bayes.persist else {
+            return Ok(None);
+        };
+        let mut models = self
+            .inner
+            .data
+            .bayes_cached_models
+            .read()
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        models.sort_unstable();
+        let models_size = U64_LEN + models.iter().map(|id| U64_LEN + id.len()).sum::<usize>();
+        let max_tokens = persist.max_size.saturating_sub(HEADER_SIZE + models_size) / TOKEN_SIZE;
+        let warm_set = BayesWarmSet {
+            version: WARM_SET_VERSION,
+            fingerprint: self.bayes_fingerprint(&models).await?,
+            saved_at: self.now(),
+            tokens: self.inner.data.bayes_cache.export_positive(max_tokens),
+            models,
+        };
+        let bytes = bincode::serialize(&warm_set).map_err(|err| {
+            trc::EventType::Spam(trc::SpamEvent::CacheSave)
+                .reason(err)
+                .details("Failed to serialize token cache")
+        })?;
+
+        // Replaced atomically, so a crash while saving does not leave a truncated file
+        let mut tmp_path = persist.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        tokio::fs::write(&tmp_path, &bytes)
+            .await
+            .map_err(|err| io_error(trc::SpamEvent::CacheSave, err, &persist.path))?;
+        tokio::fs::rename(&tmp_path, &persist.path)
+            .await
+            .map_err(|err| io_error(trc::SpamEvent::CacheSave, err, &persist.path))?;
+
+        trc::event!(
+            Spam(trc::SpamEvent::CacheSave),
+            Path = persist.path.display().to_string(),
+            Total = warm_set.tokens.len(),
+            Size = bytes.len(),
+        );
+
+        Ok(Some(warm_set.tokens.len()))
+    }
+
+    // Restores a warm set saved by bayes_warm_save, returns the number of loaded
+    // tokens or None if persistence is disabled or no usable warm set was found.
+    pub async fn bayes_warm_load(&self) -> trc::Result<Option<usize>> {
+        let Some(persist) = &self.core.spam.bayes.persist else {
+            return Ok(None);
+        };
+        let bytes = match tokio::fs::read(&persist.path).await {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(io_error(trc::SpamEvent::CacheLoad, err, &persist.path)),
+        };
+
+        let result = if bytes.len() > persist.max_size {
+            Err("Warm set exceeds the maximum size")
+        } else {
+            match bincode::deserialize::<BayesWarmSet>(&bytes) {
+                Ok(warm_set) if warm_set.version != WARM_SET_VERSION => {
+                    Err("Unsupported warm set version")
+                }
+                Ok(warm_set)
+                    if warm_set.fingerprint != self.bayes_fingerprint(&warm_set.models).await? =>
+                {
+                    Err("Warm set was saved with different tokenizer settings")
+                }
+                Ok(warm_set) => Ok(warm_set),
+                Err(_) => Err("Failed to deserialize warm set"),
+            }
+        };
+
+        // Warm sets are only loaded once
+        let _ = tokio::fs::remove_file(&persist.path).await;
+
+        match result {
+            Ok(warm_set) => {
+                let age = Duration::from_secs(self.now().saturating_sub(warm_set.saved_at));
+                let total = self
+                    .inner
+                    .data
+                    .bayes_cache
+                    .import_positive(warm_set.tokens, age);
+
+                trc::event!(
+                    Spam(trc::SpamEvent::CacheLoad),
+                    Path = persist.path.display().to_string(),
+                    Total = total,
+                    Elapsed = age,
+                );
+
+                Ok(Some(total))
+            }
+            Err(reason) => {
+                trc::event!(
+                    Spam(trc::SpamEvent::CacheLoad),
+                    Path = persist.path.display().to_string(),
+                    Reason = reason,
+                    Total = 0,
+                );
+
+                Ok(None)
+            }
+        }
+    }
+
+    // Models are tokenized with the settings stored with the model rather than the
+    // configured ones, and ignore their pruned tokens. Models whose store is no longer
+    // available are left out, so warm sets including them are discarded.
+    async fn bayes_fingerprint(&self, model_ids: &[String]) -> trc::Result<u64> {
+        let mut models = Vec::with_capacity(model_ids.len());
+        for model_id in model_ids {
+            let model_id = model_id.as_str();
+            if let Ok(store) = self.bayes_store(model_id) {
+                models.push((
+                    model_id,
+                    model_metadata(self, model_id, store, false).await?,
+                ));
+            }
+        }
+
+        Ok(xxhash_rust::xxh3::xxh3_64(
+            &bincode::serialize(
+                &models
+                    .iter()
+                    .map(|(model_id, metadata)| {
+                        (
+                            model_id,
+                            &metadata.case_folding,
+                            &metadata.normalize,
+                            &metadata.headers,
+                            &metadata.pruned,
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .unwrap_or_default(),
+        ))
+    }
+}
+
+fn io_error(event: trc::SpamEvent, err: std::io::Error, path: &std::path::Path) -> trc::Error {
+    trc::EventType::Spam(event)
+        .from_io_error(err)
+        .ctx(trc::Key::Path, path.display().to_string())
+}
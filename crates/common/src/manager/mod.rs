@@ -13,6 +13,14 @@ use crate::USER_AGENT;
 use self::config::ConfigManager;
 
 pub mod backup;
+pub mod bayes_backup;
+pub mod bayes_decay;
+pub mod bayes_live;
+pub mod bayes_merge;
+pub mod bayes_pending;
+pub mod bayes_report;
+pub mod bayes_snapshot;
+pub mod bayes_warm;
 pub mod boot;
 pub mod config;
 pub mod console;
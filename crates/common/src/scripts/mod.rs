@@ -26,6 +26,11 @@ pub enum ScriptModification {
         name: Arc<String>,
         value: Arc<String>,
     },
+    // Global variables set by plugins, applied by the event loop once the plugin returns
+    SetVariable {
+        name: String,
+        value: Variable,
+    },
 }
 
 pub fn into_sieve_value(value: Value) -> Variable {
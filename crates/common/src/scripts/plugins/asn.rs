@@ -0,0 +1,245 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::net::IpAddr;
+
+use mail_auth::common::resolver::ToReverseName;
+use serde::{Deserialize, Serialize};
+use sieve::{runtime::Variable, FunctionMap};
+use store::{write::Bincode, Serialize as _};
+use trc::AddContext;
+
+use crate::{config::spamfilter::AsnReputationConfig, Server};
+
+use super::PluginContext;
+
+pub fn register(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("asn_lookup", plugin_id, 1);
+}
+
+pub fn register_reputation(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("asn_reputation", plugin_id, 2);
+}
+
+pub fn register_reputation_update(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("asn_reputation_update", plugin_id, 3);
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AsnReputation {
+    pub spam: f64,
+    pub ham: f64,
+    pub updated: u64,
+}
+
+pub async fn exec(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    Ok(match ctx.arguments[0].to_string().parse::<IpAddr>().ok() {
+        Some(ip) => ctx
+            .server
+            .asn_lookup(ip)
+            .await
+            .map(|asn| Variable::Integer(asn as i64)),
+        None => None,
+    }
+    .unwrap_or_default())
+}
+
+pub async fn exec_reputation(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let store = match &ctx.arguments[0] {
+        Variable::String(v) if !v.is_empty() => ctx.server.core.storage.lookups.get(v.as_ref()),
+        _ => Some(&ctx.server.core.storage.lookup),
+    }
+    .ok_or_else(|| {
+        trc::SieveEvent::RuntimeError
+            .ctx(trc::Key::Id, ctx.arguments[0].to_string().into_owned())
+            .details("Unknown store")
+    })?;
+    let config = &ctx.server.core.spam.asn;
+
+    let Some(asn) = asn_argument(&ctx).await else {
+        return Ok(config.neutral.into());
+    };
+
+    Ok(store
+        .key_get::<Bincode<AsnReputation>>(asn_key(asn))
+        .await
+        .caused_by(trc::location!())?
+        .map(|reputation| {
+            reputation
+                .inner
+                .decay(config, ctx.server.now())
+                .score(config)
+        })
+        .unwrap_or(config.neutral)
+        .into())
+}
+
+pub async fn exec_reputation_update(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let store = match &ctx.arguments[0] {
+        Variable::String(v) if !v.is_empty() => ctx.server.core.storage.lookups.get(v.as_ref()),
+        _ => Some(&ctx.server.core.storage.lookup),
+    }
+    .ok_or_else(|| {
+        trc::SieveEvent::RuntimeError
+            .ctx(trc::Key::Id, ctx.arguments[0].to_string().into_owned())
+            .details("Unknown store")
+    })?;
+    let config = &ctx.server.core.spam.asn;
+    let is_spam = ctx.arguments[2].to_bool();
+
+    let Some(asn) = asn_argument(&ctx).await else {
+        return Ok(false.into());
+    };
+
+    // The reputation is read and written back without locking, an outcome recorded
+    // concurrently for the same ASN by another session or node can be lost. The
+    // score is a decaying average over many outcomes, so occasional losses are
+    // accepted rather than serializing the updates.
+    let now = ctx.server.now();
+    let mut reputation = store
+        .key_get::<Bincode<AsnReputation>>(asn_key(asn))
+        .await
+        .caused_by(trc::location!())?
+        .map(|reputation| reputation.inner.decay(config, now))
+        .unwrap_or(AsnReputation {
+            updated: now,
+            ..Default::default()
+        });
+    if is_spam {
+        reputation.spam += 1.0;
+    } else {
+        reputation.ham += 1.0;
+    }
+
+    store
+        .key_set(
+            asn_key(asn),
+            Bincode::new(reputation).serialize(),
+            config.expire.as_secs().into(),
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+    Ok(true.into())
+}
+
+// Accepts either an IP address or an ASN
+async fn asn_argument(ctx: &PluginContext<'_>) -> Option<u32> {
+    match &ctx.arguments[1] {
+        Variable::Integer(asn) => u32::try_from(*asn).ok(),
+        value => {
+            let value = value.to_string();
+            if let Ok(ip) = value.parse::<IpAddr>() {
+                ctx.server.asn_lookup(ip).await
+            } else {
+                value.trim().parse::<u32>().ok()
+            }
+        }
+    }
+    .filter(|asn| *asn != 0)
+}
+
+fn asn_key(asn: u32) -> Vec<u8> {
+    format!("asn:{asn}").into_bytes()
+}
+
+impl Server {
+    pub async fn asn_lookup(&self, ip: IpAddr) -> Option<u32> {
+        let config = &self.core.spam.asn;
+        let name = match ip {
+            IpAddr::V4(_) => format!("{}.{}", ip.to_reverse_name(), config.zone_ipv4),
+            IpAddr::V6(_) => format!("{}.{}", ip.to_reverse_name(), config.zone_ipv6),
+        };
+
+        #[cfg(feature = "test_mode")]
+        if let Some(record) = super::dns::test_txt_record(&name) {
+            return parse_asn(record);
+        }
+
+        self.core
+            .smtp
+            .resolvers
+            .dns
+            .txt_raw_lookup(name)
+            .await
+            .ok()
+            .and_then(|record| parse_asn(std::str::from_utf8(&record).ok()?))
+    }
+}
+
+// Parses records in the "ASN | prefix | country | registry | date" format, when multiple
+// origins are announced ("ASN ASN | ...") the first one is used.
+fn parse_asn(record: &str) -> Option<u32> {
+    record
+        .split('|')
+        .next()?
+        .split_ascii_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+impl AsnReputation {
+    pub fn decay(mut self, config: &AsnReputationConfig, now: u64) -> Self {
+        let half_life = config.half_life.as_secs();
+        if half_life > 0 && now > self.updated {
+            let factor = 0.5f64.powf((now - self.updated) as f64 / half_life as f64);
+            self.spam *= factor;
+            self.ham *= factor;
+        }
+        self.updated = now;
+        self
+    }
+
+    pub fn score(&self, config: &AsnReputationConfig) -> f64 {
+        (self.spam + config.neutral * config.prior_weight)
+            / (self.spam + self.ham + config.prior_weight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::config::spamfilter::AsnReputationConfig;
+
+    use super::{parse_asn, AsnReputation};
+
+    #[test]
+    fn asn_reputation() {
+        assert_eq!(
+            parse_asn("23028 | 216.90.108.0/24 | US | arin | 1998-09-25"),
+            Some(23028)
+        );
+        assert_eq!(parse_asn("701 1239 | 63.0.0.0/8 | US | arin |"), Some(701));
+        assert_eq!(parse_asn("NA | | |"), None);
+
+        let config = AsnReputationConfig {
+            half_life: Duration::from_secs(86400),
+            neutral: 0.5,
+            prior_weight: 2.0,
+            ..Default::default()
+        };
+
+        // Unknown ASNs start from the neutral value
+        assert_eq!(AsnReputation::default().score(&config), 0.5);
+
+        // Spam outcomes raise the score, bounded by the prior
+        let reputation = AsnReputation {
+            spam: 8.0,
+            ham: 0.0,
+            updated: 0,
+        };
+        assert_eq!(reputation.score(&config), 0.9);
+
+        // Outcomes lose half their weight after each half-life
+        let decayed = reputation.decay(&config, 86400);
+        assert_eq!(decayed.spam, 4.0);
+        assert_eq!(decayed.updated, 86400);
+        assert!(decayed.score(&config) < reputation.score(&config));
+        assert!(decayed.score(&config) > 0.5);
+    }
+}
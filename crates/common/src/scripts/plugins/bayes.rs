@@ -4,16 +4,34 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::{borrow::Cow, future::Future, sync::Arc, time::Instant};
+
+use ahash::AHashMap;
 use nlp::{
     bayes::{
-        cache::BayesTokenCache, tokenize::BayesTokenizer, BayesClassifier, BayesModel, TokenHash,
-        Weights,
+        cache::BayesTokenCache, calibration::IsotonicCalibration, divergence::BayesDivergence,
+        headers::HeaderFeature, logistic::LogisticModel, prune::CooccurrenceAnalyzer,
+        tokenize::BayesTokenizer, BayesMetadata, BayesModel, TokenHash, Weights,
     },
-    tokenizers::osb::{OsbToken, OsbTokenizer},
+    tokenizers::osb::{Gram, OsbToken, OsbTokenizer},
 };
 use sieve::{runtime::Variable, FunctionMap};
-use store::{write::key::KeySerializer, LookupStore, U64_LEN};
-use trc::AddContext;
+use store::{
+    write::{key::KeySerializer, Bincode, LookupClass, ValueClass},
+    IterateParams, LookupStore, Serialize as _, ValueKey, U64_LEN,
+};
+use trc::{AddContext, Collector};
+use utils::map::ttl_dashmap::TtlMap;
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::{
+    config::spamfilter::{BayesCountsRead, BayesModelConfig, BayesRetryConfig, BayesSplitConfig},
+    manager::bayes_live::{
+        ClassifyDiagnostics, SourceContribution, TokenDiagnostics, TrainingSource, MAX_TOKENS,
+    },
+    scripts::ScriptModification,
+    Server,
+};
 
 use super::PluginContext;
 
@@ -21,6 +39,10 @@ pub fn register_train(plugin_id: u32, fnc_map: &mut FunctionMap) {
     fnc_map.set_external_function("bayes_train", plugin_id, 3);
 }
 
+pub fn register_train_manual(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("bayes_train_manual", plugin_id, 4);
+}
+
 pub fn register_untrain(plugin_id: u32, fnc_map: &mut FunctionMap) {
     fnc_map.set_external_function("bayes_untrain", plugin_id, 3);
 }
@@ -33,15 +55,107 @@ pub fn register_is_balanced(plugin_id: u32, fnc_map: &mut FunctionMap) {
     fnc_map.set_external_function("bayes_is_balanced", plugin_id, 3);
 }
 
+pub fn register_divergence(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("bayes_divergence", plugin_id, 2);
+}
+
+pub fn register_correct(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("bayes_correct", plugin_id, 5);
+}
+
+pub fn register_train_split(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("bayes_train_split", plugin_id, 4);
+}
+
+pub fn register_untrain_split(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("bayes_untrain_split", plugin_id, 4);
+}
+
+pub fn register_classify_split(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("bayes_classify_split", plugin_id, 4);
+}
+
+pub fn register_classify_vars(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("bayes_classify_vars", plugin_id, 3);
+}
+
+pub fn register_classify_sender(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("bayes_classify_sender", plugin_id, 4);
+}
+
+pub fn register_check_outbound(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("bayes_check_outbound", plugin_id, 3);
+}
+
 pub async fn exec_train(ctx: PluginContext<'_>) -> trc::Result<Variable> {
-    train(ctx, true).await
+    train(ctx, true, true, None, String::new()).await
+}
+
+// Trains a message submitted by a user or an import, which is not subject to the
+// sender cooldown of auto-training. The fourth argument identifies the import
+// batch the message belongs to, each message is its own batch when empty.
+pub async fn exec_train_manual(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let batch = ctx.arguments[3].to_string().trim().to_string();
+    train(ctx, true, false, None, batch).await
 }
 
 pub async fn exec_untrain(ctx: PluginContext<'_>) -> trc::Result<Variable> {
-    train(ctx, false).await
+    train(ctx, false, false, None, String::new()).await
 }
 
-async fn train(ctx: PluginContext<'_>, is_train: bool) -> trc::Result<Variable> {
+// Trains a user correction, identified by the address that submitted it and the
+// batch it was submitted with (fifth argument)
+pub async fn exec_correct(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let sender = ctx.arguments[3].to_string().trim().to_lowercase();
+    let batch = ctx.arguments[4].to_string().trim().to_string();
+    train(ctx, true, false, Some(sender), batch).await
+}
+
+pub async fn exec_train_split(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    train_split(ctx, true).await
+}
+
+pub async fn exec_untrain_split(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    train_split(ctx, false).await
+}
+
+// Routes the subject and body tokens to the separate models configured for the model
+async fn train_split(ctx: PluginContext<'_>, is_train: bool) -> trc::Result<Variable> {
+    let split = split_config(ctx.server, ctx.arguments[0].to_string().as_ref())?;
+    let mut trained = false;
+
+    for (model_id, text) in [(&split.subject_model, 1), (&split.body_model, 2)] {
+        // Messages without a subject only train the body model
+        if ctx.arguments[text].is_empty() {
+            continue;
+        }
+        let part_ctx = PluginContext {
+            session_id: ctx.session_id,
+            access_token: ctx.access_token,
+            server: ctx.server,
+            message: ctx.message,
+            modifications: &mut *ctx.modifications,
+            arguments: vec![
+                Variable::from(model_id.clone()),
+                ctx.arguments[text].clone(),
+                ctx.arguments[3].clone(),
+            ],
+        };
+        trained |= train(part_ctx, is_train, is_train, None, String::new())
+            .await?
+            .to_bool();
+    }
+
+    Ok(trained.into())
+}
+
+async fn train(
+    ctx: PluginContext<'_>,
+    is_train: bool,
+    is_auto: bool,
+    correction: Option<String>,
+    batch: String,
+) -> trc::Result<Variable> {
     let store = match &ctx.arguments[0] {
         Variable::String(v) if !v.is_empty() => ctx.server.core.storage.lookups.get(v.as_ref()),
         _ => Some(&ctx.server.core.storage.lookup),
@@ -51,8 +165,13 @@ async fn train(ctx: PluginContext<'_>, is_train: bool) -> trc::Result<Variable>
             .ctx(trc::Key::Id, ctx.arguments[0].to_string().into_owned())
             .details("Unknown store")
     })?;
+    let model_id = ctx.arguments[0].to_string();
 
-    let text = ctx.arguments[1].to_string();
+    let Some(text) = text_argument(&ctx, &ctx.arguments[1]) else {
+        trc::bail!(trc::SpamEvent::TrainError
+            .into_err()
+            .reason("Text too large"));
+    };
     let is_spam = ctx.arguments[2].to_bool();
     if text.is_empty() {
         trc::bail!(trc::SpamEvent::TrainError
@@ -60,10 +179,28 @@ async fn train(ctx: PluginContext<'_>, is_train: bool) -> trc::Result<Variable>
             .reason("Empty message"));
     }
 
-    // Train the model
+    // Obtain the settings the model was trained with
+    let metadata = model_metadata(ctx.server, model_id.as_ref(), store, is_train).await?;
+
+    // Train the model, hashing the token stream in order to identify the normalized text
     let mut model = BayesModel::default();
+    let mut text_hash = Xxh3::new();
     model.train(
-        OsbTokenizer::new(BayesTokenizer::new(text.as_ref()), 5),
+        OsbTokenizer::new(
+            BayesTokenizer::new(text.as_ref())
+                .with_case_folding(metadata.case_folding)
+                .with_normalization(&metadata.normalize),
+            5,
+        )
+        .chain(header_tokens(&ctx, &metadata).iter().map(|token| OsbToken {
+            inner: TokenHash::from(Gram::Uni { t1: token }),
+            idx: 0,
+        }))
+        .inspect(|token: &OsbToken<TokenHash>| {
+            text_hash.update(&token.inner.h1.to_be_bytes());
+            text_hash.update(&token.inner.h2.to_be_bytes());
+        })
+        .filter(|token| !metadata.is_pruned(&token.inner)),
         is_spam,
     );
     if model.weights.is_empty() {
@@ -71,63 +208,616 @@ async fn train(ctx: PluginContext<'_>, is_train: bool) -> trc::Result<Variable>
             .into_err()
             .reason("No weights found"));
     }
+    let text_hash = text_hash.digest128();
+    let trained_key = KeySerializer::new(TRAINED_PREFIX.len() + (U64_LEN * 2))
+        .write(TRAINED_PREFIX)
+        .write((text_hash >> 64) as u64)
+        .write(text_hash as u64)
+        .finalize();
+    let config = ctx.server.core.spam.bayes.model(model_id.as_ref());
+    let retry = &ctx.server.core.spam.bayes.retry;
+
+    // Only a sample of the trained messages updates the model, corrections are never skipped
+    if let (true, None, Some(sample)) = (is_train, &correction, &config.train_sample) {
+        let is_sampled = sample.is_sampled(text_hash);
+        with_retry(retry, || {
+            store.counter_incr(sample_key(is_sampled), 1, None, false)
+        })
+        .await
+        .caused_by(trc::location!())?;
+        if !is_sampled {
+            trc::event!(
+                Spam(trc::SpamEvent::Train),
+                SpanId = ctx.session_id,
+                Details = "Message not sampled for training, skipping",
+            );
+            return Ok(false.into());
+        }
+    }
+
+    let mut weight = config.train_weight;
+
+    if !is_train {
+        // Make sure the exact same normalized text was trained with the same class
+        let trained_class = with_retry(retry, || store.key_get::<String>(trained_key.clone()))
+            .await
+            .caused_by(trc::location!())?;
+        let (class, trained_weight) = trained_class
+            .as_deref()
+            .map(|class| match class.split_once(':') {
+                Some((class, weight)) => (class, weight.parse::<u32>().ok()),
+                None => (class, Some(1)),
+            })
+            .unwrap_or_default();
+        let is_match = match class {
+            "spam" => is_spam,
+            "ham" => !is_spam,
+            _ => false,
+        };
+        if let (true, Some(trained_weight)) = (is_match, trained_weight) {
+            weight = trained_weight;
+        }
+
+        // Untraining the other class corrupts the model, lenient mode only applies
+        // to texts without a training record
+        if !is_match && (config.untrain_strict || matches!(class, "spam" | "ham")) {
+            trc::bail!(trc::SpamEvent::TrainError
+                .into_err()
+                .details("Untrain refused")
+                .reason(if trained_class.is_some() {
+                    "Text was trained with a different class"
+                } else {
+                    "Text does not match any trained message"
+                }));
+        }
+    }
+
+    // Auto-training is skipped for senders trained on recently, manual training and
+    // corrections bypass the cooldown
+    if let (true, true, Some(cooldown)) = (is_train, is_auto, config.train_cooldown) {
+        if let Some(sender) = ctx
+            .message
+            .from()
+            .and_then(|from| from.first())
+            .and_then(|addr| addr.address())
+            .map(|addr| addr.trim().to_lowercase())
+            .filter(|addr| !addr.is_empty())
+        {
+            let cooldown_key = KeySerializer::new(COOLDOWN_PREFIX.len() + U64_LEN)
+                .write(COOLDOWN_PREFIX)
+                .write(xxhash_rust::xxh3::xxh3_64(sender.as_bytes()))
+                .finalize();
+            let now = ctx.server.now();
+            let trained_at = with_retry(retry, || {
+                store.key_get::<Bincode<u64>>(cooldown_key.clone())
+            })
+            .await
+            .caused_by(trc::location!())?
+            .map(|trained_at| trained_at.inner);
+            if trained_at.is_some_and(|trained_at| now < trained_at + cooldown.as_secs()) {
+                trc::event!(
+                    Spam(trc::SpamEvent::TrainError),
+                    SpanId = ctx.session_id,
+                    Details = "Sender training cooldown active, skipping auto-training",
+                    From = sender,
+                );
+                return Ok(false.into());
+            }
+
+            // The window starts with the first trained message and is not extended afterwards
+            with_retry(retry, || {
+                store.key_set(
+                    cooldown_key.clone(),
+                    Bincode::new(now).serialize(),
+                    cooldown.as_secs().into(),
+                )
+            })
+            .await
+            .caused_by(trc::location!())?;
+        }
+    }
+
+    // Training source recorded once per batch when provenance tracking is enabled,
+    // messages trained without a batch are recorded individually
+    let source = match &correction {
+        Some(sender) if !sender.is_empty() => format!("correction:{sender}"),
+        _ => "train".to_string(),
+    };
+    let batch_id = if !batch.is_empty() {
+        xxhash_rust::xxh3::xxh3_64(batch.as_bytes())
+    } else {
+        text_hash as u64
+    };
+
+    // Corrections are weighted more heavily, up to a limit per sender past which
+    // they are not trained
+    if let Some(sender) = correction.filter(|sender| !sender.is_empty()) {
+        let corrections = with_retry(retry, || {
+            store.counter_incr(
+                KeySerializer::new(CORRECTIONS_PREFIX.len() + U64_LEN)
+                    .write(CORRECTIONS_PREFIX)
+                    .write(xxhash_rust::xxh3::xxh3_64(sender.as_bytes()))
+                    .finalize(),
+                1,
+                config.correction_sender_window.as_secs().into(),
+                true,
+            )
+        })
+        .await
+        .caused_by(trc::location!())?;
+
+        if let Some(correction_weight) = correction_weight(config, corrections) {
+            weight = correction_weight;
+        } else {
+            trc::event!(
+                Spam(trc::SpamEvent::TrainError),
+                SpanId = ctx.session_id,
+                Details = "Correction limit exceeded, skipping",
+                From = sender,
+                Total = corrections,
+            );
+            return Ok(false.into());
+        }
+    }
 
     trc::event!(
-        Spam(trc::SpamEvent::Train),
+        Spam(if is_train {
+            trc::SpamEvent::Train
+        } else {
+            trc::SpamEvent::Untrain
+        }),
         SpanId = ctx.session_id,
         Details = is_spam,
         Total = model.weights.len(),
+        Size = weight,
     );
 
-    // Update weight and invalidate cache
+    // Update weight and invalidate cache, in write-behind mode the updates are
+    // kept in memory until the next flush
     let bayes_cache = &ctx.server.inner.data.bayes_cache;
-    if is_train {
-        for (hash, weights) in model.weights {
-            store
-                .counter_incr(
-                    KeySerializer::new(U64_LEN)
-                        .write(hash.h1)
-                        .write(hash.h2)
-                        .finalize(),
-                    weights.into(),
-                    None,
-                    false,
-                )
-                .await
-                .caused_by(trc::location!())?;
+    let model_seed = model_seed(model_id.as_ref());
+    let write_behind = ctx.server.core.spam.bayes.write_behind.is_some();
+    let mut trained_hashes = Vec::new();
+    let mut pending = Vec::new();
+    let mut sources = Vec::new();
+    for (hash, weights) in model.weights {
+        // A weight above one is equivalent to training the text multiple times
+        let weights = i64::from(Weights {
+            spam: weights.spam * weight,
+            ham: weights.ham * weight,
+        });
+        let delta = if is_train { weights } else { -weights };
+        if write_behind {
+            pending.push((hash, delta));
+        } else {
+            with_retry(retry, || {
+                store.counter_incr(token_key(&hash), delta, None, false)
+            })
+            .await
+            .caused_by(trc::location!())?;
+            bayes_cache.invalidate(&hash.for_model(model_seed));
+        }
 
-            bayes_cache.invalidate(&hash);
+        if is_train && config.provenance_expiry.is_some() {
+            sources.push((
+                source_key(&hash, batch_id),
+                Bincode::new(batch_id).serialize(),
+            ));
         }
 
-        // Update training counts
-        let weights = if is_spam {
-            Weights { spam: 1, ham: 0 }
-        } else {
-            Weights { spam: 0, ham: 1 }
-        };
-        store
-            .counter_incr(
-                KeySerializer::new(U64_LEN)
-                    .write(0u64)
-                    .write(0u64)
-                    .finalize(),
-                weights.into(),
+        trained_hashes.push(hash);
+    }
+
+    // Record the training source, tokens keep up to PROVENANCE_SLOTS of the batches
+    // that trained them
+    if let (true, Some(expiry)) = (is_train, config.provenance_expiry) {
+        let provenance = Bincode::new(TrainingSource {
+            source: source.clone(),
+            batch: batch.clone(),
+            spam: is_spam,
+            trained_at: ctx.server.now(),
+        })
+        .serialize();
+        with_retry(retry, || {
+            store.key_set(
+                provenance_key(batch_id),
+                provenance.clone(),
+                expiry.as_secs().into(),
+            )
+        })
+        .await
+        .caused_by(trc::location!())?;
+        with_retry(retry, || {
+            store.key_set_many(sources.clone(), expiry.as_secs().into())
+        })
+        .await
+        .caused_by(trc::location!())?;
+    }
+
+    // Update training counts
+    let weights = i64::from(if is_spam {
+        Weights {
+            spam: weight,
+            ham: 0,
+        }
+    } else {
+        Weights {
+            spam: 0,
+            ham: weight,
+        }
+    });
+    let delta = if is_train { weights } else { -weights };
+    let is_flush = if write_behind {
+        pending.push((TokenHash::default(), delta));
+        ctx.server.bayes_pending_add(model_id.as_ref(), pending)
+    } else {
+        with_retry(retry, || {
+            store.counter_incr(token_key(&TokenHash::default()), delta, None, false)
+        })
+        .await
+        .caused_by(trc::location!())?;
+        bayes_cache.invalidate(&TokenHash::default().for_model(model_seed));
+        false
+    };
+    trained_hashes.push(TokenHash::default());
+
+    // Record the changed tokens for incremental backups
+    if let Some(expiry) = config.change_log_expiry {
+        let change_key = KeySerializer::new(CHANGES_PREFIX.len() + (U64_LEN * 2))
+            .write(CHANGES_PREFIX)
+            .write(ctx.server.now())
+            .write(
+                ctx.server
+                    .inner
+                    .data
+                    .span_id_gen
+                    .generate()
+                    .unwrap_or_default(),
+            )
+            .finalize();
+        with_retry(retry, || {
+            store.key_set(
+                change_key.clone(),
+                Bincode::new(trained_hashes.clone()).serialize(),
+                expiry.as_secs().into(),
+            )
+        })
+        .await
+        .caused_by(trc::location!())?;
+    }
+
+    // Keep track of the tokens that might not have reached the read replica yet,
+    // pending updates are tracked once flushed
+    if !write_behind {
+        ctx.server
+            .bayes_track_replica(model_id.as_ref(), trained_hashes);
+    }
+
+    // Record or remove the hash of the trained text
+    if is_train {
+        with_retry(retry, || {
+            store.key_set(
+                trained_key.clone(),
+                match (is_spam, weight) {
+                    (true, 1) => b"spam".to_vec(),
+                    (false, 1) => b"ham".to_vec(),
+                    (true, weight) => format!("spam:{weight}").into_bytes(),
+                    (false, weight) => format!("ham:{weight}").into_bytes(),
+                },
+                config.trained_hash_expiry.as_secs().into(),
+            )
+        })
+        .await
+        .caused_by(trc::location!())?;
+        with_retry(retry, || {
+            store.key_set(
+                LAST_TRAINED_KEY.to_vec(),
+                Bincode::new(ctx.server.now()).serialize(),
                 None,
-                false,
             )
+        })
+        .await
+        .caused_by(trc::location!())?;
+    } else {
+        with_retry(retry, || store.key_delete(trained_key.clone()))
             .await
             .caused_by(trc::location!())?;
-    } else {
-        //TODO: Implement untrain
-        return Ok(false.into());
     }
 
-    bayes_cache.invalidate(&TokenHash::default());
+    // The model was updated regardless of the outcome of the flush
+    if is_flush {
+        if let Err(err) = ctx.server.bayes_flush().await {
+            trc::error!(err
+                .span_id(ctx.session_id)
+                .details("Failed to flush pending training updates"));
+        }
+    }
 
     Ok(true.into())
 }
 
 pub async fn exec_classify(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    classify(&ctx)
+        .await
+        .map(|result| result.map(Variable::from).unwrap_or_default())
+}
+
+// Returns an array containing the combined score followed by the subject and body scores
+pub async fn exec_classify_split(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let split = split_config(ctx.server, ctx.arguments[0].to_string().as_ref())?;
+    let mut scores = [None, None];
+
+    for (score, (model_id, text)) in scores
+        .iter_mut()
+        .zip([(&split.subject_model, 1), (&split.body_model, 2)])
+    {
+        if ctx.arguments[text].is_empty() {
+            continue;
+        }
+        *score = classify(&PluginContext {
+            session_id: ctx.session_id,
+            access_token: ctx.access_token,
+            server: ctx.server,
+            message: ctx.message,
+            modifications: &mut *ctx.modifications,
+            arguments: vec![
+                Variable::from(model_id.clone()),
+                ctx.arguments[text].clone(),
+                ctx.arguments[3].clone(),
+            ],
+        })
+        .await?;
+    }
+
+    let [subject, body] = scores;
+    let combined = match (subject, body) {
+        (Some(subject), Some(body)) => {
+            Some(subject * split.subject_weight + body * (1.0 - split.subject_weight))
+        }
+        (subject, body) => subject.or(body),
+    };
+
+    Ok(Variable::Array(
+        [combined, subject, body]
+            .into_iter()
+            .map(|score| score.map(Variable::from).unwrap_or_default())
+            .collect::<Vec<_>>()
+            .into(),
+    ))
+}
+
+// Classifies the text as bayes_classify does, also setting the following variables
+// so scripts obtain all the diagnostics from a single call:
+//
+// - t.bayes_score: the calibrated score, empty when no verdict was reached.
+// - t.bayes_label: "spam", "ham" or "unsure" according to the model label
+//   thresholds, "untrained" when the model has no training data and empty when
+//   no verdict was reached.
+// - t.bayes_coverage: the fraction of the message tokens known to the model.
+// - t.bayes_tokens: the text of the tokens with the most extreme spam to ham
+//   ratio, strongest first.
+// - t.bayes_sources: for each of these tokens, the sources of the recorded batches
+//   that trained it, most recent first, such as "train" or "correction:<address>".
+//   Empty unless provenance is enabled.
+//
+// Returns the score, so existing scripts can switch to it without changes.
+pub async fn exec_classify_vars(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let config = ctx
+        .server
+        .core
+        .spam
+        .bayes
+        .model(ctx.arguments[0].to_string().as_ref());
+    let outcome = classify_outcome(&ctx, config.top_tokens).await?;
+    let score = outcome.score.map(Variable::from).unwrap_or_default();
+    let label = match outcome.score {
+        _ if outcome.is_empty => "untrained",
+        Some(score) if score > config.label_spam => "spam",
+        Some(score) if score < config.label_ham => "ham",
+        Some(_) => "unsure",
+        None => "",
+    };
+    let coverage = if outcome.total_tokens > 0 {
+        outcome.known_tokens as f64 / outcome.total_tokens as f64
+    } else {
+        0.0
+    };
+
+    for (name, value) in [
+        ("bayes_score", score.clone()),
+        ("bayes_label", Variable::from(label)),
+        ("bayes_coverage", Variable::from(coverage)),
+        (
+            "bayes_sources",
+            Variable::Array(
+                outcome
+                    .tokens
+                    .iter()
+                    .map(|token| {
+                        Variable::Array(
+                            token
+                                .sources
+                                .iter()
+                                .map(|source| Variable::from(source.source.clone()))
+                                .collect::<Vec<_>>()
+                                .into(),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+        ),
+        (
+            "bayes_tokens",
+            Variable::Array(
+                outcome
+                    .tokens
+                    .into_iter()
+                    .map(|token| Variable::from(token.text))
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+        ),
+    ] {
+        ctx.modifications.push(ScriptModification::SetVariable {
+            name: name.to_string(),
+            value,
+        });
+    }
+
+    Ok(score)
+}
+
+// Classifies the text as bayes_classify does, limiting the number of classifications
+// per sender (fourth argument) to the model sender rate. Once the rate is exceeded,
+// the most spammy verdict obtained for the sender during the rate period is
+// returned, or no verdict if there is none.
+pub async fn exec_classify_sender(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let model_id = ctx.arguments[0].to_string();
+    let sender = ctx.arguments[3].to_string().trim().to_lowercase();
+    let Some(rate) = ctx
+        .server
+        .core
+        .spam
+        .bayes
+        .model(model_id.as_ref())
+        .sender_rate
+        .as_ref()
+        .filter(|_| !sender.is_empty())
+    else {
+        return exec_classify(ctx).await;
+    };
+
+    let sender_cache = &ctx.server.inner.data.bayes_sender_cache;
+    let cache_key = xxhash_rust::xxh3::xxh3_128(format!("{model_id}:{sender}").as_bytes());
+    if ctx
+        .server
+        .lookup_store()
+        .is_rate_allowed(format!("bc:{model_id}:{sender}").as_bytes(), rate, false)
+        .await
+        .caused_by(trc::location!())?
+        .is_some()
+    {
+        let score = sender_cache.get_with_ttl(&cache_key);
+
+        trc::event!(
+            Spam(trc::SpamEvent::ClassifyThrottled),
+            SpanId = ctx.session_id,
+            Id = model_id.to_string(),
+            From = sender,
+            Result = score,
+        );
+
+        return Ok(score.map(Variable::from).unwrap_or_default());
+    }
+
+    let score = classify(&ctx).await?;
+    if let Some(score) = score {
+        // Senders are not able to improve the cached verdict by alternating messages
+        let cached = sender_cache
+            .get_with_ttl(&cache_key)
+            .map_or(score, |cached| cached.max(score));
+        sender_cache.insert_with_ttl(cache_key, cached, Instant::now() + rate.period);
+    }
+
+    Ok(score.map(Variable::from).unwrap_or_default())
+}
+
+// Classifies a message sent by an authenticated user with the outbound model,
+// arguments are the text, the classification parameters and the sender address.
+// Returns the score when it exceeds the outbound threshold, or empty if it does not
+// or the domain of the sender did not opt in.
+pub async fn exec_check_outbound(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let sender = ctx.arguments[2].to_string().trim().to_lowercase();
+    let Some(outbound) = ctx
+        .server
+        .core
+        .spam
+        .bayes
+        .outbound
+        .as_ref()
+        .filter(|outbound| {
+            sender
+                .rsplit_once('@')
+                .is_some_and(|(_, domain)| outbound.domains.contains(domain))
+        })
+    else {
+        return Ok(Variable::default());
+    };
+
+    let score = classify(&PluginContext {
+        session_id: ctx.session_id,
+        access_token: ctx.access_token,
+        server: ctx.server,
+        message: ctx.message,
+        modifications: ctx.modifications,
+        arguments: vec![
+            Variable::from(outbound.model.clone()),
+            ctx.arguments[0].clone(),
+            ctx.arguments[1].clone(),
+        ],
+    })
+    .await?
+    .filter(|score| *score > outbound.threshold);
+
+    if let Some(score) = score {
+        trc::event!(
+            Spam(trc::SpamEvent::OutboundSpam),
+            SpanId = ctx.session_id,
+            Id = outbound.model.clone(),
+            From = sender,
+            Result = score,
+        );
+    }
+
+    Ok(score.map(Variable::from).unwrap_or_default())
+}
+
+fn split_config<'x>(server: &'x Server, model_id: &str) -> trc::Result<&'x BayesSplitConfig> {
+    server
+        .core
+        .spam
+        .bayes
+        .model(model_id)
+        .split
+        .as_ref()
+        .ok_or_else(|| {
+            trc::SieveEvent::RuntimeError
+                .ctx(trc::Key::Id, model_id.to_string())
+                .details("Model has no separate subject and body models")
+        })
+}
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ClassifyOutcome {
+    pub score: Option<f64>,
+    pub total_tokens: usize,
+    pub known_tokens: usize,
+    pub tokens: Vec<OutcomeToken>,
+    // Whether the model was never trained, as opposed to not having enough training data
+    pub is_empty: bool,
+}
+
+// Token with the most extreme spam to ham ratio of a classified message
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct OutcomeToken {
+    pub text: String,
+    pub spam: u32,
+    pub ham: u32,
+    pub probability: f64,
+    pub sources: Vec<TrainingSource>,
+}
+
+pub(crate) async fn classify(ctx: &PluginContext<'_>) -> trc::Result<Option<f64>> {
+    classify_outcome(ctx, 0).await.map(|outcome| outcome.score)
+}
+
+// Classifies the text, also returning how many of its tokens are known to the model
+// and the text of up to top_tokens of its most extreme tokens.
+pub(crate) async fn classify_outcome(
+    ctx: &PluginContext<'_>,
+    top_tokens: usize,
+) -> trc::Result<ClassifyOutcome> {
     let store = match &ctx.arguments[0] {
         Variable::String(v) if !v.is_empty() => ctx.server.core.storage.lookups.get(v.as_ref()),
         _ => Some(&ctx.server.core.storage.lookup),
@@ -137,15 +827,19 @@ pub async fn exec_classify(ctx: PluginContext<'_>) -> trc::Result<Variable> {
             .ctx(trc::Key::Id, ctx.arguments[0].to_string().into_owned())
             .details("Unknown store")
     })?;
-    let text = ctx.arguments[1].to_string();
+    let model_id = ctx.arguments[0].to_string();
+    let Some(text) = text_argument(ctx, &ctx.arguments[1]) else {
+        return Ok(ClassifyOutcome::default());
+    };
     if text.is_empty() {
         trc::bail!(trc::SpamEvent::ClassifyError
             .into_err()
             .reason("Empty message"));
     }
 
-    // Create classifier from defaults
-    let mut classifier = BayesClassifier::default();
+    // Create classifier from the model thresholds, overridden by the script parameters
+    let config = ctx.server.core.spam.bayes.model(model_id.as_ref());
+    let mut classifier = config.classifier();
     if let Some(params) = ctx.arguments[2].as_array() {
         if let Some(Variable::Integer(value)) = params.first() {
             classifier.min_token_hits = *value as u32;
@@ -161,12 +855,81 @@ pub async fn exec_classify(ctx: PluginContext<'_>) -> trc::Result<Variable> {
         }
     }
 
+    // Token weights are read from the replica, if configured
+    let replica = config
+        .replica
+        .as_ref()
+        .map(|id| {
+            ctx.server.core.storage.lookups.get(id).ok_or_else(|| {
+                trc::SpamEvent::ClassifyError
+                    .ctx(trc::Key::Id, id.to_string())
+                    .details("Unknown replica store")
+            })
+        })
+        .transpose()?;
+    let model_seed = model_seed(model_id.as_ref());
+    let weights_store = |hash: &TokenHash| match (replica, config.replica_max_lag) {
+        (Some(replica), Some(_)) => {
+            // Recently trained tokens are read from the primary to avoid stale weights
+            if ctx
+                .server
+                .inner
+                .data
+                .bayes_trained
+                .get_with_ttl(&hash.for_model(model_seed))
+                .is_some()
+            {
+                store
+            } else {
+                replica
+            }
+        }
+        (Some(replica), None) => replica,
+        (None, _) => store,
+    };
+
     // Obtain training counts
     let bayes_cache = &ctx.server.inner.data.bayes_cache;
-    let (spam_learns, ham_learns) = bayes_cache
-        .get_or_update(TokenHash::default(), store)
-        .await
-        .map(|w| (w.spam, w.ham))?;
+    let counts_store = weights_store(&TokenHash::default());
+    let retry = &ctx.server.core.spam.bayes.retry;
+    let counts_read = ctx.server.core.spam.bayes.counts_read;
+    let mut counts = if counts_read != BayesCountsRead::Direct {
+        bayes_cache
+            .get_or_update(TokenHash::default(), model_seed, counts_store, retry)
+            .await?
+    } else {
+        bayes_cache
+            .fetch_and_insert(TokenHash::default(), model_seed, counts_store, retry)
+            .await?
+    };
+
+    // The cached counts row might predate a concurrent training update
+    if counts_read == BayesCountsRead::Verify
+        && (counts.spam < classifier.min_learns || counts.ham < classifier.min_learns)
+    {
+        counts = bayes_cache
+            .fetch_and_insert(TokenHash::default(), model_seed, counts_store, retry)
+            .await?;
+    }
+    let counts = ctx
+        .server
+        .bayes_pending_apply(model_id.as_ref(), &TokenHash::default(), counts);
+    let (spam_learns, ham_learns) = (counts.spam, counts.ham);
+
+    // Models without a counts row were never trained, which is logged separately
+    // so that a misconfigured or new model is easy to tell apart
+    if spam_learns == 0 && ham_learns == 0 {
+        trc::event!(
+            Spam(trc::SpamEvent::ModelEmpty),
+            SpanId = ctx.session_id,
+            Id = model_id.to_string(),
+        );
+        return Ok(ClassifyOutcome {
+            score: config.empty_score,
+            is_empty: true,
+            ..Default::default()
+        });
+    }
 
     // Make sure we have enough training data
     if spam_learns < classifier.min_learns || ham_learns < classifier.min_learns {
@@ -179,19 +942,103 @@ pub async fn exec_classify(ctx: PluginContext<'_>) -> trc::Result<Variable> {
                 trc::Value::from(classifier.min_learns)
             ],
         );
-        return Ok(Variable::default());
+        return Ok(ClassifyOutcome::default());
     }
 
+    // Obtain the settings the model was trained with
+    let metadata = model_metadata(ctx.server, model_id.as_ref(), store, false).await?;
+
     // Classify the text
+    let time = Instant::now();
     let mut tokens = Vec::new();
-    for token in OsbTokenizer::<_, TokenHash>::new(BayesTokenizer::new(text.as_ref()), 5) {
-        let weights = bayes_cache.get_or_update(token.inner, store).await?;
+    let mut cache_hits = 0;
+    let mut cache_misses = 0;
+    let mut known_tokens = 0;
+    let mut matched_tokens = 0;
+    let is_live = ctx.server.has_bayes_live_subscribers();
+    let mut live_tokens = Vec::new();
+    let collect_tokens = is_live || top_tokens > 0;
+    let header_tokens = header_tokens(ctx, &metadata);
+    for token in OsbTokenizer::<_, TokenHash>::new(
+        BayesTokenizer::new(text.as_ref())
+            .with_case_folding(metadata.case_folding)
+            .with_normalization(&metadata.normalize),
+        5,
+    )
+    .chain(header_tokens.iter().map(|token| OsbToken {
+        inner: TokenHash::from(Gram::Uni { t1: token }),
+        idx: 0,
+    }))
+    .filter(|token| !metadata.is_pruned(&token.inner))
+    {
+        let weights = if let Some(weights) = bayes_cache.get(&token.inner.for_model(model_seed)) {
+            cache_hits += 1;
+            weights.unwrap_or_default()
+        } else {
+            cache_misses += 1;
+            bayes_cache
+                .fetch_and_insert(
+                    token.inner,
+                    model_seed,
+                    weights_store(&token.inner),
+                    &ctx.server.core.spam.bayes.retry,
+                )
+                .await?
+        };
+        let weights = ctx
+            .server
+            .bayes_pending_apply(model_id.as_ref(), &token.inner, weights);
+        if weights.spam + weights.ham > 0 {
+            known_tokens += 1;
+        }
+        if classifier.is_match(&weights) {
+            matched_tokens += 1;
+        }
+        if collect_tokens {
+            live_tokens.push((token.inner, weights));
+        }
         tokens.push(OsbToken {
             inner: weights,
             idx: token.idx,
         });
     }
-    let result = classifier.classify(tokens.into_iter(), ham_learns, spam_learns);
+    let total_tokens = tokens.len();
+
+    // Verdicts based on a handful of matched tokens are unreliable, tokens seen fewer
+    // times than the classifier minimum are not taken into account
+    let result = if matched_tokens >= config.min_matched_tokens as usize {
+        classifier.classify(tokens.into_iter(), ham_learns, spam_learns)
+    } else {
+        trc::event!(
+            Spam(trc::SpamEvent::NotEnoughTrainingData),
+            SpanId = ctx.session_id,
+            Reason = "Not enough matched tokens",
+            Details = vec![
+                trc::Value::from(matched_tokens),
+                trc::Value::from(config.min_matched_tokens)
+            ],
+        );
+        None
+    };
+    let elapsed = time.elapsed();
+
+    // Update cache metrics
+    Collector::update_event_counter(
+        trc::EventType::Spam(trc::SpamEvent::ClassifyCacheHit),
+        cache_hits,
+    );
+    Collector::update_event_counter(
+        trc::EventType::Spam(trc::SpamEvent::ClassifyCacheMiss),
+        cache_misses,
+    );
+    Collector::update_histogram(
+        if cache_misses == 0 {
+            trc::MetricType::SpamClassifyLatencyHit
+        } else {
+            trc::MetricType::SpamClassifyLatencyMiss
+        },
+        elapsed.as_micros() as u64,
+    );
 
     trc::event!(
         Spam(trc::SpamEvent::Classify),
@@ -201,10 +1048,194 @@ pub async fn exec_classify(ctx: PluginContext<'_>) -> trc::Result<Variable> {
             trc::Value::from(ham_learns),
             trc::Value::from(classifier.min_learns)
         ],
-        Result = result.unwrap_or_default()
+        Result = result.unwrap_or_default(),
+        Elapsed = elapsed,
     );
 
-    Ok(result.map(Variable::from).unwrap_or_default())
+    // Raw scores are logged above so they can be used to fit the calibration
+    let score = result.map(|score| {
+        metadata
+            .calibration
+            .as_ref()
+            .map_or(score, |calibration| calibration.calibrate(score))
+    });
+
+    let mut tokens = if collect_tokens {
+        TokenDiagnostics::most_extreme(
+            live_tokens.iter().copied(),
+            spam_learns,
+            ham_learns,
+            MAX_TOKENS.max(top_tokens),
+        )
+    } else {
+        Vec::new()
+    };
+    let top_tokens = if score.is_some() { top_tokens } else { 0 };
+
+    // Trace the most extreme tokens back to the batches that trained them
+    if config.provenance_expiry.is_some() {
+        let max_sources = if is_live {
+            MAX_TOKENS.max(top_tokens)
+        } else {
+            top_tokens
+        };
+        let mut batches: AHashMap<u64, Option<TrainingSource>> = AHashMap::new();
+        for token in tokens.iter_mut().take(max_sources) {
+            let hash = TokenHash {
+                h1: token.h1,
+                h2: token.h2,
+            };
+            for slot in 0..PROVENANCE_SLOTS {
+                let Some(batch_id) = store
+                    .key_get::<Bincode<u64>>(source_key(&hash, slot))
+                    .await
+                    .ok()
+                    .flatten()
+                    .map(|batch_id| batch_id.inner)
+                else {
+                    continue;
+                };
+                if !batches.contains_key(&batch_id) {
+                    let source = store
+                        .key_get::<Bincode<TrainingSource>>(provenance_key(batch_id))
+                        .await
+                        .ok()
+                        .flatten()
+                        .map(|source| source.inner);
+                    batches.insert(batch_id, source);
+                }
+                if let Some(source) = batches.get(&batch_id).and_then(|source| source.as_ref()) {
+                    token.sources.push(source.clone());
+                }
+            }
+            token
+                .sources
+                .sort_by_key(|source| std::cmp::Reverse(source.trained_at));
+        }
+    }
+
+    let top_tokens = if top_tokens > 0 {
+        token_texts(
+            text.as_ref(),
+            &header_tokens,
+            &metadata,
+            &tokens[..top_tokens.min(tokens.len())],
+        )
+        .into_iter()
+        .map(|(token, text)| OutcomeToken {
+            text,
+            spam: token.spam,
+            ham: token.ham,
+            probability: token.probability(spam_learns, ham_learns),
+            sources: token.sources.clone(),
+        })
+        .collect()
+    } else {
+        Vec::new()
+    };
+
+    if is_live {
+        // Reporter addresses are only disclosed along with the message contents
+        let live_content = ctx.server.core.spam.bayes.live_content;
+        tokens.truncate(MAX_TOKENS);
+        if !live_content {
+            for source in tokens.iter_mut().flat_map(|token| token.sources.iter_mut()) {
+                source.redact();
+            }
+        }
+        ctx.server.publish_bayes_live(ClassifyDiagnostics {
+            model_id: model_id.to_string(),
+            session_id: ctx.session_id,
+            score,
+            raw_score: result,
+            spam_learns,
+            ham_learns,
+            total_tokens: live_tokens.len(),
+            cache_hits,
+            cache_misses,
+            elapsed_us: elapsed.as_micros() as u64,
+            sources: SourceContribution::from_tokens(&tokens),
+            tokens,
+            text: live_content.then(|| text.to_string()),
+        });
+    }
+
+    Ok(ClassifyOutcome {
+        score,
+        total_tokens,
+        known_tokens,
+        tokens: top_tokens,
+        is_empty: false,
+    })
+}
+
+// Feature tokens extracted from the headers of the message, these are added to
+// the text tokens as they are
+fn header_tokens(ctx: &PluginContext<'_>, metadata: &BayesMetadata) -> Vec<String> {
+    HeaderFeature::tokens(
+        &metadata.headers,
+        ctx.message.headers_raw(),
+        &ctx.server.core.spam.bayes.ignore_authserv_ids,
+    )
+}
+
+// Token hashes cannot be reversed, so the text of the tokens is recovered by
+// tokenizing the text again
+fn token_texts<'x>(
+    text: &str,
+    header_tokens: &[String],
+    metadata: &BayesMetadata,
+    tokens: &'x [TokenDiagnostics],
+) -> Vec<(&'x TokenDiagnostics, String)> {
+    let mut texts = vec![None; tokens.len()];
+    let mut remaining = tokens.len();
+    for token in OsbTokenizer::<_, TokenText>::new(
+        BayesTokenizer::new(text)
+            .with_case_folding(metadata.case_folding)
+            .with_normalization(&metadata.normalize),
+        5,
+    )
+    .chain(header_tokens.iter().map(|token| OsbToken {
+        inner: TokenText::from(Gram::Uni { t1: token }),
+        idx: 0,
+    })) {
+        if let Some(pos) = tokens
+            .iter()
+            .position(|t| t.h1 == token.inner.hash.h1 && t.h2 == token.inner.hash.h2)
+        {
+            if texts[pos].is_none() {
+                texts[pos] = Some(token.inner.text);
+                remaining -= 1;
+                if remaining == 0 {
+                    break;
+                }
+            }
+        }
+    }
+
+    tokens
+        .iter()
+        .zip(texts)
+        .filter_map(|(token, text)| text.map(|text| (token, text)))
+        .collect()
+}
+
+struct TokenText {
+    hash: TokenHash,
+    text: String,
+}
+
+impl From<Gram<'_>> for TokenText {
+    fn from(gram: Gram<'_>) -> Self {
+        let text = match &gram {
+            Gram::Uni { t1 } => t1.to_string(),
+            Gram::Bi { t1, t2 } => format!("{t1} {t2}"),
+        };
+        TokenText {
+            hash: TokenHash::from(gram),
+            text,
+        }
+    }
 }
 
 pub async fn exec_is_balanced(ctx: PluginContext<'_>) -> trc::Result<Variable> {
@@ -229,13 +1260,22 @@ pub async fn exec_is_balanced(ctx: PluginContext<'_>) -> trc::Result<Variable> {
     })?;
 
     let learn_spam = ctx.arguments[1].to_bool();
+    let model_id = ctx.arguments[0].to_string();
 
     // Obtain training counts
     let bayes_cache = &ctx.server.inner.data.bayes_cache;
-    let (spam_learns, ham_learns) = bayes_cache
-        .get_or_update(TokenHash::default(), store)
-        .await
-        .map(|w| (w.spam as f64, w.ham as f64))?;
+    let counts = bayes_cache
+        .get_or_update(
+            TokenHash::default(),
+            model_seed(model_id.as_ref()),
+            store,
+            &ctx.server.core.spam.bayes.retry,
+        )
+        .await?;
+    let counts = ctx
+        .server
+        .bayes_pending_apply(model_id.as_ref(), &TokenHash::default(), counts);
+    let (spam_learns, ham_learns) = (counts.spam as f64, counts.ham as f64);
 
     let result = if spam_learns > 0.0 || ham_learns > 0.0 {
         if learn_spam {
@@ -262,37 +1302,628 @@ pub async fn exec_is_balanced(ctx: PluginContext<'_>) -> trc::Result<Variable> {
     Ok(result.into())
 }
 
+// Returns the cached divergence of the first model from the second, see
+// Server::bayes_divergence
+pub async fn exec_divergence(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    Ok(ctx
+        .server
+        .bayes_divergence(
+            ctx.arguments[0].to_string().as_ref(),
+            ctx.arguments[1].to_string().as_ref(),
+        )
+        .await?
+        .map(Variable::from)
+        .unwrap_or_default())
+}
+
+// Returns the keys of all token weights in a model, including the training counts
+pub(crate) async fn token_keys(store: &LookupStore) -> trc::Result<Vec<Vec<u8>>> {
+    token_keys_sample(store, usize::MAX).await
+}
+
+// Returns the keys of up to max_keys token weights, excluding the training counts.
+// Keys are ordered by hash, so the first keys are a random sample of the model.
+pub(crate) async fn token_keys_sample(
+    store: &LookupStore,
+    max_keys: usize,
+) -> trc::Result<Vec<Vec<u8>>> {
+    let LookupStore::Store(token_store) = store else {
+        trc::bail!(trc::StoreEvent::NotSupported
+            .into_err()
+            .details("Iterating Bayes tokens requires a data store"));
+    };
+    let mut keys = Vec::new();
+    token_store
+        .iterate(
+            IterateParams::new(
+                ValueKey::from(ValueClass::Lookup(LookupClass::Counter(vec![0u8]))),
+                ValueKey::from(ValueClass::Lookup(LookupClass::Counter(vec![
+                    u8::MAX;
+                    U64_LEN * 2
+                ]))),
+            )
+            .no_values(),
+            |key, _| {
+                if is_token_key(key) && (max_keys == usize::MAX || key.iter().any(|b| *b != 0)) {
+                    keys.push(key.to_vec());
+                }
+                Ok(keys.len() < max_keys)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+    Ok(keys)
+}
+
+// Prefixes of the counters written to lookup stores by other plugins and by Bayes
+// itself, which are never token weights
+const COUNTER_PREFIXES: &[&[u8]] = &[b"bayes:", super::cluster::CLUSTER_PREFIX];
+
+// Token weights are stored under 128-bit keys, other counters sharing the store are
+// identified by their prefix
+fn is_token_key(key: &[u8]) -> bool {
+    key.len() == U64_LEN * 2
+        && !COUNTER_PREFIXES
+            .iter()
+            .any(|prefix| key.starts_with(prefix))
+}
+
+pub(crate) const METADATA_KEY: &[u8] = b"bayes:metadata";
+pub(crate) const LAST_TRAINED_KEY: &[u8] = b"bayes:last-trained";
+const TRAINED_PREFIX: &[u8] = b"bayes:trained:";
+pub(crate) const CHANGES_PREFIX: &[u8] = b"bayes:changes:";
+const CORRECTIONS_PREFIX: &[u8] = b"bayes:corrections:";
+const PROVENANCE_PREFIX: &[u8] = b"bayes:provenance:";
+const SOURCES_PREFIX: &[u8] = b"bayes:sources:";
+const PROVENANCE_SLOTS: u64 = 4;
+const COOLDOWN_PREFIX: &[u8] = b"bayes:cooldown:";
+const SAMPLE_PREFIX: &[u8] = b"bayes:sample:";
+
+pub(crate) fn token_key(hash: &TokenHash) -> Vec<u8> {
+    KeySerializer::new(U64_LEN * 2)
+        .write(hash.h1)
+        .write(hash.h2)
+        .finalize()
+}
+
+// Counters of the messages selected and skipped by training sampling
+pub(crate) fn sample_key(is_sampled: bool) -> Vec<u8> {
+    KeySerializer::new(SAMPLE_PREFIX.len() + 1)
+        .write(SAMPLE_PREFIX)
+        .write(is_sampled as u8)
+        .finalize()
+}
+
+pub(crate) fn model_seed(model_id: &str) -> u64 {
+    if !model_id.is_empty() {
+        xxhash_rust::xxh3::xxh3_64(model_id.as_bytes())
+    } else {
+        0
+    }
+}
+
+// Weight of a correction given the number of corrections submitted by the sender
+// within the window, corrections past the limit are not trained
+fn correction_weight(config: &BayesModelConfig, corrections: i64) -> Option<u32> {
+    (corrections <= config.correction_sender_limit as i64).then_some(config.correction_weight)
+}
+
+// Training source of a batch
+fn provenance_key(batch_id: u64) -> Vec<u8> {
+    KeySerializer::new(PROVENANCE_PREFIX.len() + U64_LEN)
+        .write(PROVENANCE_PREFIX)
+        .write(batch_id)
+        .finalize()
+}
+
+// Batch that trained a token, batches share a slot when their ids are congruent
+// modulo PROVENANCE_SLOTS, in which case the most recent one is kept
+fn source_key(hash: &TokenHash, batch_id: u64) -> Vec<u8> {
+    KeySerializer::new(SOURCES_PREFIX.len() + (U64_LEN * 2) + 1)
+        .write(SOURCES_PREFIX)
+        .write(hash.h1)
+        .write(hash.h2)
+        .write((batch_id % PROVENANCE_SLOTS) as u8)
+        .finalize()
+}
+
+// Returns the settings a model was trained with. When training a model without
+// metadata, the configured settings are recorded unless the model already contains
+// training data, in which case it was trained with the default settings.
+pub(crate) async fn model_metadata(
+    server: &Server,
+    model_id: &str,
+    store: &LookupStore,
+    record: bool,
+) -> trc::Result<Arc<BayesMetadata>> {
+    // Tokens are only cached after reading the model metadata, warm sets are
+    // fingerprinted with the metadata of every model read since startup
+    if !server
+        .inner
+        .data
+        .bayes_cached_models
+        .read()
+        .contains(model_id)
+    {
+        server
+            .inner
+            .data
+            .bayes_cached_models
+            .write()
+            .insert(model_id.to_string());
+    }
+
+    if let Some(metadata) = server.inner.data.bayes_metadata.get_with_ttl(model_id) {
+        return Ok(metadata);
+    }
+
+    let metadata = if let Some(metadata) = with_retry(&server.core.spam.bayes.retry, || {
+        store.key_get::<String>(METADATA_KEY.to_vec())
+    })
+    .await
+    .caused_by(trc::location!())?
+    {
+        serde_json::from_str::<BayesMetadata>(&metadata).map_err(|err| {
+            trc::SpamEvent::ClassifyError
+                .into_err()
+                .caused_by(trc::location!())
+                .details("Failed to deserialize Bayes model metadata")
+                .reason(err)
+        })?
+    } else if record {
+        let weights = server
+            .inner
+            .data
+            .bayes_cache
+            .get_or_update(
+                TokenHash::default(),
+                model_seed(model_id),
+                store,
+                &server.core.spam.bayes.retry,
+            )
+            .await?;
+        let metadata = if weights.spam == 0 && weights.ham == 0 {
+            let config = server.core.spam.bayes.model(model_id);
+            BayesMetadata {
+                case_folding: config.case_folding,
+                normalize: config.normalize.clone(),
+                headers: config.headers.clone(),
+                ..Default::default()
+            }
+        } else {
+            BayesMetadata::default()
+        };
+        return server.bayes_store_metadata(model_id, store, metadata).await;
+    } else {
+        // Model has not been trained yet or was trained with the default settings
+        return Ok(Arc::new(BayesMetadata::default()));
+    };
+
+    Ok(server.inner.data.bayes_metadata.insert_with_ttl(
+        model_id.to_string(),
+        Arc::new(metadata),
+        Instant::now() + server.core.spam.bayes.metadata_ttl,
+    ))
+}
+
+// Borrows the text from string variables to avoid copying large messages, texts
+// above the configured size are either truncated or refused.
+pub(crate) fn text_argument<'x>(
+    ctx: &PluginContext<'_>,
+    variable: &'x Variable,
+) -> Option<Cow<'x, str>> {
+    let text = match variable {
+        Variable::String(text) => Cow::Borrowed(text.as_str()),
+        variable => variable.to_string(),
+    };
+    let max_size = ctx.server.core.spam.bayes.max_text_size;
+    if max_size == 0 || text.len() <= max_size {
+        return Some(text);
+    }
+
+    trc::event!(
+        Spam(trc::SpamEvent::ClassifyError),
+        SpanId = ctx.session_id,
+        Details = if ctx.server.core.spam.bayes.truncate_text {
+            "Text too large, truncating"
+        } else {
+            "Text too large, skipping"
+        },
+        Size = text.len(),
+        Limit = max_size,
+    );
+
+    if ctx.server.core.spam.bayes.truncate_text {
+        let mut end = max_size;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        Some(match text {
+            Cow::Borrowed(text) => Cow::Borrowed(&text[..end]),
+            Cow::Owned(mut text) => {
+                text.truncate(end);
+                Cow::Owned(text)
+            }
+        })
+    } else {
+        None
+    }
+}
+
+// Retries transient backend errors with exponential backoff, once the retries are
+// exhausted the error is reported as a Bayes backend error.
+pub(crate) async fn with_retry<T, F, R>(retry: &BayesRetryConfig, f: F) -> trc::Result<T>
+where
+    F: Fn() -> R,
+    R: Future<Output = trc::Result<T>>,
+{
+    let mut attempt = 0;
+    let mut backoff = retry.backoff;
+
+    loop {
+        match f().await {
+            Ok(result) => return Ok(result),
+            Err(err) if err.is_backend_error() => {
+                if attempt < retry.attempts {
+                    attempt += 1;
+                    tokio::time::sleep(backoff).await;
+                    backoff = backoff.saturating_mul(2).min(retry.max_backoff);
+                } else {
+                    return Err(err
+                        .wrap(trc::EventType::Spam(trc::SpamEvent::BackendError))
+                        .ctx(trc::Key::Total, attempt));
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+impl Server {
+    // Returns the divergence of a model from another, computed over a sample of the
+    // tokens of the first model and cached for the configured time
+    pub async fn bayes_divergence(
+        &self,
+        model_id: &str,
+        other_model_id: &str,
+    ) -> trc::Result<Option<f64>> {
+        let config = &self.core.spam.bayes.divergence;
+        let cache_key =
+            xxhash_rust::xxh3::xxh3_128(format!("{model_id}\0{other_model_id}").as_bytes());
+        if let Some(divergence) = self
+            .inner
+            .data
+            .bayes_divergence_cache
+            .get_with_ttl(&cache_key)
+        {
+            return Ok(divergence);
+        }
+
+        // Pending training updates are not visible in the store until flushed
+        self.bayes_flush().await?;
+
+        let store = self.bayes_store(model_id)?;
+        let other_store = self.bayes_store(other_model_id)?;
+        let retry = &self.core.spam.bayes.retry;
+
+        // Compare the token weights over the shared vocabulary of both models
+        let training_key = token_key(&TokenHash::default());
+        let mut divergence = BayesDivergence::new(
+            Weights::from(with_retry(retry, || store.counter_get(training_key.clone())).await?),
+            Weights::from(
+                with_retry(retry, || other_store.counter_get(training_key.clone())).await?,
+            ),
+        );
+        for key in token_keys_sample(store, config.sample).await? {
+            let weights =
+                Weights::from(with_retry(retry, || store.counter_get(key.clone())).await?);
+            if weights.spam + weights.ham > 0 {
+                divergence.add(
+                    weights,
+                    Weights::from(
+                        with_retry(retry, || other_store.counter_get(key.clone())).await?,
+                    ),
+                );
+            }
+        }
+        let divergence = divergence.divergence();
+
+        self.inner.data.bayes_divergence_cache.insert_with_ttl(
+            cache_key,
+            divergence,
+            Instant::now() + config.cache_ttl,
+        );
+
+        Ok(divergence)
+    }
+
+    // Models are identified by their lookup store id, an empty id selects the default store
+    pub fn bayes_store(&self, model_id: &str) -> trc::Result<&LookupStore> {
+        if !model_id.is_empty() {
+            self.core.storage.lookups.get(model_id)
+        } else {
+            Some(&self.core.storage.lookup)
+        }
+        .ok_or_else(|| {
+            trc::SpamEvent::TrainError
+                .ctx(trc::Key::Id, model_id.to_string())
+                .details("Unknown store")
+        })
+    }
+
+    // Analyzes the token co-occurrence of the sample texts and prunes the tokens that
+    // carry no additional signal, returns the number of newly pruned tokens.
+    pub async fn bayes_prune(&self, model_id: &str, texts: &[String]) -> trc::Result<usize> {
+        let store = self.bayes_store(model_id)?;
+        let config = self.core.spam.bayes.model(model_id);
+        let retry = &self.core.spam.bayes.retry;
+
+        let mut metadata = model_metadata(self, model_id, store, true)
+            .await?
+            .as_ref()
+            .clone();
+        let mut analyzer = CooccurrenceAnalyzer::new();
+        for text in texts {
+            analyzer.add_document(
+                OsbTokenizer::<_, TokenHash>::new(
+                    BayesTokenizer::new(text.as_ref())
+                        .with_case_folding(metadata.case_folding)
+                        .with_normalization(&metadata.normalize),
+                    5,
+                )
+                .filter(|token| !metadata.is_pruned(&token.inner)),
+            );
+        }
+        let mut pruned =
+            analyzer.redundant_tokens(config.prune_threshold, config.prune_min_documents);
+
+        // The pruned tokens are stored in the model metadata, once the limit is
+        // reached no further tokens are pruned
+        let max_pruned = config
+            .prune_max_tokens
+            .saturating_sub(metadata.pruned.len());
+        if pruned.len() > max_pruned {
+            trc::event!(
+                Spam(trc::SpamEvent::TrainError),
+                Id = model_id.to_string(),
+                Details = "Pruned tokens limit reached",
+                Total = pruned.len() - max_pruned,
+                Limit = config.prune_max_tokens,
+            );
+            pruned.truncate(max_pruned);
+        }
+        if pruned.is_empty() {
+            return Ok(0);
+        }
+
+        // Record the pruned tokens before removing their weights
+        let num_pruned = pruned.len();
+        metadata.pruned.extend(pruned.iter().copied());
+        metadata.pruned.sort_unstable();
+        metadata.pruned.dedup();
+        self.bayes_store_metadata(model_id, store, metadata).await?;
+
+        let bayes_cache = &self.inner.data.bayes_cache;
+        for hash in pruned {
+            with_retry(retry, || store.counter_delete(token_key(&hash)))
+                .await
+                .caused_by(trc::location!())?;
+            bayes_cache.invalidate(&hash.for_model(model_seed(model_id)));
+        }
+
+        trc::event!(
+            Spam(trc::SpamEvent::Train),
+            Id = model_id.to_string(),
+            Details = "Pruned redundant tokens",
+            Total = num_pruned,
+            Size = analyzer.documents(),
+        );
+
+        Ok(num_pruned)
+    }
+
+    // Replaces or removes the probability calibration of a model
+    pub async fn bayes_update_calibration(
+        &self,
+        model_id: &str,
+        calibration: Option<IsotonicCalibration>,
+    ) -> trc::Result<()> {
+        self.bayes_update_metadata(model_id, |metadata| metadata.calibration = calibration)
+            .await
+    }
+
+    pub async fn bayes_update_logistic(
+        &self,
+        model_id: &str,
+        logistic: Option<LogisticModel>,
+    ) -> trc::Result<()> {
+        self.bayes_update_metadata(model_id, |metadata| metadata.logistic = logistic)
+            .await
+    }
+
+    async fn bayes_update_metadata(
+        &self,
+        model_id: &str,
+        update: impl FnOnce(&mut BayesMetadata),
+    ) -> trc::Result<()> {
+        let store = self.bayes_store(model_id)?;
+
+        let mut metadata = model_metadata(self, model_id, store, true)
+            .await?
+            .as_ref()
+            .clone();
+        update(&mut metadata);
+        self.bayes_store_metadata(model_id, store, metadata)
+            .await
+            .map(|_| ())
+    }
+
+    // Writes the metadata of a model and caches it, other nodes read the update
+    // once their cached copy expires
+    pub(crate) async fn bayes_store_metadata(
+        &self,
+        model_id: &str,
+        store: &LookupStore,
+        metadata: BayesMetadata,
+    ) -> trc::Result<Arc<BayesMetadata>> {
+        let value = serde_json::to_vec(&metadata).unwrap_or_default();
+        with_retry(&self.core.spam.bayes.retry, || {
+            store.key_set(METADATA_KEY.to_vec(), value.clone(), None)
+        })
+        .await
+        .caused_by(trc::location!())?;
+
+        Ok(self.inner.data.bayes_metadata.insert_with_ttl(
+            model_id.to_string(),
+            Arc::new(metadata),
+            Instant::now() + self.core.spam.bayes.metadata_ttl,
+        ))
+    }
+}
+
 trait LookupOrInsert {
-    async fn get_or_update(&self, hash: TokenHash, get_token: &LookupStore)
-        -> trc::Result<Weights>;
+    async fn get_or_update(
+        &self,
+        hash: TokenHash,
+        model_seed: u64,
+        get_token: &LookupStore,
+        retry: &BayesRetryConfig,
+    ) -> trc::Result<Weights>;
+
+    async fn fetch_and_insert(
+        &self,
+        hash: TokenHash,
+        model_seed: u64,
+        get_token: &LookupStore,
+        retry: &BayesRetryConfig,
+    ) -> trc::Result<Weights>;
 }
 
 impl LookupOrInsert for BayesTokenCache {
     async fn get_or_update(
         &self,
         hash: TokenHash,
+        model_seed: u64,
         get_token: &LookupStore,
+        retry: &BayesRetryConfig,
     ) -> trc::Result<Weights> {
-        if let Some(weights) = self.get(&hash) {
+        if let Some(weights) = self.get(&hash.for_model(model_seed)) {
             Ok(weights.unwrap_or_default())
         } else {
-            let num = get_token
-                .counter_get(
-                    KeySerializer::new(U64_LEN)
-                        .write(hash.h1)
-                        .write(hash.h2)
-                        .finalize(),
-                )
+            self.fetch_and_insert(hash, model_seed, get_token, retry)
                 .await
-                .caused_by(trc::location!())?;
-            Ok(if num != 0 {
-                let weights = Weights::from(num);
-                self.insert_positive(hash, weights);
-                weights
-            } else {
-                self.insert_negative(hash);
-                Weights::default()
-            })
         }
     }
+
+    async fn fetch_and_insert(
+        &self,
+        hash: TokenHash,
+        model_seed: u64,
+        get_token: &LookupStore,
+        retry: &BayesRetryConfig,
+    ) -> trc::Result<Weights> {
+        // Values read while the token is being invalidated are returned but not cached
+        let generation = self.generation(&hash.for_model(model_seed));
+        let num = with_retry(retry, || {
+            get_token.counter_get(
+                KeySerializer::new(U64_LEN)
+                    .write(hash.h1)
+                    .write(hash.h2)
+                    .finalize(),
+            )
+        })
+        .await
+        .caused_by(trc::location!())?;
+        Ok(if num != 0 {
+            let weights = Weights::from(num);
+            self.insert_positive_at(hash.for_model(model_seed), weights, generation);
+            weights
+        } else {
+            self.insert_negative_at(hash.for_model(model_seed), generation);
+            Weights::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nlp::{
+        bayes::{BayesMetadata, TokenHash},
+        tokenizers::osb::Gram,
+    };
+
+    use crate::{config::spamfilter::BayesModelConfig, manager::bayes_live::TokenDiagnostics};
+
+    use super::{correction_weight, is_token_key, token_key, token_texts};
+
+    #[test]
+    fn bayes_token_texts() {
+        let tokens = [
+            Gram::Bi {
+                t1: "cheap",
+                t2: "pill",
+            },
+            Gram::Uni { t1: "unknown" },
+            Gram::Uni { t1: "offer" },
+        ]
+        .into_iter()
+        .map(|gram| {
+            let hash = TokenHash::from(gram);
+            TokenDiagnostics {
+                h1: hash.h1,
+                h2: hash.h2,
+                spam: 1,
+                ham: 0,
+                sources: Vec::new(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+        // Texts are returned as tokenized, in the order of the tokens. Tokens not
+        // found in the text are skipped.
+        assert_eq!(
+            token_texts(
+                "Limited offer: buy cheap pills today",
+                &[],
+                &BayesMetadata::default(),
+                &tokens
+            ),
+            vec![
+                (&tokens[0], "cheap pill".to_string()),
+                (&tokens[2], "offer".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn bayes_token_keys() {
+        assert!(is_token_key(&token_key(&TokenHash { h1: 1, h2: 2 })));
+        assert!(is_token_key(&token_key(&TokenHash::default())));
+
+        // Counters of other plugins sharing the store are not token weights
+        assert!(!is_token_key(b"cluster:\x00\x01\x02\x03\x04\x05\x06\x07"));
+        assert!(!is_token_key(b"bayes:sample:\x01\x00\x00"));
+        assert!(!is_token_key(b"cluster:\x00\x01\x02\x03\x04\x05\x06\x07b"));
+    }
+
+    #[test]
+    fn bayes_correction_limit() {
+        let config = BayesModelConfig {
+            correction_weight: 3,
+            correction_sender_limit: 2,
+            ..Default::default()
+        };
+
+        assert_eq!(correction_weight(&config, 1), Some(3));
+        assert_eq!(correction_weight(&config, 2), Some(3));
+        assert_eq!(correction_weight(&config, 3), None);
+
+        // A zero limit disables corrections
+        let config = BayesModelConfig {
+            correction_sender_limit: 0,
+            ..config
+        };
+        assert_eq!(correction_weight(&config, 1), None);
+    }
 }
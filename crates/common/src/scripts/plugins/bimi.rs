@@ -0,0 +1,470 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{io::Cursor, time::Instant};
+
+use mail_auth::Error;
+use rustls_pemfile::certs;
+use rustls_pki_types::CertificateDer;
+use sieve::{runtime::Variable, FunctionMap};
+use utils::map::ttl_dashmap::TtlMap;
+use x509_parser::{
+    certificate::X509Certificate,
+    der_parser::asn1_rs::FromDer,
+    extensions::{GeneralName, ParsedExtension},
+};
+
+use crate::{config::spamfilter::BimiConfig, HttpLimitResponse, Server};
+
+use super::PluginContext;
+
+// Extended key usage of Verified Mark Certificates (id-kp-BrandIndicatorforMessageIdentification)
+const VMC_KEY_USAGE: &str = "1.3.6.1.5.5.7.3.31";
+
+pub fn register(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("bimi_trust", plugin_id, 2);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BimiTrust {
+    None = 0,
+    Record = 1,
+    Verified = 2,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BimiRecord {
+    pub location: Option<String>,
+    pub authority: Option<String>,
+}
+
+// Returns the BIMI trust level of the domain (0 = no usable record, 1 = record
+// published, 2 = record with a verified mark certificate). The second argument
+// is the value of the BIMI-Selector header, the default selector is used when empty.
+// Callers are expected to check that the message passed an enforced DMARC policy.
+pub async fn exec(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let domain = ctx.arguments[0]
+        .to_string()
+        .trim()
+        .trim_end_matches('.')
+        .to_lowercase();
+    let Some(selector) = parse_selector(ctx.arguments[1].to_string().as_ref()) else {
+        return Ok(0.into());
+    };
+    if domain.is_empty() {
+        return Ok(0.into());
+    }
+    let config = &ctx.server.core.spam.bimi;
+    let cache_key = xxhash_rust::xxh3::xxh3_128(
+        format!("{selector}._bimi.{domain}:{}", config.verify_vmc).as_bytes(),
+    );
+
+    let trust = if let Some(trust) = ctx.server.inner.data.bimi_cache.get_with_ttl(&cache_key) {
+        trust
+    } else if let Some(trust) = ctx.server.bimi_trust(config, &domain, &selector).await {
+        ctx.server.inner.data.bimi_cache.insert_with_ttl(
+            cache_key,
+            trust,
+            Instant::now() + config.cache_ttl,
+        );
+        trust
+    } else {
+        // Temporary failures are not cached
+        BimiTrust::None
+    };
+
+    Ok((trust as i64).into())
+}
+
+impl Server {
+    // Returns None if the trust level could not be determined due to a temporary error
+    async fn bimi_trust(
+        &self,
+        config: &BimiConfig,
+        domain: &str,
+        selector: &str,
+    ) -> Option<BimiTrust> {
+        // Records are looked up at the organizational domain when the author domain has none,
+        // certificates are then matched against the domain the record was found at
+        let mut record_domain = domain;
+        let mut record = self.bimi_record(selector, domain).await?;
+        if record.is_none() {
+            if let Some(org_domain) = psl::domain_str(domain).filter(|d| *d != domain) {
+                record = self.bimi_record(selector, org_domain).await?;
+                record_domain = org_domain;
+            }
+        }
+
+        match record {
+            // Domains declining to participate are treated as having no record
+            None
+            | Some(BimiRecord {
+                location: None,
+                authority: None,
+            }) => Some(BimiTrust::None),
+            Some(BimiRecord {
+                authority: Some(authority),
+                ..
+            }) if config.verify_vmc => self.bimi_verify(config, record_domain, &authority).await,
+            Some(_) => Some(BimiTrust::Record),
+        }
+    }
+
+    async fn bimi_record(&self, selector: &str, domain: &str) -> Option<Option<BimiRecord>> {
+        match self
+            .core
+            .smtp
+            .resolvers
+            .dns
+            .txt_raw_lookup(format!("{selector}._bimi.{domain}."))
+            .await
+        {
+            Ok(record) => Some(parse_record(
+                std::str::from_utf8(&record).unwrap_or_default(),
+            )),
+            Err(Error::DnsRecordNotFound(_)) => Some(None),
+            Err(err) => {
+                trc::event!(
+                    Spam(trc::SpamEvent::ClassifyError),
+                    Details = "Failed to lookup BIMI record",
+                    Domain = domain.to_string(),
+                    Reason = err.to_string(),
+                );
+                None
+            }
+        }
+    }
+
+    async fn bimi_verify(
+        &self,
+        config: &BimiConfig,
+        domain: &str,
+        authority: &str,
+    ) -> Option<BimiTrust> {
+        if !authority
+            .get(..8)
+            .is_some_and(|scheme| scheme.eq_ignore_ascii_case("https://"))
+        {
+            return Some(BimiTrust::None);
+        }
+
+        let time = Instant::now();
+        let result = async {
+            let response = reqwest::Client::builder()
+                .timeout(config.timeout)
+                .build()?
+                .get(authority)
+                .send()
+                .await?
+                .error_for_status()?;
+            response.bytes_with_limit(config.max_size).await
+        }
+        .await;
+
+        match result {
+            Ok(Some(pem)) => Some(verify_certificate(
+                &pem,
+                domain,
+                self.now() as i64,
+                &config.roots,
+            )),
+            Ok(None) => Some(BimiTrust::None),
+            Err(err) => {
+                trc::event!(
+                    Spam(trc::SpamEvent::ClassifyError),
+                    Details = "Failed to fetch BIMI evidence document",
+                    Url = authority.to_string(),
+                    Reason = err.to_string(),
+                    Elapsed = time.elapsed(),
+                );
+                None
+            }
+        }
+    }
+}
+
+// Extracts the selector from a BIMI-Selector header ("v=BIMI1; s=selector;")
+pub fn parse_selector(header: &str) -> Option<String> {
+    let header = header.trim();
+    let selector = if header.is_empty() {
+        "default".to_string()
+    } else {
+        tags(header)
+            .find_map(|(name, value)| name.eq_ignore_ascii_case("s").then_some(value))?
+            .to_lowercase()
+    };
+
+    (!selector.is_empty()
+        && selector
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_' | '.')))
+    .then_some(selector)
+}
+
+// Parses a BIMI assertion record, returns None if the record is not a BIMI record.
+// Multiple TXT records are concatenated by the resolver, so the record is located
+// by its version tag.
+pub fn parse_record(txt: &str) -> Option<BimiRecord> {
+    let start = txt.find("v=BIMI1")?;
+    let mut tags = tags(&txt[start..]);
+    if tags.next() != Some(("v", "BIMI1")) {
+        return None;
+    }
+
+    let mut record = BimiRecord {
+        location: None,
+        authority: None,
+    };
+    for (name, value) in tags {
+        let value = Some(value.to_string()).filter(|value| !value.is_empty());
+        if name.eq_ignore_ascii_case("l") {
+            record.location = value;
+        } else if name.eq_ignore_ascii_case("a") {
+            record.authority = value;
+        }
+    }
+
+    Some(record)
+}
+
+fn tags(text: &str) -> impl Iterator<Item = (&str, &str)> {
+    text.split(';').filter_map(|tag| {
+        let (name, value) = tag.split_once('=')?;
+        Some((name.trim(), value.trim()))
+    })
+}
+
+// Checks that the leaf certificate of the evidence document is a VMC currently
+// valid for the domain. Certificates are only reported as verified when they chain
+// up to one of the configured mark verifying authorities, otherwise the evidence
+// document is no stronger than the record itself.
+pub fn verify_certificate(pem: &[u8], domain: &str, now: i64, roots: &[Vec<u8>]) -> BimiTrust {
+    let chain = certs(&mut Cursor::new(pem))
+        .filter_map(|der| der.ok())
+        .collect::<Vec<_>>();
+    let Some(Ok((_, cert))) = chain
+        .first()
+        .map(|der| X509Certificate::from_der(der.as_ref()))
+    else {
+        return BimiTrust::None;
+    };
+    if !is_valid_at(&cert, now) {
+        return BimiTrust::None;
+    }
+
+    let mut has_usage = false;
+    let mut has_domain = false;
+    for ext in cert.extensions() {
+        match ext.parsed_extension() {
+            ParsedExtension::ExtendedKeyUsage(usage) => {
+                has_usage = usage
+                    .other
+                    .iter()
+                    .any(|oid| oid.to_id_string() == VMC_KEY_USAGE);
+            }
+            ParsedExtension::SubjectAlternativeName(san) => {
+                has_domain = san.general_names.iter().any(|name| {
+                    matches!(name, GeneralName::DNSName(name) if name.eq_ignore_ascii_case(domain))
+                });
+            }
+            _ => {}
+        }
+    }
+
+    if !has_usage || !has_domain {
+        BimiTrust::None
+    } else if is_trusted_chain(&cert, &chain[1..], now, roots) {
+        BimiTrust::Verified
+    } else {
+        BimiTrust::Record
+    }
+}
+
+// Walks the intermediates included in the evidence document until a certificate
+// signed by one of the roots is found
+fn is_trusted_chain(
+    cert: &X509Certificate<'_>,
+    intermediates: &[CertificateDer<'_>],
+    now: i64,
+    roots: &[Vec<u8>],
+) -> bool {
+    let roots = roots
+        .iter()
+        .filter_map(|der| X509Certificate::from_der(der).ok().map(|(_, root)| root))
+        .collect::<Vec<_>>();
+    let intermediates = intermediates
+        .iter()
+        .filter_map(|der| {
+            X509Certificate::from_der(der.as_ref())
+                .ok()
+                .map(|(_, cert)| cert)
+        })
+        .collect::<Vec<_>>();
+    let is_issuer = |cert: &X509Certificate<'_>, issuer: &X509Certificate<'_>| {
+        issuer.is_ca()
+            && cert.issuer() == issuer.subject()
+            && is_valid_at(issuer, now)
+            && cert.verify_signature(Some(issuer.public_key())).is_ok()
+    };
+
+    let mut cert = cert;
+    for _ in 0..=intermediates.len() {
+        if roots.iter().any(|root| is_issuer(cert, root)) {
+            return true;
+        }
+        match intermediates.iter().find(|issuer| is_issuer(cert, issuer)) {
+            Some(issuer) => cert = issuer,
+            None => break,
+        }
+    }
+
+    false
+}
+
+fn is_valid_at(cert: &X509Certificate<'_>, now: i64) -> bool {
+    let validity = cert.validity();
+    now >= validity.not_before.timestamp() && now <= validity.not_after.timestamp()
+}
+
+#[cfg(test)]
+mod tests {
+    use rcgen::{
+        BasicConstraints, Certificate, CertificateParams, CustomExtension, DistinguishedName,
+        DnType, IsCa,
+    };
+
+    use super::{parse_record, parse_selector, verify_certificate, BimiRecord, BimiTrust};
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn vmc_params(domain: &str) -> CertificateParams {
+        let mut params = CertificateParams::new(vec![domain.to_string()]);
+        params.custom_extensions = vec![CustomExtension::from_oid_content(
+            &[2, 5, 29, 37],
+            vec![
+                0x30, 0x0a, 0x06, 0x08, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x1f,
+            ],
+        )];
+        params
+    }
+
+    #[test]
+    fn bimi_record() {
+        assert_eq!(
+            parse_record("v=BIMI1; l=https://example.com/logo.svg; a=https://example.com/vmc.pem;"),
+            Some(BimiRecord {
+                location: Some("https://example.com/logo.svg".to_string()),
+                authority: Some("https://example.com/vmc.pem".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_record("google-site-verification=abcv=BIMI1;l=;a=;"),
+            Some(BimiRecord {
+                location: None,
+                authority: None,
+            })
+        );
+        assert_eq!(parse_record("v=spf1 -all"), None);
+        assert_eq!(parse_record("v=BIMI1x; l=https://example.com/"), None);
+
+        assert_eq!(parse_selector(""), Some("default".to_string()));
+        assert_eq!(
+            parse_selector("v=BIMI1; s=Brand2;"),
+            Some("brand2".to_string())
+        );
+        assert_eq!(parse_selector("v=BIMI1; s=../x y;"), None);
+        assert_eq!(parse_selector("v=BIMI1;"), None);
+
+        // Certificates without the VMC key usage are not accepted
+        let pem = Certificate::from_params(CertificateParams::new(vec!["example.com".to_string()]))
+            .unwrap()
+            .serialize_pem()
+            .unwrap();
+        assert_eq!(
+            verify_certificate(pem.as_bytes(), "example.com", NOW, &[]),
+            BimiTrust::None
+        );
+
+        // Self-signed certificates are not verified, even when listed as a root
+        let leaf = Certificate::from_params(vmc_params("example.com")).unwrap();
+        let pem = leaf.serialize_pem().unwrap();
+        assert_eq!(
+            verify_certificate(pem.as_bytes(), "example.com", NOW, &[]),
+            BimiTrust::Record
+        );
+        assert_eq!(
+            verify_certificate(
+                pem.as_bytes(),
+                "example.com",
+                NOW,
+                &[leaf.serialize_der().unwrap()]
+            ),
+            BimiTrust::Record
+        );
+
+        // Certificates issued by a mark verifying authority, directly or
+        // through an intermediate included in the evidence document
+        let mut params = CertificateParams::new(vec![]);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.distinguished_name = DistinguishedName::new();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "Mark Verifying Authority");
+        let root = Certificate::from_params(params).unwrap();
+        let roots = [root.serialize_der().unwrap()];
+        let mut params = CertificateParams::new(vec![]);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.distinguished_name = DistinguishedName::new();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "Intermediate");
+        let intermediate = Certificate::from_params(params).unwrap();
+
+        let pem = leaf.serialize_pem_with_signer(&root).unwrap();
+        assert_eq!(
+            verify_certificate(pem.as_bytes(), "example.com", NOW, &roots),
+            BimiTrust::Verified
+        );
+        assert_eq!(
+            verify_certificate(pem.as_bytes(), "example.com", NOW, &[]),
+            BimiTrust::Record
+        );
+        let pem = format!(
+            "{}{}",
+            leaf.serialize_pem_with_signer(&intermediate).unwrap(),
+            intermediate.serialize_pem_with_signer(&root).unwrap()
+        );
+        assert_eq!(
+            verify_certificate(pem.as_bytes(), "example.com", NOW, &roots),
+            BimiTrust::Verified
+        );
+        assert_eq!(
+            verify_certificate(
+                leaf.serialize_pem_with_signer(&intermediate)
+                    .unwrap()
+                    .as_bytes(),
+                "example.com",
+                NOW,
+                &roots
+            ),
+            BimiTrust::Record
+        );
+
+        let pem = leaf.serialize_pem_with_signer(&root).unwrap();
+        assert_eq!(
+            verify_certificate(pem.as_bytes(), "example.org", NOW, &roots),
+            BimiTrust::None
+        );
+        assert_eq!(
+            verify_certificate(pem.as_bytes(), "example.com", i64::MAX, &roots),
+            BimiTrust::None
+        );
+        assert_eq!(
+            verify_certificate(b"not a certificate", "example.com", NOW, &roots),
+            BimiTrust::None
+        );
+    }
+}
@@ -0,0 +1,214 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use mail_parser::Message;
+use sieve::{runtime::Variable, FunctionMap};
+
+use crate::config::spamfilter::{BulkConfig, BulkIndicator};
+
+use super::PluginContext;
+
+pub fn register(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("bulk_score", plugin_id, 1);
+}
+
+// Returns a negative score for messages that follow the bulk mail conventions,
+// a positive score for bulk mail that does not, and zero for non-bulk mail.
+pub fn exec(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let sender_domain = ctx.arguments[0].to_string();
+    let config = &ctx.server.core.spam.bulk;
+
+    Ok(bulk_indicators(ctx.message, sender_domain.as_ref())
+        .into_iter()
+        .map(|indicator| config.weight(indicator))
+        .sum::<f64>()
+        .into())
+}
+
+pub fn bulk_indicators(message: &Message<'_>, sender_domain: &str) -> Vec<BulkIndicator> {
+    let mut indicators = Vec::new();
+
+    // Precedence (RFC 2076), List-Id (RFC 2919) and Feedback-ID
+    let has_precedence = message.header_raw("Precedence").is_some_and(|value| {
+        let value = value.trim();
+        ["bulk", "list", "junk"]
+            .iter()
+            .any(|v| value.eq_ignore_ascii_case(v))
+    });
+    let has_list_id = message.header_raw("List-Id").is_some();
+    let has_feedback_id = message.header_raw("Feedback-ID").is_some();
+    if has_list_id {
+        indicators.push(BulkIndicator::ListId);
+    }
+    if has_feedback_id {
+        indicators.push(BulkIndicator::FeedbackId);
+    }
+
+    // List-Unsubscribe (RFC 2369) and one-click unsubscribe (RFC 8058)
+    let unsubscribe_post = message.header_raw("List-Unsubscribe-Post").map(|value| {
+        value
+            .trim()
+            .eq_ignore_ascii_case("List-Unsubscribe=One-Click")
+    });
+    match message.header_raw("List-Unsubscribe") {
+        Some(value) => {
+            let uris = value
+                .split(',')
+                .filter_map(|uri| {
+                    let uri = uri.trim().strip_prefix('<')?.strip_suffix('>')?.trim();
+                    let (scheme, rest) = uri.split_once(':')?;
+                    let scheme = scheme.to_ascii_lowercase();
+                    let domain = match scheme.as_str() {
+                        "mailto" => rest
+                            .split('?')
+                            .next()?
+                            .rsplit_once('@')
+                            .map(|(_, domain)| domain),
+                        "http" | "https" => {
+                            rest.strip_prefix("//")?.split(['/', '?', '#', ':']).next()
+                        }
+                        _ => None,
+                    }?;
+                    Some((scheme, domain.to_ascii_lowercase()))
+                })
+                .collect::<Vec<_>>();
+
+            if uris.is_empty() {
+                indicators.push(BulkIndicator::UnsubscribeInvalid);
+            } else {
+                indicators.push(BulkIndicator::Unsubscribe);
+
+                let has_https = uris.iter().any(|(scheme, _)| scheme == "https");
+                if !has_https && uris.iter().any(|(scheme, _)| scheme == "http") {
+                    indicators.push(BulkIndicator::UnsubscribeInsecure);
+                }
+                match unsubscribe_post {
+                    Some(true) if has_https => indicators.push(BulkIndicator::OneClick),
+                    Some(_) => indicators.push(BulkIndicator::OneClickInvalid),
+                    None => {}
+                }
+
+                // The unsubscribe address has to belong to the sender
+                let sender_domain = sender_domain.trim().to_ascii_lowercase();
+                let sender_sld = psl::domain_str(&sender_domain).unwrap_or(&sender_domain);
+                if !sender_domain.is_empty() {
+                    if uris
+                        .iter()
+                        .any(|(_, domain)| psl::domain_str(domain).unwrap_or(domain) == sender_sld)
+                    {
+                        indicators.push(BulkIndicator::UnsubscribeAligned);
+                    } else {
+                        indicators.push(BulkIndicator::UnsubscribeMisaligned);
+                    }
+                }
+            }
+        }
+        None if has_precedence || has_list_id || has_feedback_id => {
+            indicators.push(BulkIndicator::UnsubscribeMissing);
+        }
+        None => {}
+    }
+
+    indicators
+}
+
+impl BulkConfig {
+    pub fn weight(&self, indicator: BulkIndicator) -> f64 {
+        self.weights[indicator as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mail_parser::MessageParser;
+
+    use crate::config::spamfilter::{BulkConfig, BulkIndicator};
+
+    use super::bulk_indicators;
+
+    #[test]
+    fn bulk_indicators_score() {
+        let config = BulkConfig::default();
+
+        for (message, sender_domain, expected) in [
+            (
+                concat!(
+                    "From: news@example.com\r\n",
+                    "Subject: Hello\r\n\r\n",
+                    "Hello world\r\n"
+                ),
+                "example.com",
+                vec![],
+            ),
+            (
+                concat!(
+                    "From: news@example.com\r\n",
+                    "List-Id: <news.example.com>\r\n",
+                    "List-Unsubscribe: <mailto:leave@lists.example.com>, ",
+                    "<https://www.example.com/unsubscribe?id=1>\r\n",
+                    "List-Unsubscribe-Post: List-Unsubscribe=One-Click\r\n",
+                    "Subject: Newsletter\r\n\r\n",
+                    "Hello world\r\n"
+                ),
+                "mail.example.com",
+                vec![
+                    BulkIndicator::ListId,
+                    BulkIndicator::Unsubscribe,
+                    BulkIndicator::OneClick,
+                    BulkIndicator::UnsubscribeAligned,
+                ],
+            ),
+            (
+                concat!(
+                    "From: deals@example.com\r\n",
+                    "Precedence: bulk\r\n",
+                    "Subject: Deals\r\n\r\n",
+                    "Hello world\r\n"
+                ),
+                "example.com",
+                vec![BulkIndicator::UnsubscribeMissing],
+            ),
+            (
+                concat!(
+                    "From: deals@example.com\r\n",
+                    "Precedence: bulk\r\n",
+                    "List-Unsubscribe: <http://tracker.example.org/u>\r\n",
+                    "List-Unsubscribe-Post: List-Unsubscribe=One-Click\r\n",
+                    "Subject: Deals\r\n\r\n",
+                    "Hello world\r\n"
+                ),
+                "example.com",
+                vec![
+                    BulkIndicator::Unsubscribe,
+                    BulkIndicator::UnsubscribeInsecure,
+                    BulkIndicator::OneClickInvalid,
+                    BulkIndicator::UnsubscribeMisaligned,
+                ],
+            ),
+            (
+                concat!(
+                    "From: deals@example.com\r\n",
+                    "List-Unsubscribe: click here\r\n",
+                    "Subject: Deals\r\n\r\n",
+                    "Hello world\r\n"
+                ),
+                "example.com",
+                vec![BulkIndicator::UnsubscribeInvalid],
+            ),
+        ] {
+            let message = MessageParser::new().parse(message.as_bytes()).unwrap();
+            let indicators = bulk_indicators(&message, sender_domain);
+            assert_eq!(indicators, expected);
+
+            let score = indicators.iter().map(|i| config.weight(*i)).sum::<f64>();
+            match expected.first() {
+                None => assert_eq!(score, 0.0),
+                Some(BulkIndicator::ListId) => assert!(score < 0.0),
+                _ => assert!(score > 0.0, "{expected:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use nlp::{bayes::tokenize::BayesTokenizer, lsh::LshSignature};
+use sieve::{runtime::Variable, FunctionMap};
+use store::{write::key::KeySerializer, U64_LEN};
+use trc::AddContext;
+
+use super::PluginContext;
+
+pub(crate) const CLUSTER_PREFIX: &[u8] = b"cluster:";
+// Suffix that keeps cluster counters from having the 128-bit length of Bayes tokens
+const CLUSTER_SUFFIX: u8 = b'b';
+
+pub fn register(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("message_cluster", plugin_id, 2);
+}
+
+// Returns an array containing the cluster id and the number of similar messages
+// seen within the configured window (including the current one).
+pub async fn exec(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let store = match &ctx.arguments[0] {
+        Variable::String(v) if !v.is_empty() => ctx.server.core.storage.lookups.get(v.as_ref()),
+        _ => Some(&ctx.server.core.storage.lookup),
+    }
+    .ok_or_else(|| {
+        trc::SieveEvent::RuntimeError
+            .ctx(trc::Key::Id, ctx.arguments[0].to_string().into_owned())
+            .details("Unknown store")
+    })?;
+    let text = ctx.arguments[1].to_string();
+    let Some(signature) = LshSignature::new(BayesTokenizer::new(text.as_ref())) else {
+        return Ok(Variable::default());
+    };
+    let window = ctx.server.core.spam.cluster.window.as_secs();
+
+    // Each band is counted separately, the message joins the largest matching cluster
+    let mut cluster_id = signature.cluster_id();
+    let mut cluster_size = 0;
+    for band in signature.bands {
+        let size = store
+            .counter_incr(
+                KeySerializer::new(CLUSTER_PREFIX.len() + U64_LEN + 1)
+                    .write(CLUSTER_PREFIX)
+                    .write(band)
+                    .write(CLUSTER_SUFFIX)
+                    .finalize(),
+                1,
+                window.into(),
+                true,
+            )
+            .await
+            .caused_by(trc::location!())?;
+        if size > cluster_size {
+            cluster_id = band;
+            cluster_size = size;
+        }
+    }
+
+    Ok(Variable::Array(
+        vec![
+            Variable::from(format!("{cluster_id:016x}")),
+            Variable::from(cluster_size),
+        ]
+        .into(),
+    ))
+}
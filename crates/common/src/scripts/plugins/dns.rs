@@ -228,6 +228,26 @@ trait ShortError {
     fn short_error(&self) -> &'static str;
 }
 
+// TXT records served to plugins when running tests, the test resolver only caches
+// parsed TXT records
+#[cfg(feature = "test_mode")]
+pub(crate) fn test_txt_record(name: &str) -> Option<&'static str> {
+    const TXT_RECORDS: &[(&str, &str)] = &[
+        (
+            "1.0.0.10.origin.asn.cymru.com",
+            "23028 | 10.0.0.0/8 | US | arin | 2002-01-04",
+        ),
+        (
+            "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.origin6.asn.cymru.com",
+            "23028 | 2001:db8::/32 | US | arin | 2002-01-04",
+        ),
+    ];
+
+    TXT_RECORDS
+        .iter()
+        .find_map(|(record_name, record)| (*record_name == name).then_some(*record))
+}
+
 impl ShortError for mail_auth::Error {
     fn short_error(&self) -> &'static str {
         match self {
@@ -0,0 +1,245 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ahash::AHashMap;
+use mail_parser::{Message, MimeHeaders};
+use nlp::bayes::tokenize::BayesTokenizer;
+use sieve::{runtime::Variable, FunctionMap};
+
+use super::{
+    bayes::{classify, classify_outcome, model_metadata},
+    bulk::bulk_indicators,
+    obfuscation::obfuscation_score,
+    tracking::remote_images,
+    unwrap::wrapped_messages,
+    PluginContext,
+};
+
+// Version of the feature vector, bumped whenever features are added. Features are
+// only ever appended, so consumers of an older version can keep reading the first
+// FEATURES.len() values of a newer vector.
+pub const FEATURE_VERSION: u32 = 1;
+
+// Order of the features following the version, missing values are reported as -1.
+pub const FEATURES: [&str; 15] = [
+    "bayes_score",
+    "token_coverage",
+    "token_count",
+    "word_entropy",
+    "subject_obfuscation",
+    "bulk_score",
+    "remote_images",
+    "tracking_pixels",
+    "wrapped_messages",
+    "attachments",
+    "html_only",
+    "auth_spf",
+    "auth_dkim",
+    "auth_dmarc",
+    "auth_arc",
+];
+
+// Subset of the features combined with the Bayes score by the logistic ensemble
+// unless others are selected when fitting it, chosen for being cheap to compute.
+pub const ENSEMBLE_FEATURES: [&str; 6] = [
+    "bayes_score",
+    "token_coverage",
+    "word_entropy",
+    "auth_spf",
+    "auth_dkim",
+    "auth_dmarc",
+];
+
+pub fn register(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("feature_vector", plugin_id, 4);
+}
+
+pub fn register_ensemble(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("bayes_classify_ensemble", plugin_id, 4);
+}
+
+// Returns an array starting with the feature version followed by the features
+// listed in FEATURES. Arguments are the Bayes model, text and classification
+// parameters (as in bayes_classify) and an array with the SPF, DKIM, DMARC and
+// ARC results.
+pub async fn exec(mut ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let features = message_features(&mut ctx).await?;
+
+    Ok(Variable::Array(
+        [FEATURE_VERSION as f64]
+            .into_iter()
+            .chain(features)
+            .map(Variable::from)
+            .collect::<Vec<_>>()
+            .into(),
+    ))
+}
+
+// Takes the same arguments as feature_vector and returns the probability obtained
+// by the logistic model of the Bayes model, which combines the Bayes score with
+// the selected features. Models without a fitted logistic model return the Bayes
+// score, as do messages without a Bayes verdict (which is then empty).
+pub async fn exec_ensemble(mut ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let model_id = ctx.arguments[0].to_string();
+    let store = ctx.server.bayes_store(model_id.as_ref())?;
+    let Some(logistic) = model_metadata(ctx.server, model_id.as_ref(), store, false)
+        .await?
+        .logistic
+        .clone()
+    else {
+        return classify(&ctx)
+            .await
+            .map(|score| score.map(Variable::from).unwrap_or_default());
+    };
+
+    let features = message_features(&mut ctx).await?;
+    if features[0] < 0.0 {
+        return Ok(Variable::default());
+    }
+    let values = logistic
+        .features
+        .iter()
+        .map(|name| {
+            FEATURES
+                .iter()
+                .position(|feature| feature == name)
+                .map_or(-1.0, |idx| features[idx])
+        })
+        .collect::<Vec<_>>();
+
+    Ok(logistic.predict(&values).into())
+}
+
+// Computes the features listed in FEATURES, in the same order
+async fn message_features(ctx: &mut PluginContext<'_>) -> trc::Result<Vec<f64>> {
+    let outcome = if !ctx.arguments[1].is_empty() {
+        classify_outcome(
+            &PluginContext {
+                session_id: ctx.session_id,
+                access_token: ctx.access_token,
+                server: ctx.server,
+                message: ctx.message,
+                modifications: &mut *ctx.modifications,
+                arguments: ctx.arguments[..3].to_vec(),
+            },
+            0,
+        )
+        .await?
+    } else {
+        Default::default()
+    };
+    let text = ctx.arguments[1].to_string();
+    let auth = ctx.arguments[3]
+        .as_array()
+        .map(|results| {
+            results
+                .iter()
+                .map(|result| auth_feature(result.to_string().as_ref()))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let config = &ctx.server.core.spam;
+    let sender_domain = ctx
+        .message
+        .from()
+        .and_then(|from| from.first())
+        .and_then(|addr| addr.address())
+        .and_then(|addr| addr.rsplit_once('@'))
+        .map(|(_, domain)| domain.to_lowercase())
+        .unwrap_or_default();
+    let images = remote_images(ctx.message);
+
+    let mut features = Vec::with_capacity(FEATURES.len());
+    features.push(outcome.score.unwrap_or(-1.0));
+    features.push(if outcome.total_tokens > 0 {
+        outcome.known_tokens as f64 / outcome.total_tokens as f64
+    } else {
+        -1.0
+    });
+    features.push(outcome.total_tokens as f64);
+    features.push(word_entropy(text.as_ref()));
+    features.push(obfuscation_score(
+        ctx.message.subject().unwrap_or_default(),
+        &config.obfuscation,
+    ));
+    features.push(
+        bulk_indicators(ctx.message, &sender_domain)
+            .into_iter()
+            .map(|indicator| config.bulk.weight(indicator))
+            .sum::<f64>(),
+    );
+    features.push(images.len() as f64);
+    features.push(images.iter().filter(|image| image.is_pixel).count() as f64);
+    features.push(wrapped_messages(ctx.message, &config.unwrap).len() as f64);
+    features.push(ctx.message.attachments().count() as f64);
+    features.push(html_only(ctx.message) as u32 as f64);
+    for idx in 0..4 {
+        features.push(auth.get(idx).copied().unwrap_or(-1.0));
+    }
+    debug_assert_eq!(features.len(), FEATURES.len());
+
+    Ok(features)
+}
+
+// Shannon entropy of the word distribution, in bits
+pub fn word_entropy(text: &str) -> f64 {
+    let mut words: AHashMap<String, u32> = AHashMap::new();
+    let mut total = 0;
+    for word in BayesTokenizer::new(text) {
+        *words.entry(word.into_owned()).or_default() += 1;
+        total += 1;
+    }
+
+    words
+        .values()
+        .map(|count| {
+            let p = *count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn html_only(message: &Message<'_>) -> bool {
+    !message.html_body.is_empty()
+        && message.text_body.iter().all(|pos| {
+            message
+                .part(*pos)
+                .is_some_and(|part| part.is_content_type("text", "html"))
+        })
+}
+
+// Authentication results range from fail (0) to pass (1), missing results are -1
+fn auth_feature(result: &str) -> f64 {
+    match result {
+        "pass" => 1.0,
+        "fail" | "permerror" => 0.0,
+        "softfail" => 0.25,
+        "" => -1.0,
+        _ => 0.5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{word_entropy, ENSEMBLE_FEATURES, FEATURES};
+
+    #[test]
+    fn feature_entropy() {
+        assert_eq!(word_entropy(""), 0.0);
+        assert_eq!(word_entropy("free free free free"), 0.0);
+        assert!((word_entropy("cheap pills cheap pills") - 1.0).abs() < f64::EPSILON);
+        assert!((word_entropy("one two three four") - 2.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn feature_ensemble() {
+        assert!(ENSEMBLE_FEATURES
+            .iter()
+            .all(|feature| FEATURES.contains(feature)));
+        assert_eq!(ENSEMBLE_FEATURES[0], FEATURES[0]);
+    }
+}
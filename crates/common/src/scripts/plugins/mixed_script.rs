@@ -0,0 +1,83 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use sieve::{runtime::Variable, FunctionMap};
+use unicode_security::MixedScript;
+
+use crate::config::spamfilter::MixedScriptConfig;
+
+use super::PluginContext;
+
+pub fn register(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("mixed_script", plugin_id, 1);
+}
+
+// Returns the number of words mixing characters from different scripts, such as
+// Latin letters replaced with Cyrillic or Greek homoglyphs.
+pub fn exec(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    Ok((mixed_script_words(
+        ctx.arguments[0].to_string().as_ref(),
+        &ctx.server.core.spam.mixed_script,
+    ) as i64)
+        .into())
+}
+
+pub fn mixed_script_words(text: &str, config: &MixedScriptConfig) -> usize {
+    text.split(|c: char| c.is_whitespace() || c.is_ascii_punctuation())
+        .take(config.max_words)
+        .filter(|word| {
+            // ASCII words are always single script, skipping them keeps the scan cheap
+            !word.is_ascii()
+                && !word.is_single_script()
+                && (config.allow.is_empty()
+                    || !config.allow.contains(
+                        &word
+                            .trim_matches(|c: char| !c.is_alphanumeric())
+                            .to_lowercase(),
+                    ))
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::spamfilter::MixedScriptConfig;
+
+    use super::mixed_script_words;
+
+    #[test]
+    fn mixed_script() {
+        let mut config = MixedScriptConfig::default();
+
+        // Single script words, including scripts that are legitimately combined
+        for text in [
+            "Your invoice for October",
+            "Счёт за октябрь, invoice attached",
+            "東京の天気予報、カタカナ",
+            "Café déjà vu 2024",
+            "«Привет» мир!",
+        ] {
+            assert_eq!(mixed_script_words(text, &config), 0, "{text}");
+        }
+
+        // Latin words containing Cyrillic and Greek homoglyphs
+        assert_eq!(
+            mixed_script_words("Your Раypal account was lоcked by Αpple", &config),
+            3
+        );
+
+        // Allowed words are not counted
+        config.allow.insert("раypal".to_string());
+        assert_eq!(
+            mixed_script_words("Your Раypal account was lоcked by Αpple", &config),
+            2
+        );
+
+        // Only the first words are scanned
+        config.max_words = 2;
+        assert_eq!(mixed_script_words("lоcked lоcked lоcked", &config), 2);
+    }
+}
@@ -4,16 +4,31 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod asn;
 pub mod bayes;
+pub mod bimi;
+pub mod bulk;
+pub mod cluster;
 pub mod dns;
 pub mod exec;
+pub mod features;
 pub mod headers;
 pub mod http;
 pub mod llm_prompt;
 pub mod lookup;
+pub mod mixed_script;
+pub mod obfuscation;
+pub mod offense;
 pub mod pyzor;
 pub mod query;
+pub mod redirect;
+pub mod remote_classifier;
+pub mod sa_report;
+pub mod sending_pattern;
+pub mod sent_profile;
 pub mod text;
+pub mod tracking;
+pub mod unwrap;
 
 use mail_parser::Message;
 use sieve::{runtime::Variable, FunctionMap, Input};
@@ -33,7 +48,7 @@ pub struct PluginContext<'x> {
     pub arguments: Vec<Variable>,
 }
 
-const PLUGINS_REGISTER: [RegisterPluginFnc; 19] = [
+const PLUGINS_REGISTER: [RegisterPluginFnc; 47] = [
     query::register,
     exec::register,
     lookup::register,
@@ -53,6 +68,34 @@ const PLUGINS_REGISTER: [RegisterPluginFnc; 19] = [
     text::register_tokenize,
     text::register_domain_part,
     llm_prompt::register,
+    asn::register,
+    asn::register_reputation,
+    asn::register_reputation_update,
+    bayes::register_divergence,
+    obfuscation::register,
+    bulk::register,
+    remote_classifier::register,
+    bayes::register_correct,
+    cluster::register,
+    sending_pattern::register,
+    tracking::register,
+    bayes::register_train_split,
+    bayes::register_untrain_split,
+    bayes::register_classify_split,
+    unwrap::register,
+    features::register,
+    sent_profile::register_update,
+    sent_profile::register_similarity,
+    offense::register,
+    redirect::register,
+    bimi::register,
+    mixed_script::register,
+    bayes::register_classify_vars,
+    bayes::register_classify_sender,
+    features::register_ensemble,
+    bayes::register_check_outbound,
+    sa_report::register,
+    bayes::register_train_manual,
 ];
 
 pub trait RegisterSievePlugins {
@@ -107,6 +150,34 @@ impl Core {
             16 => text::exec_tokenize(ctx),
             17 => text::exec_domain_part(ctx),
             18 => llm_prompt::exec(ctx).await,
+            19 => asn::exec(ctx).await,
+            20 => asn::exec_reputation(ctx).await,
+            21 => asn::exec_reputation_update(ctx).await,
+            22 => bayes::exec_divergence(ctx).await,
+            23 => obfuscation::exec(ctx),
+            24 => bulk::exec(ctx),
+            25 => remote_classifier::exec(ctx).await,
+            26 => bayes::exec_correct(ctx).await,
+            27 => cluster::exec(ctx).await,
+            28 => sending_pattern::exec(ctx).await,
+            29 => tracking::exec(ctx).await,
+            30 => bayes::exec_train_split(ctx).await,
+            31 => bayes::exec_untrain_split(ctx).await,
+            32 => bayes::exec_classify_split(ctx).await,
+            33 => unwrap::exec(ctx),
+            34 => features::exec(ctx).await,
+            35 => sent_profile::exec_update(ctx).await,
+            36 => sent_profile::exec_similarity(ctx).await,
+            37 => offense::exec(ctx).await,
+            38 => redirect::exec(ctx).await,
+            39 => bimi::exec(ctx).await,
+            40 => mixed_script::exec(ctx),
+            41 => bayes::exec_classify_vars(ctx).await,
+            42 => bayes::exec_classify_sender(ctx).await,
+            43 => features::exec_ensemble(ctx).await,
+            44 => bayes::exec_check_outbound(ctx).await,
+            45 => sa_report::exec(ctx).await,
+            46 => bayes::exec_train_manual(ctx).await,
             _ => unreachable!(),
         };
 
@@ -0,0 +1,142 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use sieve::{runtime::Variable, FunctionMap};
+use unicode_security::MixedScript;
+
+use crate::config::spamfilter::ObfuscationConfig;
+
+use super::PluginContext;
+
+pub fn register(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("subject_obfuscation", plugin_id, 1);
+}
+
+pub fn exec(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let subject = match &ctx.arguments[0] {
+        Variable::String(subject) if !subject.is_empty() => subject.to_string(),
+        _ => ctx.message.subject().unwrap_or_default().to_string(),
+    };
+
+    Ok(obfuscation_score(&subject, &ctx.server.core.spam.obfuscation).into())
+}
+
+pub fn obfuscation_score(text: &str, config: &ObfuscationConfig) -> f64 {
+    let mut score = 0.0;
+
+    // Explicit bidirectional overrides and isolates, implicit marks (LRM/RLM) are
+    // used by legitimate right-to-left text and are not taken into account.
+    if config.bidi > 0.0 && text.chars().any(is_bidi_control) {
+        score += config.bidi;
+    }
+
+    // Zero-width characters splitting Latin words, joiners are legitimate within
+    // emoji sequences and in scripts such as Arabic or Devanagari.
+    if config.zero_width > 0.0 {
+        let mut prev = None;
+        let mut chars = text.chars().peekable();
+        while let Some(ch) = chars.next() {
+            if is_zero_width(ch) {
+                if matches!(ch, '\u{00AD}' | '\u{200B}' | '\u{2060}' | '\u{FEFF}')
+                    || prev.is_some_and(|c: char| c.is_ascii_alphanumeric())
+                        && chars.peek().is_some_and(|c| c.is_ascii_alphanumeric())
+                {
+                    score += config.zero_width;
+                    break;
+                }
+            } else {
+                prev = Some(ch);
+            }
+        }
+    }
+
+    // Latin letters written using mathematical, fullwidth or enclosed forms
+    if config.styled > 0.0 && text.chars().any(is_styled_latin) {
+        score += config.styled;
+    }
+
+    // Words mixing scripts, such as Latin letters replaced with Cyrillic or Greek
+    // homoglyphs. Words written entirely in a single non-Latin script are not counted.
+    if config.homoglyph > 0.0 {
+        let mut words = 0;
+        let mut mixed_words = 0;
+        for word in text
+            .split(|c: char| c.is_whitespace() || c.is_ascii_punctuation())
+            .filter(|word| word.chars().count() > 1)
+        {
+            words += 1;
+            if !word.is_single_script() {
+                mixed_words += 1;
+            }
+        }
+        if mixed_words > 0 {
+            let density = mixed_words as f64 / words as f64;
+            if density >= config.homoglyph_min_density {
+                score += config.homoglyph * density;
+            }
+        }
+    }
+
+    score
+}
+
+fn is_bidi_control(ch: char) -> bool {
+    matches!(ch, '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+fn is_zero_width(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{00AD}' | '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{2060}' | '\u{FEFF}'
+    )
+}
+
+fn is_styled_latin(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{1D400}'..='\u{1D7FF}'
+            | '\u{FF10}'..='\u{FF19}'
+            | '\u{FF21}'..='\u{FF3A}'
+            | '\u{FF41}'..='\u{FF5A}'
+            | '\u{2460}'..='\u{24FF}'
+            | '\u{1F130}'..='\u{1F189}'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::spamfilter::ObfuscationConfig;
+
+    use super::obfuscation_score;
+
+    #[test]
+    fn subject_obfuscation() {
+        let config = ObfuscationConfig::default();
+
+        // Legitimate subjects, including non-Latin ones
+        for subject in [
+            "Your invoice for October",
+            "Счёт за октябрь",
+            "\u{200F}فاتورة شهر أكتوبر",
+            "मेरा नाम\u{200D}",
+            "Family trip 👨\u{200D}👩\u{200D}👧",
+            "東京の天気予報",
+        ] {
+            assert_eq!(obfuscation_score(subject, &config), 0.0, "{subject}");
+        }
+
+        // Obfuscated subjects
+        for (subject, expected) in [
+            ("Your \u{202E}eciovni\u{202C} is ready", config.bidi),
+            ("Fr\u{200B}ee gift card", config.zero_width),
+            ("Fr\u{200C}ee gift card", config.zero_width),
+            ("𝐅𝐑𝐄𝐄 gift card", config.styled),
+            ("Раypal account locked", config.homoglyph / 3.0),
+        ] {
+            assert_eq!(obfuscation_score(subject, &config), expected, "{subject}");
+        }
+    }
+}
@@ -0,0 +1,140 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use serde::{Deserialize, Serialize};
+use sieve::{runtime::Variable, FunctionMap};
+use store::{
+    write::{key::KeySerializer, Bincode},
+    Serialize as _, U64_LEN,
+};
+use trc::AddContext;
+
+use crate::config::spamfilter::OffenseConfig;
+
+use super::PluginContext;
+
+const OFFENSE_PREFIX: &[u8] = b"offense:";
+
+pub fn register(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("sender_offense", plugin_id, 3);
+}
+
+// Decayed number of messages from a sender that tripped the classifier
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Offenses {
+    pub count: f64,
+    pub updated: u64,
+}
+
+// Returns the escalation level of the sender (0 = none, 1 = tag, 2 = quarantine,
+// 3 = reject), recording an offense first when the third argument is true.
+pub async fn exec(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let store = match &ctx.arguments[0] {
+        Variable::String(v) if !v.is_empty() => ctx.server.core.storage.lookups.get(v.as_ref()),
+        _ => Some(&ctx.server.core.storage.lookup),
+    }
+    .ok_or_else(|| {
+        trc::SieveEvent::RuntimeError
+            .ctx(trc::Key::Id, ctx.arguments[0].to_string().into_owned())
+            .details("Unknown store")
+    })?;
+    let sender = ctx.arguments[1].to_string().trim().to_lowercase();
+    if sender.is_empty() {
+        return Ok(0.into());
+    }
+    let config = &ctx.server.core.spam.offense;
+    let key = KeySerializer::new(OFFENSE_PREFIX.len() + U64_LEN)
+        .write(OFFENSE_PREFIX)
+        .write(xxhash_rust::xxh3::xxh3_64(sender.as_bytes()))
+        .finalize();
+
+    let now = ctx.server.now();
+    let offenses = store
+        .key_get::<Bincode<Offenses>>(key.clone())
+        .await
+        .caused_by(trc::location!())?
+        .map(|offenses| offenses.inner.decay(config, now));
+
+    let offenses = if ctx.arguments[2].to_bool() {
+        let mut offenses = offenses.unwrap_or(Offenses {
+            count: 0.0,
+            updated: now,
+        });
+        offenses.count += 1.0;
+        store
+            .key_set(
+                key,
+                Bincode::new(offenses).serialize(),
+                config.expire.as_secs().into(),
+            )
+            .await
+            .caused_by(trc::location!())?;
+        offenses
+    } else if let Some(offenses) = offenses {
+        offenses
+    } else {
+        return Ok(0.into());
+    };
+
+    Ok((config.level(offenses.count) as i64).into())
+}
+
+impl Offenses {
+    pub fn decay(mut self, config: &OffenseConfig, now: u64) -> Self {
+        let half_life = config.half_life.as_secs();
+        if half_life > 0 && now > self.updated {
+            self.count *= 0.5f64.powf((now - self.updated) as f64 / half_life as f64);
+        }
+        self.updated = now;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::spamfilter::{OffenseConfig, OffenseLevel};
+
+    use super::Offenses;
+
+    #[test]
+    fn offense_escalation() {
+        let config = OffenseConfig::default();
+        let mut offenses = Offenses::default();
+        let mut levels = Vec::new();
+        for _ in 0..6 {
+            offenses.count += 1.0;
+            levels.push(config.level(offenses.count));
+        }
+        assert_eq!(
+            levels,
+            vec![
+                OffenseLevel::Tag,
+                OffenseLevel::Tag,
+                OffenseLevel::Quarantine,
+                OffenseLevel::Quarantine,
+                OffenseLevel::Quarantine,
+                OffenseLevel::Reject
+            ]
+        );
+
+        // Reformed senders return to normal treatment
+        let half_life = config.half_life.as_secs();
+        let offenses = offenses.decay(&config, half_life);
+        assert_eq!(offenses.count, 3.0);
+        assert_eq!(config.level(offenses.count), OffenseLevel::Quarantine);
+        let offenses = offenses.decay(&config, half_life * 4);
+        assert_eq!(config.level(offenses.count), OffenseLevel::None);
+
+        // Levels without a threshold are skipped
+        let config = OffenseConfig {
+            thresholds: [None, Some(2.0), Some(4.0)],
+            ..Default::default()
+        };
+        assert_eq!(config.level(1.0), OffenseLevel::None);
+        assert_eq!(config.level(2.0), OffenseLevel::Quarantine);
+        assert_eq!(config.level(10.0), OffenseLevel::Reject);
+    }
+}
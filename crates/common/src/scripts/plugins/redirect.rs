@@ -0,0 +1,238 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{net::IpAddr, time::Instant};
+
+use reqwest::{header::LOCATION, redirect::Policy, Url};
+use sieve::{runtime::Variable, FunctionMap};
+use store::LookupStore;
+use utils::map::ttl_dashmap::TtlMap;
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::config::spamfilter::RedirectConfig;
+
+use super::PluginContext;
+
+pub fn register(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("redirect_chain", plugin_id, 2);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedirectChain {
+    pub depth: u32,
+    pub domain: String,
+}
+
+// Returns an array containing the number of redirects found before reaching the
+// final destination of the URL, followed by the destination domain. Only links
+// served by the domains in the redirector list are resolved, either by following
+// the redirect (when enabled) or by extracting the target URL from the query.
+pub async fn exec(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let Ok(url) = Url::parse(ctx.arguments[0].to_string().trim()) else {
+        return Ok(Variable::default());
+    };
+    let store_id = ctx.arguments[1].to_string();
+    let redirectors = ctx
+        .server
+        .core
+        .storage
+        .lookups
+        .get(store_id.as_ref())
+        .ok_or_else(|| {
+            trc::SieveEvent::RuntimeError
+                .ctx(trc::Key::Id, store_id.to_string())
+                .details("Unknown store")
+        })?;
+    let config = &ctx.server.core.spam.redirect;
+
+    // Chains depend on the redirector list they were resolved with
+    let mut hasher = Xxh3::new();
+    hasher.update(store_id.as_bytes());
+    hasher.update(&[0]);
+    hasher.update(url.as_str().as_bytes());
+    let cache_key = hasher.digest128();
+
+    let chain = if let Some(chain) = ctx
+        .server
+        .inner
+        .data
+        .redirect_cache
+        .get_with_ttl(&cache_key)
+    {
+        chain
+    } else {
+        let chain = redirect_chain(config, redirectors, url).await?;
+        ctx.server.inner.data.redirect_cache.insert_with_ttl(
+            cache_key,
+            chain.clone(),
+            Instant::now() + config.cache_ttl,
+        );
+        chain
+    };
+
+    Ok(Variable::Array(
+        vec![Variable::from(chain.depth), Variable::from(chain.domain)].into(),
+    ))
+}
+
+async fn redirect_chain(
+    config: &RedirectConfig,
+    redirectors: &LookupStore,
+    mut url: Url,
+) -> trc::Result<RedirectChain> {
+    let mut depth = 0;
+    let mut client = None;
+
+    while depth < config.max_depth as u32 {
+        let Some(domain) = url_domain(&url) else {
+            break;
+        };
+        if !redirectors.key_exists(domain.into_bytes()).await? {
+            break;
+        }
+
+        let next = if config.follow {
+            // The client is shared by all the requests of the chain
+            if client.is_none() {
+                client = Some(
+                    reqwest::Client::builder()
+                        .user_agent(config.user_agent.as_str())
+                        .timeout(config.timeout)
+                        .redirect(Policy::none())
+                        .build()
+                        .map_err(|err| {
+                            trc::SpamEvent::ClassifyError
+                                .into_err()
+                                .details("Failed to build HTTP client")
+                                .reason(err)
+                        })?,
+                );
+            }
+            match &client {
+                Some(client) => follow(client, &url).await,
+                None => None,
+            }
+        } else {
+            embedded_url(&url)
+        };
+        match next {
+            Some(next) if next != url => {
+                url = next;
+                depth += 1;
+            }
+            _ => break,
+        }
+    }
+
+    Ok(RedirectChain {
+        depth,
+        domain: url_domain(&url).unwrap_or_default(),
+    })
+}
+
+// Requests the URL without following redirects, returning the redirect target
+async fn follow(client: &reqwest::Client, url: &Url) -> Option<Url> {
+    let time = Instant::now();
+    let response = client.head(url.clone()).send().await;
+
+    match response {
+        Ok(response) if response.status().is_redirection() => response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| url.join(location).ok())
+            .filter(is_http),
+        Ok(_) => None,
+        Err(err) => {
+            trc::event!(
+                Spam(trc::SpamEvent::ClassifyError),
+                Details = "Failed to follow redirect",
+                Url = url.to_string(),
+                Reason = err.to_string(),
+                Elapsed = time.elapsed(),
+            );
+            None
+        }
+    }
+}
+
+// Redirectors that carry the destination in the query string can be resolved offline
+pub fn embedded_url(url: &Url) -> Option<Url> {
+    url.query_pairs().find_map(|(_, value)| {
+        let value = value.trim();
+        if ["http://", "https://"].iter().any(|scheme| {
+            value
+                .get(..scheme.len())
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme))
+        }) {
+            Url::parse(value).ok().filter(is_http)
+        } else {
+            None
+        }
+    })
+}
+
+fn url_domain(url: &Url) -> Option<String> {
+    if !is_http(url) {
+        return None;
+    }
+    let host = url.host_str()?.trim_end_matches('.').to_lowercase();
+
+    // IPv6 hosts are enclosed in brackets
+    if host.starts_with('[') || host.parse::<IpAddr>().is_ok() {
+        Some(host)
+    } else {
+        Some(
+            psl::domain_str(&host)
+                .map(|domain| domain.to_string())
+                .unwrap_or(host),
+        )
+    }
+}
+
+fn is_http(url: &Url) -> bool {
+    matches!(url.scheme(), "http" | "https")
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+
+    use super::{embedded_url, url_domain};
+
+    #[test]
+    fn redirect_embedded_url() {
+        let url = |url: &str| Url::parse(url).unwrap();
+
+        assert_eq!(
+            embedded_url(&url(
+                "https://l.example.com/redirect?id=1&u=https%3A%2F%2Fbit.ly%2Fabc%3Fx%3D1"
+            )),
+            Some(url("https://bit.ly/abc?x=1"))
+        );
+        assert_eq!(
+            embedded_url(&url(
+                "https://www.google.com/url?q=http://Evil.Example.org/"
+            )),
+            Some(url("http://evil.example.org/"))
+        );
+        assert_eq!(embedded_url(&url("https://bit.ly/abc")), None);
+        assert_eq!(
+            embedded_url(&url("https://l.example.com/?u=javascript:alert(1)")),
+            None
+        );
+
+        assert_eq!(
+            url_domain(&url("https://WWW.Example.co.uk./path")),
+            Some("example.co.uk".to_string())
+        );
+        assert_eq!(
+            url_domain(&url("http://192.168.1.1/")),
+            Some("192.168.1.1".to_string())
+        );
+        assert_eq!(url_domain(&url("ftp://example.com/")), None);
+    }
+}
@@ -0,0 +1,171 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Instant;
+
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use sieve::{runtime::Variable, FunctionMap};
+use utils::map::ttl_dashmap::TtlMap;
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::{
+    config::spamfilter::{RemoteClassifierConfig, RemoteClassifierPolicy},
+    HttpLimitResponse, Server,
+};
+
+use super::{
+    bayes::{classify, text_argument},
+    PluginContext,
+};
+
+pub fn register(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("bayes_classify_remote", plugin_id, 3);
+}
+
+#[derive(Debug, Serialize)]
+struct RemoteClassifyRequest<'x> {
+    model: &'x str,
+    text: &'x str,
+}
+
+#[derive(Debug, Deserialize)]
+struct RemoteClassifyResponse {
+    score: f64,
+}
+
+// Returns an array containing the score and the classifier that produced it
+// ("remote", "local" or "both"), or an empty value if none did.
+pub async fn exec(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let Some(config) = &ctx.server.core.spam.remote else {
+        return classify(&ctx).await.map(|score| verdict(score, "local"));
+    };
+    let model_id = ctx.arguments[0].to_string();
+    let Some(text) = text_argument(&ctx, &ctx.arguments[1]) else {
+        return Ok(Variable::default());
+    };
+
+    Ok(match config.policy {
+        RemoteClassifierPolicy::PreferRemote => {
+            match ctx.server.remote_classify(config, &model_id, &text).await {
+                Some(score) => verdict(Some(score), "remote"),
+                None => verdict(classify(&ctx).await?, "local"),
+            }
+        }
+        RemoteClassifierPolicy::PreferLocal => match classify(&ctx).await? {
+            Some(score) => verdict(Some(score), "local"),
+            None => verdict(
+                ctx.server.remote_classify(config, &model_id, &text).await,
+                "remote",
+            ),
+        },
+        RemoteClassifierPolicy::Average => {
+            let (remote, local) = tokio::join!(
+                ctx.server.remote_classify(config, &model_id, &text),
+                classify(&ctx)
+            );
+            match (remote, local?) {
+                (Some(remote), Some(local)) => verdict(Some((remote + local) / 2.0), "both"),
+                (Some(remote), None) => verdict(Some(remote), "remote"),
+                (None, local) => verdict(local, "local"),
+            }
+        }
+    })
+}
+
+fn verdict(score: Option<f64>, source: &'static str) -> Variable {
+    match score {
+        Some(score) => Variable::Array(vec![score.into(), source.into()].into()),
+        None => Variable::default(),
+    }
+}
+
+impl Server {
+    pub async fn remote_classify(
+        &self,
+        config: &RemoteClassifierConfig,
+        model_id: &str,
+        text: &str,
+    ) -> Option<f64> {
+        let mut hasher = Xxh3::new();
+        hasher.update(model_id.as_bytes());
+        hasher.update(&[0]);
+        hasher.update(text.as_bytes());
+        let cache_key = hasher.digest128();
+
+        if let Some(score) = self
+            .inner
+            .data
+            .remote_classify_cache
+            .get_with_ttl(&cache_key)
+        {
+            return Some(score);
+        }
+
+        let time = Instant::now();
+        match send_request(config, model_id, text).await {
+            Ok(score) => {
+                self.inner.data.remote_classify_cache.insert_with_ttl(
+                    cache_key,
+                    score,
+                    Instant::now() + config.cache_ttl,
+                );
+                Some(score)
+            }
+            Err(err) => {
+                trc::event!(
+                    Spam(trc::SpamEvent::ClassifyError),
+                    Details = "Remote classifier unavailable",
+                    Url = config.url.clone(),
+                    Reason = err,
+                    Elapsed = time.elapsed(),
+                );
+                None
+            }
+        }
+    }
+}
+
+async fn send_request(
+    config: &RemoteClassifierConfig,
+    model_id: &str,
+    text: &str,
+) -> Result<f64, String> {
+    let mut request = reqwest::Client::builder()
+        .timeout(config.timeout)
+        .build()
+        .map_err(|err| format!("Failed to build request: {err}"))?
+        .post(&config.url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(
+            serde_json::to_vec(&RemoteClassifyRequest {
+                model: model_id,
+                text,
+            })
+            .map_err(|err| format!("Failed to serialize request: {err}"))?,
+        );
+    if let Some(token) = &config.auth_token {
+        request = request.bearer_auth(token);
+    }
+
+    let response = request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| format!("Request failed: {err}"))?
+        .bytes_with_limit(config.max_size)
+        .await
+        .map_err(|err| format!("Failed to read response: {err}"))?
+        .ok_or_else(|| format!("Response exceeds {} bytes", config.max_size))?;
+    let response = serde_json::from_slice::<RemoteClassifyResponse>(&response)
+        .map_err(|err| format!("Invalid response: {err}"))?;
+
+    if (0.0..=1.0).contains(&response.score) {
+        Ok(response.score)
+    } else {
+        Err(format!("Score {} out of range", response.score))
+    }
+}
@@ -0,0 +1,130 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use sieve::{runtime::Variable, FunctionMap};
+
+use super::{
+    bayes::{classify_outcome, OutcomeToken},
+    PluginContext,
+};
+
+// Tokens the spammy and hammy tokens are selected from, as many as the most
+// significant tokens SpamAssassin combines
+const SIGNIFICANT_TOKENS: usize = 150;
+
+// SpamAssassin Bayes rules, from the lowest probability each rule applies to
+const BAYES_RULES: [(f64, &str, &str); 10] = [
+    (0.999, "BAYES_999", "99.9 to 100%"),
+    (0.99, "BAYES_99", "99 to 100%"),
+    (0.95, "BAYES_95", "95 to 99%"),
+    (0.8, "BAYES_80", "80 to 95%"),
+    (0.6, "BAYES_60", "60 to 80%"),
+    (0.4, "BAYES_50", "40 to 60%"),
+    (0.2, "BAYES_40", "20 to 40%"),
+    (0.05, "BAYES_20", "5 to 20%"),
+    (0.01, "BAYES_05", "1 to 5%"),
+    (0.0, "BAYES_00", "0 to 1%"),
+];
+
+pub fn register(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("bayes_sa_report", plugin_id, 3);
+}
+
+// Classifies the text as bayes_classify does and returns a report in the style of
+// the SpamAssassin Bayes diagnostics: the matching BAYES_* rule with the score,
+// followed by the spammy and hammy tokens (as many as the model top tokens) in the
+// long token format "probability-hits--{ham}h-{spam}s--token". Lines are folded,
+// so the report can be added as a header as it is. Returns empty when no verdict
+// was reached.
+pub async fn exec(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let max_tokens = ctx
+        .server
+        .core
+        .spam
+        .bayes
+        .model(ctx.arguments[0].to_string().as_ref())
+        .top_tokens;
+    let outcome = classify_outcome(&ctx, SIGNIFICANT_TOKENS).await?;
+
+    Ok(outcome
+        .score
+        .map(|score| Variable::from(sa_report(score, &outcome.tokens, max_tokens)))
+        .unwrap_or_default())
+}
+
+fn sa_report(score: f64, tokens: &[OutcomeToken], max_tokens: usize) -> String {
+    let (rule, range) = BAYES_RULES
+        .iter()
+        .find(|(min, _, _)| score >= *min)
+        .map(|(_, rule, range)| (*rule, *range))
+        .unwrap_or(("BAYES_00", "0 to 1%"));
+    let spammy = tokens
+        .iter()
+        .filter(|token| token.probability > 0.5)
+        .take(max_tokens);
+    let hammy = tokens
+        .iter()
+        .filter(|token| token.probability < 0.5)
+        .take(max_tokens);
+
+    format!(
+        "{rule} Bayes spam probability is {range}, score={score:.4}\r\n\tspammy: {}\r\n\thammy: {}",
+        format_tokens(spammy),
+        format_tokens(hammy)
+    )
+}
+
+fn format_tokens<'x>(tokens: impl Iterator<Item = &'x OutcomeToken>) -> String {
+    tokens
+        .map(|token| {
+            format!(
+                "{:.3}-{}--{}h-{}s--{}",
+                token.probability,
+                token.spam + token.ham,
+                token.ham,
+                token.spam,
+                token.text
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scripts::plugins::bayes::OutcomeToken;
+
+    use super::sa_report;
+
+    #[test]
+    fn bayes_sa_report() {
+        let token = |text: &str, spam, ham, probability| OutcomeToken {
+            text: text.to_string(),
+            spam,
+            ham,
+            probability,
+            sources: Vec::new(),
+        };
+        let tokens = [
+            token("viagra", 40, 0, 1.0),
+            token("meeting", 1, 30, 0.032),
+            token("cheap pill", 12, 1, 0.9231),
+            token("unsubscribe", 9, 2, 0.8),
+        ];
+
+        assert_eq!(
+            sa_report(0.99812, &tokens, 2),
+            concat!(
+                "BAYES_99 Bayes spam probability is 99 to 100%, score=0.9981\r\n",
+                "\tspammy: 1.000-40--0h-40s--viagra, 0.923-13--1h-12s--cheap pill\r\n",
+                "\thammy: 0.032-31--30h-1s--meeting"
+            )
+        );
+        assert!(sa_report(0.5, &[], 5).starts_with("BAYES_50 "));
+        assert!(sa_report(0.0, &[], 5).starts_with("BAYES_00 "));
+        assert!(sa_report(0.9995, &[], 5).starts_with("BAYES_999 "));
+    }
+}
@@ -0,0 +1,165 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use serde::{Deserialize, Serialize};
+use sieve::{runtime::Variable, FunctionMap};
+use store::{
+    write::{key::KeySerializer, Bincode},
+    Serialize as _, U64_LEN,
+};
+use trc::AddContext;
+
+use crate::config::spamfilter::SendingPatternConfig;
+
+use super::PluginContext;
+
+const PATTERN_PREFIX: &[u8] = b"sending:";
+const HOURS: usize = 24;
+
+pub fn register(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("sending_anomaly", plugin_id, 2);
+}
+
+// Decayed number of messages sent by a sender during each hour of the day (UTC)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SendingPattern {
+    pub hours: [f32; HOURS],
+    pub updated: u64,
+}
+
+// Returns the anomaly score (0.0 - 1.0) of the current time for the sender and
+// records the message in the sender's pattern.
+pub async fn exec(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let store = match &ctx.arguments[0] {
+        Variable::String(v) if !v.is_empty() => ctx.server.core.storage.lookups.get(v.as_ref()),
+        _ => Some(&ctx.server.core.storage.lookup),
+    }
+    .ok_or_else(|| {
+        trc::SieveEvent::RuntimeError
+            .ctx(trc::Key::Id, ctx.arguments[0].to_string().into_owned())
+            .details("Unknown store")
+    })?;
+    let sender = ctx.arguments[1].to_string().trim().to_lowercase();
+    if sender.is_empty() {
+        return Ok(0.0.into());
+    }
+    let config = &ctx.server.core.spam.sending_pattern;
+    let key = KeySerializer::new(PATTERN_PREFIX.len() + U64_LEN)
+        .write(PATTERN_PREFIX)
+        .write(xxhash_rust::xxh3::xxh3_64(sender.as_bytes()))
+        .finalize();
+
+    let now = ctx.server.now();
+    let mut pattern = store
+        .key_get::<Bincode<SendingPattern>>(key.clone())
+        .await
+        .caused_by(trc::location!())?
+        .map(|pattern| pattern.inner.decay(config, now))
+        .unwrap_or(SendingPattern {
+            updated: now,
+            ..Default::default()
+        });
+    let hour = hour_of_day(now);
+    let score = pattern.anomaly(config, hour);
+
+    pattern.hours[hour] += 1.0;
+    store
+        .key_set(
+            key,
+            Bincode::new(pattern).serialize(),
+            config.expire.as_secs().into(),
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+    Ok(score.into())
+}
+
+fn hour_of_day(timestamp: u64) -> usize {
+    ((timestamp % 86400) / 3600) as usize
+}
+
+impl SendingPattern {
+    pub fn decay(mut self, config: &SendingPatternConfig, now: u64) -> Self {
+        let half_life = config.half_life.as_secs();
+        if half_life > 0 && now > self.updated {
+            let factor = 0.5f64.powf((now - self.updated) as f64 / half_life as f64) as f32;
+            for count in &mut self.hours {
+                *count *= factor;
+            }
+        }
+        self.updated = now;
+        self
+    }
+
+    pub fn total(&self) -> f64 {
+        self.hours.iter().map(|count| *count as f64).sum()
+    }
+
+    // Compares the activity around the given hour with the busiest hour of the sender,
+    // senders without enough history are never considered anomalous.
+    pub fn anomaly(&self, config: &SendingPatternConfig, hour: usize) -> f64 {
+        let total = self.total();
+        if total < config.min_messages {
+            return 0.0;
+        }
+
+        // Adjacent hours are included to tolerate small shifts in the sending time
+        let activity = |hour: usize| {
+            self.hours[hour] as f64
+                + 0.5
+                    * (self.hours[(hour + HOURS - 1) % HOURS] as f64
+                        + self.hours[(hour + 1) % HOURS] as f64)
+        };
+        let peak = (0..HOURS).map(activity).fold(0.0, f64::max);
+        if peak <= 0.0 {
+            return 0.0;
+        }
+
+        // Confidence grows with the amount of history
+        let confidence = total / (total + config.min_messages);
+        ((1.0 - activity(hour) / peak) * confidence).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::config::spamfilter::SendingPatternConfig;
+
+    use super::{hour_of_day, SendingPattern};
+
+    #[test]
+    fn sending_pattern() {
+        let config = SendingPatternConfig {
+            half_life: Duration::from_secs(86400),
+            min_messages: 10.0,
+            expire: Duration::from_secs(86400),
+        };
+        assert_eq!(hour_of_day(86400 + 3 * 3600 + 59), 3);
+
+        // Business hours sender
+        let mut pattern = SendingPattern::default();
+        for hour in 9..17 {
+            pattern.hours[hour] = 10.0;
+        }
+        assert_eq!(pattern.anomaly(&config, 12), 0.0);
+        assert!(pattern.anomaly(&config, 3) > 0.85);
+        assert!(pattern.anomaly(&config, 8) < pattern.anomaly(&config, 7));
+
+        // Not enough history
+        let mut new_sender = SendingPattern::default();
+        new_sender.hours[12] = 5.0;
+        assert_eq!(new_sender.anomaly(&config, 3), 0.0);
+
+        // Counts lose half their weight after each half-life
+        let decayed = pattern.decay(&config, 86400);
+        assert_eq!(decayed.hours[9], 5.0);
+        assert_eq!(decayed.updated, 86400);
+        assert!(decayed.anomaly(&config, 3) < pattern.anomaly(&config, 3));
+    }
+}
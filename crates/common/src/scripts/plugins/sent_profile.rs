@@ -0,0 +1,257 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use nlp::bayes::tokenize::BayesTokenizer;
+use serde::{Deserialize, Serialize};
+use sieve::{runtime::Variable, FunctionMap};
+use store::{
+    write::{key::KeySerializer, Bincode},
+    LookupStore, Serialize as _, U64_LEN,
+};
+use trc::AddContext;
+
+use crate::config::spamfilter::SentProfileConfig;
+
+use super::PluginContext;
+
+const PROFILE_PREFIX: &[u8] = b"sent:";
+const DIMENSIONS: usize = 256;
+
+pub fn register_update(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("sent_profile_update", plugin_id, 3);
+}
+
+pub fn register_similarity(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("sent_similarity", plugin_id, 3);
+}
+
+// Decayed sum of the normalized word vectors of the messages sent by a user,
+// words are hashed into a fixed number of dimensions to keep profiles small.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SentProfile {
+    pub vector: Vec<f32>,
+    pub messages: f32,
+    pub updated: u64,
+}
+
+// Adds the text of a message sent by the user to the user's profile
+pub async fn exec_update(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let store = profile_store(&ctx)?;
+    let Some(user) = user_argument(&ctx.arguments[1]) else {
+        return Ok(false.into());
+    };
+    let Some(vector) = text_vector(ctx.arguments[2].to_string().as_ref()) else {
+        return Ok(false.into());
+    };
+    let config = &ctx.server.core.spam.sent_profile;
+    let now = ctx.server.now();
+    let key = profile_key(&user);
+
+    let mut profile = store
+        .key_get::<Bincode<SentProfile>>(key.clone())
+        .await
+        .caused_by(trc::location!())?
+        .map(|profile| profile.inner.decay(config, now))
+        .unwrap_or_else(|| SentProfile::new(now));
+    profile.add(&vector);
+    store
+        .key_set(
+            key,
+            Bincode::new(profile).serialize(),
+            config.expire.as_secs().into(),
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+    Ok(true.into())
+}
+
+// Returns the similarity (0.0 - 1.0) of the text to the messages sent by the user
+pub async fn exec_similarity(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let store = profile_store(&ctx)?;
+    let (Some(user), Some(vector)) = (
+        user_argument(&ctx.arguments[1]),
+        text_vector(ctx.arguments[2].to_string().as_ref()),
+    ) else {
+        return Ok(0.0.into());
+    };
+
+    Ok(store
+        .key_get::<Bincode<SentProfile>>(profile_key(&user))
+        .await
+        .caused_by(trc::location!())?
+        .map_or(0.0, |profile| {
+            profile
+                .inner
+                .decay(&ctx.server.core.spam.sent_profile, ctx.server.now())
+                .similarity(&ctx.server.core.spam.sent_profile, &vector)
+        })
+        .into())
+}
+
+fn profile_store<'x>(ctx: &PluginContext<'x>) -> trc::Result<&'x LookupStore> {
+    match &ctx.arguments[0] {
+        Variable::String(v) if !v.is_empty() => ctx.server.core.storage.lookups.get(v.as_ref()),
+        _ => Some(&ctx.server.core.storage.lookup),
+    }
+    .ok_or_else(|| {
+        trc::SieveEvent::RuntimeError
+            .ctx(trc::Key::Id, ctx.arguments[0].to_string().into_owned())
+            .details("Unknown store")
+    })
+}
+
+// Profiles are only looked up for messages addressed to a single user
+fn user_argument(value: &Variable) -> Option<String> {
+    match value {
+        Variable::Array(users) if users.len() == 1 => user_argument(&users[0]),
+        Variable::Array(_) => None,
+        value => Some(value.to_string().trim().to_lowercase()).filter(|user| !user.is_empty()),
+    }
+}
+
+fn profile_key(user: &str) -> Vec<u8> {
+    KeySerializer::new(PROFILE_PREFIX.len() + U64_LEN)
+        .write(PROFILE_PREFIX)
+        .write(xxhash_rust::xxh3::xxh3_64(user.as_bytes()))
+        .finalize()
+}
+
+// Returns the L2 normalized hashed word vector of the text
+pub fn text_vector(text: &str) -> Option<[f32; DIMENSIONS]> {
+    let mut vector = [0.0f32; DIMENSIONS];
+    for word in BayesTokenizer::new(text) {
+        vector[(xxhash_rust::xxh3::xxh3_64(word.as_bytes()) % DIMENSIONS as u64) as usize] += 1.0;
+    }
+
+    // Sublinear term frequencies, so repeated words do not dominate
+    let mut norm = 0.0;
+    for value in &mut vector {
+        if *value > 0.0 {
+            *value = 1.0 + value.ln();
+            norm += *value * *value;
+        }
+    }
+    if norm == 0.0 {
+        return None;
+    }
+    let norm = norm.sqrt();
+    for value in &mut vector {
+        *value /= norm;
+    }
+
+    Some(vector)
+}
+
+impl SentProfile {
+    pub fn new(now: u64) -> Self {
+        SentProfile {
+            vector: vec![0.0; DIMENSIONS],
+            messages: 0.0,
+            updated: now,
+        }
+    }
+
+    pub fn decay(mut self, config: &SentProfileConfig, now: u64) -> Self {
+        // Profiles stored with a different number of dimensions are discarded
+        if self.vector.len() != DIMENSIONS {
+            return SentProfile::new(now);
+        }
+
+        let half_life = config.half_life.as_secs();
+        if half_life > 0 && now > self.updated {
+            let factor = 0.5f64.powf((now - self.updated) as f64 / half_life as f64) as f32;
+            for value in &mut self.vector {
+                *value *= factor;
+            }
+            self.messages *= factor;
+        }
+        self.updated = now;
+        self
+    }
+
+    pub fn add(&mut self, vector: &[f32; DIMENSIONS]) {
+        for (value, add) in self.vector.iter_mut().zip(vector) {
+            *value += add;
+        }
+        self.messages += 1.0;
+    }
+
+    // Cosine similarity with the profile, users without enough sent messages
+    // have a proportionally lower similarity.
+    pub fn similarity(&self, config: &SentProfileConfig, vector: &[f32; DIMENSIONS]) -> f64 {
+        let messages = self.messages as f64;
+        if messages <= 0.0 || self.vector.len() != DIMENSIONS {
+            return 0.0;
+        }
+
+        let mut dot = 0.0f64;
+        let mut norm = 0.0f64;
+        for (value, other) in self.vector.iter().zip(vector) {
+            dot += *value as f64 * *other as f64;
+            norm += *value as f64 * *value as f64;
+        }
+        if norm <= 0.0 {
+            return 0.0;
+        }
+
+        let confidence = messages / (messages + config.min_messages);
+        (dot / norm.sqrt() * confidence).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::config::spamfilter::SentProfileConfig;
+
+    use super::{text_vector, SentProfile};
+
+    #[test]
+    fn sent_profile() {
+        let config = SentProfileConfig {
+            half_life: Duration::from_secs(86400),
+            min_messages: 1.0,
+            expire: Duration::from_secs(86400),
+        };
+
+        let mut profile = SentProfile::new(0);
+        for text in [
+            "Hi team, the quarterly budget review meeting is moved to Thursday",
+            "Please review the budget spreadsheet before the meeting on Thursday",
+            "Thanks for the review, the quarterly meeting notes are attached",
+        ] {
+            profile.add(&text_vector(text).unwrap());
+        }
+
+        let similar = profile.similarity(
+            &config,
+            &text_vector("Can we review the quarterly budget at the Thursday meeting?").unwrap(),
+        );
+        let unrelated = profile.similarity(
+            &config,
+            &text_vector("Congratulations! Claim your free cruise prize now, limited offer")
+                .unwrap(),
+        );
+        assert!(similar > 0.4, "{similar}");
+        assert!(unrelated < similar / 2.0, "{unrelated} {similar}");
+        assert_eq!(text_vector(""), None);
+
+        // Old profiles lose weight, reducing the confidence
+        let decayed = profile.clone().decay(&config, 86400);
+        assert_eq!(decayed.messages, 1.5);
+        assert!(
+            decayed.similarity(
+                &config,
+                &text_vector("quarterly budget review meeting").unwrap()
+            ) < profile.similarity(
+                &config,
+                &text_vector("quarterly budget review meeting").unwrap()
+            )
+        );
+    }
+}
@@ -0,0 +1,227 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use mail_parser::Message;
+use sieve::{runtime::Variable, FunctionMap};
+
+use crate::scripts::functions::html::{get_attribute, html_to_tokens};
+
+use super::PluginContext;
+
+// Images up to this size (in pixels) on both dimensions are considered pixels
+const MAX_PIXEL_SIZE: u32 = 2;
+
+pub fn register(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("tracking_pixels", plugin_id, 2);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteImage {
+    pub host: String,
+    pub is_pixel: bool,
+}
+
+// Returns an array containing the total number of tracking images, followed by
+// the number of first-party and third-party ones. An image is a tracker when it
+// is a hidden or tiny remote image, or when it is served by a domain included in
+// the tracker list (pass an empty list id to only detect pixels).
+pub async fn exec(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    let sender_domain = ctx.arguments[0].to_string().to_lowercase();
+    let sender_sld = psl::domain_str(&sender_domain).unwrap_or(sender_domain.as_str());
+    let trackers = match &ctx.arguments[1] {
+        Variable::String(v) if !v.is_empty() => Some(
+            ctx.server
+                .core
+                .storage
+                .lookups
+                .get(v.as_ref())
+                .ok_or_else(|| {
+                    trc::SieveEvent::RuntimeError
+                        .ctx(trc::Key::Id, v.to_string())
+                        .details("Unknown store")
+                })?,
+        ),
+        _ => None,
+    };
+
+    let mut first_party = 0;
+    let mut third_party = 0;
+    for image in remote_images(ctx.message) {
+        let host_sld = psl::domain_str(&image.host).unwrap_or(image.host.as_str());
+        let is_tracker = image.is_pixel
+            || match trackers {
+                Some(trackers) => trackers.key_exists(host_sld.as_bytes().to_vec()).await?,
+                None => false,
+            };
+
+        if is_tracker {
+            if host_sld == sender_sld {
+                first_party += 1;
+            } else {
+                third_party += 1;
+            }
+        }
+    }
+
+    Ok(Variable::Array(
+        vec![
+            Variable::from(first_party + third_party),
+            Variable::from(first_party),
+            Variable::from(third_party),
+        ]
+        .into(),
+    ))
+}
+
+// Returns the images loaded from remote hosts in the HTML parts of the message
+pub fn remote_images(message: &Message<'_>) -> Vec<RemoteImage> {
+    let mut images = Vec::new();
+
+    for part in message.html_bodies() {
+        let Some(html) = part.text_contents() else {
+            continue;
+        };
+
+        for token in html_to_tokens(html) {
+            let Variable::String(tag) = token else {
+                continue;
+            };
+            if !tag.starts_with("<img ") {
+                continue;
+            }
+
+            let Some(host) = get_attribute(&tag, "src").and_then(remote_host) else {
+                continue;
+            };
+            let is_tiny = matches!(
+                (
+                    get_attribute(&tag, "width").and_then(parse_size),
+                    get_attribute(&tag, "height").and_then(parse_size),
+                ),
+                (Some(width), Some(height)) if width <= MAX_PIXEL_SIZE && height <= MAX_PIXEL_SIZE
+            );
+
+            images.push(RemoteImage {
+                host,
+                is_pixel: is_tiny || get_attribute(&tag, "style").is_some_and(is_hidden_style),
+            });
+        }
+    }
+
+    images
+}
+
+fn remote_host(src: &str) -> Option<String> {
+    let src = src.trim();
+    let rest = ["https://", "http://", "//"].iter().find_map(|scheme| {
+        src.get(..scheme.len())
+            .filter(|prefix| prefix.eq_ignore_ascii_case(scheme))
+            .map(|_| &src[scheme.len()..])
+    })?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host)
+        .split(':')
+        .next()?
+        .trim_end_matches('.');
+
+    if !host.is_empty() {
+        Some(host.to_lowercase())
+    } else {
+        None
+    }
+}
+
+fn parse_size(value: &str) -> Option<u32> {
+    let value = value.trim();
+    let value = value
+        .get(value.len().saturating_sub(2)..)
+        .filter(|suffix| suffix.eq_ignore_ascii_case("px"))
+        .map_or(value, |_| &value[..value.len() - 2]);
+    value.trim().parse().ok()
+}
+
+// Inline styles used to hide beacons or shrink them to a single pixel
+fn is_hidden_style(style: &str) -> bool {
+    let mut width = None;
+    let mut height = None;
+
+    for declaration in style.split(';') {
+        let Some((property, value)) = declaration.split_once(':') else {
+            continue;
+        };
+        let property = property.trim().to_ascii_lowercase();
+        let value = value
+            .trim()
+            .trim_end_matches("!important")
+            .trim()
+            .to_ascii_lowercase();
+
+        match property.as_str() {
+            "display" if value == "none" => return true,
+            "visibility" if value == "hidden" => return true,
+            "opacity" if value.parse::<f32>().is_ok_and(|opacity| opacity == 0.0) => return true,
+            "width" | "max-width" => width = parse_size(&value).or(width),
+            "height" | "max-height" => height = parse_size(&value).or(height),
+            _ => {}
+        }
+    }
+
+    matches!((width, height), (Some(width), Some(height)) if width <= MAX_PIXEL_SIZE && height <= MAX_PIXEL_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use mail_parser::MessageParser;
+
+    use super::{remote_images, RemoteImage};
+
+    #[test]
+    fn tracking_pixels() {
+        let message = MessageParser::new()
+            .parse(concat!(
+                "From: news@example.com\r\n",
+                "Content-Type: text/html\r\n\r\n",
+                "<html><body><p>Hello</p>",
+                "<img src=\"https://www.example.com/logo.png\" width=\"200\" height=\"80\">",
+                "<img src=\"https://t.example.com/open?id=1\" width=\"1\" height=\"1\">",
+                "<img src=\"http://tracker.net:8080/p.gif\" style=\"width: 1px; height: 1px\">",
+                "<img src=\"//cdn.beacon.io/b.gif\" style=\"display:none\">",
+                "<IMG SRC=\"HTTPS://Ads.Example.Org/banner.png\">",
+                "<img src=\"cid:inline@example.com\" width=\"1\" height=\"1\">",
+                "<img src=\"data:image/gif;base64,R0lGOD\" width=\"0\" height=\"0\">",
+                "</body></html>"
+            ))
+            .unwrap();
+
+        assert_eq!(
+            remote_images(&message),
+            vec![
+                RemoteImage {
+                    host: "www.example.com".into(),
+                    is_pixel: false
+                },
+                RemoteImage {
+                    host: "t.example.com".into(),
+                    is_pixel: true
+                },
+                RemoteImage {
+                    host: "tracker.net".into(),
+                    is_pixel: true
+                },
+                RemoteImage {
+                    host: "cdn.beacon.io".into(),
+                    is_pixel: true
+                },
+                RemoteImage {
+                    host: "ads.example.org".into(),
+                    is_pixel: false
+                },
+            ]
+        );
+    }
+}
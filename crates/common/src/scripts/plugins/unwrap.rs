@@ -0,0 +1,116 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use mail_parser::{Message, PartType};
+use sieve::{runtime::Variable, FunctionMap};
+
+use crate::config::spamfilter::UnwrapConfig;
+
+use super::PluginContext;
+
+pub fn register(plugin_id: u32, fnc_map: &mut FunctionMap) {
+    fnc_map.set_external_function("wrapped_messages", plugin_id, 0);
+}
+
+// Returns an array with the subject and text of each message forwarded as an
+// attachment, so they can be classified separately from the outer message.
+pub fn exec(ctx: PluginContext<'_>) -> trc::Result<Variable> {
+    Ok(Variable::Array(
+        wrapped_messages(ctx.message, &ctx.server.core.spam.unwrap)
+            .into_iter()
+            .map(Variable::from)
+            .collect::<Vec<_>>()
+            .into(),
+    ))
+}
+
+pub fn wrapped_messages(message: &Message<'_>, config: &UnwrapConfig) -> Vec<String> {
+    let mut texts = Vec::new();
+    let mut stack = vec![(message, 0)];
+
+    // Depth first, nested messages beyond the maximum depth are ignored
+    while let Some((message, depth)) = stack.pop() {
+        if depth > 0 {
+            let mut text = message.subject().unwrap_or_default().to_string();
+            for pos in 0..message.text_body.len() {
+                if let Some(body) = message.body_text(pos) {
+                    text.push(' ');
+                    text.push_str(body.as_ref());
+                }
+            }
+            texts.push(text);
+            if texts.len() >= config.max_messages {
+                break;
+            }
+        }
+
+        if depth < config.max_depth {
+            for part in message.parts.iter().rev() {
+                if let PartType::Message(inner) = &part.body {
+                    stack.push((inner, depth + 1));
+                }
+            }
+        }
+    }
+
+    texts
+}
+
+#[cfg(test)]
+mod tests {
+    use mail_parser::MessageParser;
+
+    use crate::config::spamfilter::UnwrapConfig;
+
+    use super::wrapped_messages;
+
+    #[test]
+    fn unwrap_messages() {
+        let message = MessageParser::new()
+            .parse(concat!(
+                "From: jdoe@example.com\r\n",
+                "Subject: Fwd: look at this\r\n",
+                "Content-Type: multipart/mixed; boundary=\"outer\"\r\n\r\n",
+                "--outer\r\n",
+                "Content-Type: text/plain\r\n\r\n",
+                "See the attached message.\r\n",
+                "--outer\r\n",
+                "Content-Type: message/rfc822\r\n\r\n",
+                "Subject: Claim your prize\r\n",
+                "Content-Type: multipart/mixed; boundary=\"inner\"\r\n\r\n",
+                "--inner\r\n",
+                "Content-Type: text/plain\r\n\r\n",
+                "You won a free cruise.\r\n",
+                "--inner\r\n",
+                "Content-Type: message/rfc822\r\n\r\n",
+                "Subject: Nested offer\r\n\r\n",
+                "Cheap pills online.\r\n",
+                "--inner--\r\n",
+                "--outer--\r\n",
+            ))
+            .unwrap();
+
+        let config = UnwrapConfig {
+            max_depth: 3,
+            max_messages: 5,
+        };
+        let texts = wrapped_messages(&message, &config);
+        assert_eq!(texts.len(), 2);
+        assert!(texts[0].starts_with("Claim your prize"), "{texts:?}");
+        assert!(texts[0].contains("You won a free cruise."), "{texts:?}");
+        assert!(texts[1].starts_with("Nested offer"), "{texts:?}");
+        assert!(texts[1].contains("Cheap pills online."), "{texts:?}");
+
+        // Recursion and the number of unwrapped messages are bounded
+        for (max_depth, max_messages, expected) in [(1, 5, 1), (3, 1, 1), (0, 5, 0)] {
+            let config = UnwrapConfig {
+                max_depth,
+                max_messages,
+            };
+            assert_eq!(wrapped_messages(&message, &config).len(), expected);
+        }
+    }
+}
@@ -6,6 +6,7 @@
 
 pub mod otel;
 pub mod prometheus;
+pub mod statsd;
 
 #[cfg(feature = "enterprise")]
 pub mod store;
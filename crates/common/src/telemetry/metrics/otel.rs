@@ -6,12 +6,12 @@
 
 use std::time::SystemTime;
 
-use opentelemetry::global::set_error_handler;
+use opentelemetry::{global::set_error_handler, KeyValue};
 use opentelemetry_sdk::metrics::data::{
     DataPoint, Gauge, Histogram, HistogramDataPoint, Metric, ResourceMetrics, ScopeMetrics, Sum,
     Temporality,
 };
-use trc::{Collector, TelemetryEvent};
+use trc::{atomics::hdr::EXPORTED_QUANTILES, Collector, TelemetryEvent};
 
 use crate::config::telemetry::OtelMetrics;
 
@@ -82,6 +82,29 @@ impl OtelMetrics {
             });
         }
 
+        // Add percentiles
+        for histogram in Collector::collect_percentiles() {
+            metrics.push(Metric {
+                name: histogram.id().name().into(),
+                description: histogram.id().description().into(),
+                unit: histogram.id().unit().into(),
+                data: Box::new(Gauge {
+                    data_points: EXPORTED_QUANTILES
+                        .iter()
+                        .filter_map(|quantile| {
+                            Some(DataPoint {
+                                attributes: vec![KeyValue::new("quantile", quantile.to_string())],
+                                start_time: start_time.into(),
+                                time: now.into(),
+                                value: histogram.percentile(*quantile)?,
+                                exemplars: vec![],
+                            })
+                        })
+                        .collect(),
+                }),
+            });
+        }
+
         // Export metrics
         if let Err(err) = self
             .exporter
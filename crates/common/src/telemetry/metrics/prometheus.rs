@@ -5,10 +5,18 @@
  */
 
 use prometheus::{
-    proto::{Bucket, Counter, Gauge, Histogram, Metric, MetricFamily, MetricType},
+    proto::{
+        Bucket, Counter, Gauge, Histogram, Metric, MetricFamily, MetricType, Quantile, Summary,
+    },
     TextEncoder,
 };
-use trc::{atomics::histogram::AtomicHistogram, Collector};
+use trc::{
+    atomics::{
+        hdr::{AtomicHdrHistogram, EXPORTED_QUANTILES},
+        histogram::AtomicHistogram,
+    },
+    Collector,
+};
 
 use crate::Server;
 
@@ -52,6 +60,16 @@ impl Server {
             metrics.push(metric);
         }
 
+        // Add percentiles
+        for histogram in Collector::collect_percentiles() {
+            let mut metric = MetricFamily::default();
+            metric.set_name(metric_name(histogram.id().name()));
+            metric.set_help(histogram.id().description().into());
+            metric.set_field_type(MetricType::SUMMARY);
+            metric.set_metric(vec![new_summary(histogram)]);
+            metrics.push(metric);
+        }
+
         TextEncoder::new().encode_to_string(&metrics).map_err(|e| {
             trc::EventType::Telemetry(trc::TelemetryEvent::OtelExporterError).reason(e)
         })
@@ -112,3 +130,23 @@ fn new_histogram(histogram: &AtomicHistogram<12>) -> Metric {
     m.set_histogram(h);
     m
 }
+
+fn new_summary(histogram: &AtomicHdrHistogram) -> Metric {
+    let mut m = Metric::default();
+    let mut s = Summary::default();
+    s.set_sample_count(histogram.count());
+    s.set_sample_sum(histogram.sum() as f64);
+    s.set_quantile(
+        EXPORTED_QUANTILES
+            .iter()
+            .filter_map(|quantile| {
+                let mut q = Quantile::default();
+                q.set_quantile(*quantile);
+                q.set_value(histogram.percentile(*quantile)? as f64);
+                Some(q)
+            })
+            .collect(),
+    );
+    m.set_summary(s);
+    m
+}
@@ -0,0 +1,209 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{fmt::Write, net::SocketAddr, sync::LazyLock};
+
+use ahash::AHashMap;
+use parking_lot::Mutex;
+use tokio::net::UdpSocket;
+use trc::{Collector, TelemetryEvent};
+
+use crate::config::telemetry::StatsdMetrics;
+
+// Keep datagrams below the typical MTU to avoid fragmentation
+const MAX_PACKET_SIZE: usize = 1432;
+
+// Counters and histograms in the collector are cumulative (which is what Prometheus expects),
+// whereas StatsD expects deltas. The last reported values are kept here, outside of the
+// configuration, so that a settings reload does not report the same events twice.
+static LAST_VALUES: LazyLock<Mutex<AHashMap<String, u64>>> = LazyLock::new(Default::default);
+
+impl StatsdMetrics {
+    pub async fn push_metrics(&self, is_enterprise: bool) {
+        let lines = self.collect(is_enterprise);
+        if lines.is_empty() {
+            return;
+        }
+
+        if let Err(err) = self.send(&lines).await {
+            trc::event!(
+                Telemetry(TelemetryEvent::StatsdExporterError),
+                Reason = err.to_string(),
+                Details = self.endpoint.clone(),
+            );
+        }
+    }
+
+    fn collect(&self, is_enterprise: bool) -> Vec<String> {
+        let mut lines = Vec::with_capacity(256);
+        let mut last_values = LAST_VALUES.lock();
+
+        // Add counters
+        for counter in Collector::collect_counters(is_enterprise) {
+            let name = self.metric_name(counter.id().name());
+            lines.extend(counter_line(&mut last_values, &name, counter.value()));
+        }
+
+        // Add gauges
+        for gauge in Collector::collect_gauges(is_enterprise) {
+            lines.push(gauge_line(
+                &self.metric_name(gauge.id().name()),
+                gauge.get(),
+            ));
+        }
+
+        // Add histograms
+        for histogram in Collector::collect_histograms(is_enterprise) {
+            let name = self.metric_name(histogram.id().name());
+
+            for (suffix, value) in [("count", histogram.count()), ("sum", histogram.sum())] {
+                lines.extend(counter_line(
+                    &mut last_values,
+                    &format!("{name}.{suffix}"),
+                    value,
+                ));
+            }
+
+            for (count, upper_bound) in histogram
+                .buckets_iter()
+                .into_iter()
+                .zip(histogram.upper_bounds_iter())
+            {
+                let name = if upper_bound != u64::MAX {
+                    format!("{name}.bucket.le_{upper_bound}")
+                } else {
+                    format!("{name}.bucket.le_inf")
+                };
+                lines.extend(counter_line(&mut last_values, &name, count));
+            }
+        }
+
+        lines
+    }
+
+    async fn send(&self, lines: &[String]) -> std::io::Result<()> {
+        let addr = tokio::net::lookup_host(&self.endpoint)
+            .await?
+            .next()
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "StatsD endpoint did not resolve to any address",
+                )
+            })?;
+        let socket = UdpSocket::bind(match addr {
+            SocketAddr::V4(_) => "0.0.0.0:0",
+            SocketAddr::V6(_) => "[::]:0",
+        })
+        .await?;
+
+        let mut packet = String::with_capacity(MAX_PACKET_SIZE);
+        for line in lines {
+            if !packet.is_empty() && packet.len() + line.len() + 1 > MAX_PACKET_SIZE {
+                socket.send_to(packet.as_bytes(), addr).await?;
+                packet.clear();
+            }
+            if !packet.is_empty() {
+                packet.push('\n');
+            }
+            let _ = write!(packet, "{line}");
+        }
+        if !packet.is_empty() {
+            socket.send_to(packet.as_bytes(), addr).await?;
+        }
+
+        Ok(())
+    }
+
+    fn metric_name(&self, id: &str) -> String {
+        let mut name = String::with_capacity(self.prefix.len() + id.len() + 1);
+        if !self.prefix.is_empty() {
+            name.push_str(&self.prefix);
+            name.push('.');
+        }
+        for c in id.chars() {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                name.push(c);
+            } else {
+                name.push('_');
+            }
+        }
+        name
+    }
+}
+
+// Reports the increase since the last push, counters that did not increase are
+// omitted and counters that went down (after a reset) are tracked from their new value
+fn counter_line(last_values: &mut AHashMap<String, u64>, name: &str, value: u64) -> Option<String> {
+    let last = last_values.insert(name.to_string(), value).unwrap_or(0);
+    if value > last {
+        Some(format!("{name}:{}|c", value - last))
+    } else {
+        None
+    }
+}
+
+fn gauge_line(name: &str, value: u64) -> String {
+    format!("{name}:{value}|g")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ahash::AHashMap;
+
+    use crate::config::telemetry::StatsdMetrics;
+
+    use super::{counter_line, gauge_line};
+
+    #[test]
+    fn statsd_lines() {
+        let metrics = StatsdMetrics {
+            endpoint: "127.0.0.1:8125".to_string(),
+            prefix: "stalwart".to_string(),
+            interval: Duration::from_secs(30),
+        };
+        assert_eq!(
+            metrics.metric_name("smtp.vrfy-count"),
+            "stalwart.smtp.vrfy-count"
+        );
+        assert_eq!(metrics.metric_name("a b/c:d|e"), "stalwart.a_b_c_d_e");
+        assert_eq!(
+            StatsdMetrics {
+                prefix: String::new(),
+                ..metrics
+            }
+            .metric_name("queue.count"),
+            "queue.count"
+        );
+        assert_eq!(gauge_line("queue.count", 7), "queue.count:7|g");
+
+        // Counters are reported as the increase since the previous push
+        let mut last_values = AHashMap::new();
+        assert_eq!(
+            counter_line(&mut last_values, "events", 5),
+            Some("events:5|c".to_string())
+        );
+        assert_eq!(
+            counter_line(&mut last_values, "events", 12),
+            Some("events:7|c".to_string())
+        );
+        assert_eq!(counter_line(&mut last_values, "events", 12), None);
+        assert_eq!(
+            counter_line(&mut last_values, "other", 3),
+            Some("other:3|c".to_string())
+        );
+
+        // Counters that went down are tracked from their new value
+        assert_eq!(counter_line(&mut last_values, "events", 4), None);
+        assert_eq!(
+            counter_line(&mut last_values, "events", 6),
+            Some("events:2|c".to_string())
+        );
+        assert_eq!(counter_line(&mut last_values, "never", 0), None);
+    }
+}
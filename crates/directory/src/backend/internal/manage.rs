@@ -991,6 +991,17 @@ impl ManageDirectory for Store {
                 {
                     principal.inner.remove(PrincipalField::Quota);
                 }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::SpamFilterOptOut,
+                    PrincipalValue::Integer(opt_out),
+                ) if matches!(principal.inner.typ, Type::Individual) => {
+                    if opt_out != 0 {
+                        principal.inner.set(PrincipalField::SpamFilterOptOut, 1u64);
+                    } else {
+                        principal.inner.remove(PrincipalField::SpamFilterOptOut);
+                    }
+                }
                 (
                     PrincipalAction::Set,
                     PrincipalField::Quota,
@@ -84,6 +84,10 @@ impl LdapDirectory {
                 .values((&prefix, "attributes.email-alias"))
                 .map(|(_, v)| v.to_string())
                 .collect(),
+            attr_spam_filter_opt_out: config
+                .values((&prefix, "attributes.spam-filter-opt-out"))
+                .map(|(_, v)| v.to_string())
+                .collect(),
             attrs_principal: vec!["objectClass".to_string()],
         };
 
@@ -96,6 +100,7 @@ impl LdapDirectory {
             &mappings.attr_groups,
             &mappings.attr_email_address,
             &mappings.attr_email_alias,
+            &mappings.attr_spam_filter_opt_out,
         ] {
             mappings.attrs_principal.extend(attr.iter().cloned());
         }
@@ -15,7 +15,7 @@ use crate::{
             manage::{self, ManageDirectory, UpdatePrincipal},
             PrincipalField,
         },
-        RcptType,
+        parse_flag, RcptType,
     },
     IntoError, Principal, QueryBy, Type, ROLE_ADMIN, ROLE_USER,
 };
@@ -387,6 +387,10 @@ impl LdapMappings {
                 if let Ok(quota) = value.into_iter().next().unwrap_or_default().parse::<u64>() {
                     principal.set(PrincipalField::Quota, quota);
                 }
+            } else if self.attr_spam_filter_opt_out.contains(&attr) {
+                if let Some(opt_out) = value.into_iter().next().and_then(|v| parse_flag(&v)) {
+                    principal.set(PrincipalField::SpamFilterOptOut, opt_out as u64);
+                }
             } else if self.attr_type.contains(&attr) {
                 for value in value {
                     match value.to_ascii_lowercase().as_str() {
@@ -32,6 +32,7 @@ pub struct LdapMappings {
     attr_email_address: Vec<String>,
     attr_email_alias: Vec<String>,
     attr_quota: Vec<String>,
+    attr_spam_filter_opt_out: Vec<String>,
     attrs_principal: Vec<String>,
 }
 
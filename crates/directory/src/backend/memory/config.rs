@@ -147,6 +147,17 @@ impl MemoryDirectory {
             {
                 principal.set(PrincipalField::Quota, quota);
             }
+            if config
+                .property::<bool>((
+                    prefix.as_str(),
+                    "principals",
+                    lookup_id,
+                    "spam-filter-opt-out",
+                ))
+                .unwrap_or_default()
+            {
+                principal.set(PrincipalField::SpamFilterOptOut, 1u64);
+            }
 
             directory.principals.push(principal);
         }
@@ -30,3 +30,12 @@ impl From<bool> for RcptType {
         }
     }
 }
+
+// Parses boolean attributes stored by external directories
+pub(crate) fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" | "" => Some(false),
+        _ => None,
+    }
+}
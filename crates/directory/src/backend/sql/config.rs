@@ -43,6 +43,10 @@ impl SqlDirectory {
                 .value((&prefix, "columns.quota"))
                 .unwrap_or_default()
                 .to_string(),
+            column_spam_filter_opt_out: config
+                .value((&prefix, "columns.spam-filter-opt-out"))
+                .unwrap_or_default()
+                .to_string(),
             column_type: config
                 .value((&prefix, "columns.class"))
                 .unwrap_or_default()
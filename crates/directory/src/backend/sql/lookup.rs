@@ -15,7 +15,7 @@ use crate::{
             manage::{self, ManageDirectory, UpdatePrincipal},
             PrincipalField, PrincipalValue,
         },
-        RcptType,
+        parse_flag, RcptType,
     },
     Principal, QueryBy, Type, ROLE_ADMIN, ROLE_USER,
 };
@@ -291,6 +291,18 @@ impl SqlMappings {
                     if let Value::Integer(quota) = value {
                         principal.set(PrincipalField::Quota, quota as u64);
                     }
+                } else if !self.column_spam_filter_opt_out.is_empty()
+                    && name.eq_ignore_ascii_case(&self.column_spam_filter_opt_out)
+                {
+                    let opt_out = match value {
+                        Value::Integer(v) => Some(v != 0),
+                        Value::Bool(v) => Some(v),
+                        Value::Text(v) => parse_flag(&v),
+                        _ => None,
+                    };
+                    if let Some(opt_out) = opt_out {
+                        principal.set(PrincipalField::SpamFilterOptOut, opt_out as u64);
+                    }
                 }
             }
         }
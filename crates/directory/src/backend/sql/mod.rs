@@ -26,5 +26,6 @@ pub(crate) struct SqlMappings {
     column_secret: String,
     column_email: String,
     column_quota: String,
+    column_spam_filter_opt_out: String,
     column_type: String,
 }
@@ -47,6 +47,11 @@ impl Principal {
         self.get_int(PrincipalField::Quota).unwrap_or_default()
     }
 
+    pub fn is_spam_filter_opt_out(&self) -> bool {
+        self.get_int(PrincipalField::SpamFilterOptOut)
+            .is_some_and(|v| v != 0)
+    }
+
     // SPDX-SnippetBegin
     // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
     // SPDX-License-Identifier: LicenseRef-SEL
@@ -401,6 +406,16 @@ impl Principal {
             }
         }
 
+        if let Some(opt_out) = external.take_int(PrincipalField::SpamFilterOptOut) {
+            if self.get_int(PrincipalField::SpamFilterOptOut) != Some(opt_out) {
+                updates.push(PrincipalUpdate::set(
+                    PrincipalField::SpamFilterOptOut,
+                    PrincipalValue::Integer(opt_out),
+                ));
+                self.set(PrincipalField::SpamFilterOptOut, opt_out);
+            }
+        }
+
         // Add external members
         if let Some(member_of) = external
             .take_int_array(PrincipalField::MemberOf)
@@ -835,7 +850,9 @@ impl<'de> serde::Deserialize<'de> for Principal {
                             })?;
                             continue;
                         }
-                        PrincipalField::Quota => map.next_value::<PrincipalValue>()?,
+                        PrincipalField::Quota | PrincipalField::SpamFilterOptOut => {
+                            map.next_value::<PrincipalValue>()?
+                        }
                         PrincipalField::Secrets
                         | PrincipalField::Emails
                         | PrincipalField::MemberOf
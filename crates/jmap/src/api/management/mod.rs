@@ -15,6 +15,7 @@ pub mod reload;
 pub mod report;
 pub mod settings;
 pub mod sieve;
+pub mod spam;
 pub mod stores;
 pub mod troubleshoot;
 
@@ -36,6 +37,7 @@ use report::ManageReports;
 use serde::Serialize;
 use settings::ManageSettings;
 use sieve::SieveHandler;
+use spam::SpamFilterManagement;
 use store::write::now;
 use stores::ManageStore;
 use troubleshoot::TroubleshootApi;
@@ -118,6 +120,10 @@ impl ManagementApi for Server {
                 self.handle_view_logs(req, &access_token).await
             }
             "sieve" => self.handle_run_sieve(req, path, body, &access_token).await,
+            "spam-filter" => {
+                self.handle_manage_spam_filter(req, path, body, &access_token)
+                    .await
+            }
             "restart" if req.method() == Method::GET => {
                 // Validate the access token
                 access_token.assert_has_permission(Permission::Restart)?;
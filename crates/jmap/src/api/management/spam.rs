@@ -0,0 +1,381 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{
+    auth::AccessToken,
+    config::spamfilter::BayesDecayConfig,
+    manager::bayes_backup::BayesBackup,
+    scripts::plugins::features::{ENSEMBLE_FEATURES, FEATURES},
+    Server,
+};
+use directory::{backend::internal::manage, Permission};
+use http_body_util::{combinators::BoxBody, StreamBody};
+use hyper::{
+    body::{Bytes, Frame},
+    Method, StatusCode,
+};
+use nlp::bayes::{calibration::IsotonicCalibration, logistic::LogisticModel};
+use serde::Deserialize;
+use serde_json::json;
+use std::{future::Future, time::Duration};
+use tokio::sync::broadcast::error::RecvError;
+use trc::{Collector, MetricType};
+use utils::url_params::UrlParams;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, HttpResponseBody, JsonResponse};
+
+use super::decode_path_element;
+
+#[derive(Debug, Deserialize)]
+struct CalibrationSample {
+    score: f64,
+    spam: bool,
+}
+
+// Features as returned by feature_vector, starting with the feature version
+#[derive(Debug, Deserialize)]
+struct LogisticSample {
+    features: Vec<f64>,
+    spam: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct MergeRequest {
+    sources: [String; 2],
+}
+
+pub trait SpamFilterManagement: Sync + Send {
+    fn handle_manage_spam_filter(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl SpamFilterManagement for Server {
+    async fn handle_manage_spam_filter(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        // Validate the access token
+        access_token.assert_has_permission(Permission::UpdateSpamFilter)?;
+
+        // Models are identified by their lookup store id, omitting it selects the default store
+        let model_id = path
+            .get(2)
+            .map(|id| decode_path_element(id))
+            .unwrap_or_default();
+        if !model_id.is_empty() && !self.core.storage.lookups.contains_key(model_id.as_ref()) {
+            return Err(manage::not_found(model_id.into_owned()));
+        }
+
+        match (path.get(1).copied(), req.method()) {
+            (Some("calibration"), &Method::POST) => {
+                // Fit the calibration from the raw scores of a labeled validation set
+                let samples = serde_json::from_slice::<Vec<CalibrationSample>>(
+                    body.as_deref().unwrap_or_default(),
+                )
+                .map_err(|err| {
+                    trc::EventType::Resource(trc::ResourceEvent::BadParameters).reason(err)
+                })?;
+                let calibration =
+                    IsotonicCalibration::fit(samples.into_iter().map(|s| (s.score, s.spam)))
+                        .ok_or_else(|| {
+                            manage::error("No calibration samples provided", None::<u32>)
+                        })?;
+                let points = calibration.points.len();
+
+                self.bayes_update_calibration(model_id.as_ref(), calibration.into())
+                    .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": points,
+                }))
+                .into_http_response())
+            }
+            (Some("calibration"), &Method::DELETE) => {
+                self.bayes_update_calibration(model_id.as_ref(), None)
+                    .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some("logistic"), &Method::POST) => {
+                // Fit the logistic model from the feature vectors of a labeled set,
+                // optionally selecting the features to combine
+                let params = UrlParams::new(req.uri().query());
+                let features = params.get("features").map_or_else(
+                    || ENSEMBLE_FEATURES.iter().map(|f| f.to_string()).collect(),
+                    |features| {
+                        features
+                            .split(',')
+                            .map(|f| f.trim().to_string())
+                            .filter(|f| !f.is_empty())
+                            .collect::<Vec<_>>()
+                    },
+                );
+                let mut indexes = Vec::with_capacity(features.len());
+                for feature in &features {
+                    indexes.push(
+                        FEATURES.iter().position(|f| f == feature).ok_or_else(|| {
+                            manage::error("Unknown feature", feature.to_string().into())
+                        })? + 1,
+                    );
+                }
+                let samples = serde_json::from_slice::<Vec<LogisticSample>>(
+                    body.as_deref().unwrap_or_default(),
+                )
+                .map_err(|err| {
+                    trc::EventType::Resource(trc::ResourceEvent::BadParameters).reason(err)
+                })?
+                .into_iter()
+                // Messages without a Bayes verdict are not classified by the ensemble
+                .filter(|sample| sample.features.get(1).is_some_and(|score| *score >= 0.0))
+                .filter_map(|sample| {
+                    indexes
+                        .iter()
+                        .map(|idx| sample.features.get(*idx).copied())
+                        .collect::<Option<Vec<_>>>()
+                        .map(|values| (values, sample.spam))
+                })
+                .collect::<Vec<_>>();
+                let logistic = LogisticModel::fit(features, &samples).ok_or_else(|| {
+                    manage::error(
+                        "Both spam and ham samples with a Bayes score are required",
+                        None::<u32>,
+                    )
+                })?;
+
+                self.bayes_update_logistic(model_id.as_ref(), logistic.clone().into())
+                    .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": logistic,
+                }))
+                .into_http_response())
+            }
+            (Some("logistic"), &Method::DELETE) => {
+                self.bayes_update_logistic(model_id.as_ref(), None).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some("prune"), &Method::POST) => {
+                // Sample texts used to find the redundant tokens of the model
+                let texts =
+                    serde_json::from_slice::<Vec<String>>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters).reason(err)
+                        })?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": self.bayes_prune(model_id.as_ref(), &texts).await?,
+                }))
+                .into_http_response())
+            }
+            (Some("snapshot"), &Method::GET) => Ok(JsonResponse::new(json!({
+                "data": self.bayes_snapshot(model_id.as_ref()).await?,
+            }))
+            .into_http_response()),
+            (Some("report"), &Method::GET) => {
+                // Reports are rendered as Markdown unless JSON is requested
+                let params = UrlParams::new(req.uri().query());
+                let report = self
+                    .bayes_report(
+                        model_id.as_ref(),
+                        params.parse("sample").unwrap_or(10000),
+                        params.parse("top").unwrap_or(20),
+                    )
+                    .await?;
+
+                if params.get("format") == Some("json") {
+                    Ok(JsonResponse::new(json!({
+                        "data": report,
+                    }))
+                    .into_http_response())
+                } else {
+                    Ok(HttpResponse {
+                        status: StatusCode::OK,
+                        content_type: "text/markdown; charset=utf-8".into(),
+                        content_disposition: "".into(),
+                        cache_control: "no-store".into(),
+                        body: HttpResponseBody::Text(report.to_markdown()),
+                    })
+                }
+            }
+            (Some("backup"), &Method::GET) => {
+                // Incremental backups include the tokens changed since the "until" value of the previous backup
+                let params = UrlParams::new(req.uri().query());
+                let since = params.parse::<u64>("since").unwrap_or_default();
+                let compact = params.get("format") == Some("compact");
+
+                Ok(JsonResponse::new(json!({
+                    "data": self.bayes_backup(model_id.as_ref(), since, compact).await?,
+                }))
+                .into_http_response())
+            }
+            (Some("restore"), &Method::POST) => {
+                // Token weights are absolute, large backups can be restored in chunks
+                let backup =
+                    serde_json::from_slice::<BayesBackup>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters).reason(err)
+                        })?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": self.bayes_restore(model_id.as_ref(), backup).await?,
+                }))
+                .into_http_response())
+            }
+            (Some("merge"), &Method::POST) => {
+                // The model in the path is the destination of the merge
+                let request =
+                    serde_json::from_slice::<MergeRequest>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters).reason(err)
+                        })?;
+                for source in &request.sources {
+                    if !source.is_empty() && !self.core.storage.lookups.contains_key(source) {
+                        return Err(manage::not_found(source.to_string()));
+                    }
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": self.bayes_merge(
+                        [request.sources[0].as_str(), request.sources[1].as_str()],
+                        model_id.as_ref(),
+                    )
+                    .await?,
+                }))
+                .into_http_response())
+            }
+            (Some("decay"), &Method::POST) => {
+                // Runs the configured decay immediately, or with the factor in the query
+                let mut decay = self
+                    .core
+                    .spam
+                    .bayes
+                    .model(model_id.as_ref())
+                    .decay
+                    .clone()
+                    .unwrap_or(BayesDecayConfig {
+                        factor: 1.0,
+                        interval: Duration::ZERO,
+                        jitter: Duration::ZERO,
+                        batch_size: 1000,
+                    });
+                if let Some(factor) = UrlParams::new(req.uri().query()).parse::<f64>("factor") {
+                    decay.factor = factor;
+                }
+                if !(0.0..1.0).contains(&decay.factor) {
+                    return Err(manage::error(
+                        "Decay factor must be between 0 and 1",
+                        None::<u32>,
+                    ));
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": self.bayes_decay(model_id.as_ref(), &decay).await?,
+                }))
+                .into_http_response())
+            }
+            (Some("latency"), &Method::GET) => {
+                // Classification latency percentiles in microseconds, split by cache usage
+                let mut latency = serde_json::Map::new();
+                for (path, metric) in [
+                    ("cache-hit", MetricType::SpamClassifyLatencyHit),
+                    ("cache-miss", MetricType::SpamClassifyLatencyMiss),
+                ] {
+                    let histogram = Collector::read_percentiles(metric);
+                    latency.insert(
+                        path.to_string(),
+                        json!({
+                            "count": histogram.map(|h| h.count()).unwrap_or_default(),
+                            "average": histogram.map(|h| h.average()).unwrap_or_default(),
+                            "p50": histogram.and_then(|h| h.percentile(0.5)),
+                            "p95": histogram.and_then(|h| h.percentile(0.95)),
+                            "p99": histogram.and_then(|h| h.percentile(0.99)),
+                        }),
+                    );
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": latency,
+                }))
+                .into_http_response())
+            }
+            (Some("live"), &Method::GET) => {
+                // Only one out of every "sample" classifications is streamed
+                let params = UrlParams::new(req.uri().query());
+                let sample = params.parse::<u64>("sample").unwrap_or(1).max(1);
+                let filter = path.get(2).map(|_| model_id.into_owned());
+
+                let mut rx = self.inner.data.bayes_live.subscribe();
+                let ping_interval = Duration::from_secs(30);
+                let ping_payload = Bytes::from(format!(
+                    "event: ping\ndata: {{\"interval\": {}}}\n\n",
+                    ping_interval.as_millis()
+                ));
+
+                Ok(HttpResponse {
+                    status: StatusCode::OK,
+                    content_type: "text/event-stream".into(),
+                    content_disposition: "".into(),
+                    cache_control: "no-store".into(),
+                    body: HttpResponseBody::Stream(BoxBody::new(StreamBody::new(
+                        async_stream::stream! {
+                            let mut skip = 0u64;
+
+                            loop {
+                                match tokio::time::timeout(ping_interval, rx.recv()).await {
+                                    Ok(Ok(diagnostics)) => {
+                                        if filter
+                                            .as_ref()
+                                            .is_some_and(|filter| filter != &diagnostics.model_id)
+                                        {
+                                            continue;
+                                        }
+                                        if skip > 0 {
+                                            skip -= 1;
+                                        } else {
+                                            skip = sample - 1;
+                                            yield Ok(Frame::data(Bytes::from(format!(
+                                                "event: classify\ndata: {}\n\n",
+                                                serde_json::to_string(diagnostics.as_ref())
+                                                    .unwrap_or_default()
+                                            ))));
+                                        }
+                                    }
+                                    Ok(Err(RecvError::Lagged(skipped))) => {
+                                        yield Ok(Frame::data(Bytes::from(format!(
+                                            "event: lagged\ndata: {{\"skipped\": {skipped}}}\n\n"
+                                        ))));
+                                    }
+                                    Ok(Err(RecvError::Closed)) => {
+                                        break;
+                                    }
+                                    Err(_) => {
+                                        yield Ok(Frame::data(ping_payload.clone()));
+                                    }
+                                }
+                            }
+                        },
+                    ))),
+                })
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
@@ -133,8 +133,17 @@ impl EmailIngest for Server {
                             .map_or(false, |value| value.contains(header_value))
                 })
             {
-                params.mailbox_ids[0] = JUNK_ID;
-                is_spam = true;
+                // Recipients that opted out of spam filtering keep the message in their inbox
+                if !self.core.spam.opt_out
+                    || !self
+                        .get_cached_access_token(account_id)
+                        .await
+                        .caused_by(trc::location!())?
+                        .spam_filter_opt_out
+                {
+                    params.mailbox_ids[0] = JUNK_ID;
+                    is_spam = true;
+                }
             }
         }
 
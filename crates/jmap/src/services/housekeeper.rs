@@ -14,7 +14,7 @@ use common::{
     config::telemetry::OtelMetrics,
     core::BuildServer,
     ipc::{HousekeeperEvent, PurgeType},
-    Inner,
+    Inner, Server,
 };
 
 #[cfg(feature = "enterprise")]
@@ -23,6 +23,7 @@ use common::telemetry::{
     tracers::store::TracingStore,
 };
 
+use rand::Rng;
 use smtp::reporting::SmtpReporting;
 use store::write::{now, purge::PurgeStore};
 use tokio::sync::mpsc;
@@ -44,6 +45,9 @@ enum ActionClass {
     Store(usize),
     Acme(String),
     OtelMetrics,
+    StatsdMetrics,
+    BayesFlush,
+    BayesDecay(String),
     #[cfg(feature = "enterprise")]
     InternalMetrics,
     CalculateMetrics,
@@ -97,6 +101,22 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                 queue.schedule(Instant::now() + otel.interval, ActionClass::OtelMetrics);
             }
 
+            // StatsD Push Metrics
+            if let Some(statsd) = &server.core.metrics.statsd {
+                queue.schedule(Instant::now() + statsd.interval, ActionClass::StatsdMetrics);
+            }
+
+            // Spam filter write-behind training updates
+            if let Some(write_behind) = &server.core.spam.bayes.write_behind {
+                queue.schedule(
+                    Instant::now() + write_behind.interval,
+                    ActionClass::BayesFlush,
+                );
+            }
+
+            // Spam filter model decay
+            schedule_bayes_decay(server.clone(), bayes_decay_models(&server));
+
             // Calculate expensive metrics
             queue.schedule(Instant::now(), ActionClass::CalculateMetrics);
 
@@ -168,6 +188,39 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                             _ => {}
                         }
 
+                        // Reload StatsD push metrics
+                        match &server.core.metrics.statsd {
+                            Some(statsd) if !queue.has_action(&ActionClass::StatsdMetrics) => {
+                                queue.schedule(
+                                    Instant::now() + statsd.interval,
+                                    ActionClass::StatsdMetrics,
+                                );
+                            }
+                            _ => {}
+                        }
+
+                        // Reload spam filter write-behind
+                        match &server.core.spam.bayes.write_behind {
+                            Some(write_behind) if !queue.has_action(&ActionClass::BayesFlush) => {
+                                queue.schedule(
+                                    Instant::now() + write_behind.interval,
+                                    ActionClass::BayesFlush,
+                                );
+                            }
+                            _ => {}
+                        }
+
+                        // Reload spam filter model decay
+                        let model_ids = bayes_decay_models(&server)
+                            .into_iter()
+                            .filter(|model_id| {
+                                !queue.has_action(&ActionClass::BayesDecay(model_id.clone()))
+                            })
+                            .collect::<Vec<_>>();
+                        if !model_ids.is_empty() {
+                            schedule_bayes_decay(server.clone(), model_ids);
+                        }
+
                         // SPDX-SnippetBegin
                         // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
                         // SPDX-License-Identifier: LicenseRef-SEL
@@ -229,6 +282,11 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                         queue.remove_action(&action);
                         queue.schedule(renew_at, action);
                     }
+                    HousekeeperEvent::BayesDecayReschedule { model_id, due } => {
+                        let action = ActionClass::BayesDecay(model_id);
+                        queue.remove_action(&action);
+                        queue.schedule(due, action);
+                    }
                     HousekeeperEvent::Purge(purge) => match purge {
                         PurgeType::Data(store) => {
                             // SPDX-SnippetBegin
@@ -401,6 +459,11 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                 tokio::spawn(async move {
                                     trc::event!(Housekeeper(trc::HousekeeperEvent::PurgeSessions));
                                     server.inner.data.http_auth_cache.cleanup();
+                                    server.inner.data.remote_classify_cache.cleanup();
+                                    server.inner.data.bayes_sender_cache.cleanup();
+                                    server.inner.data.bayes_divergence_cache.cleanup();
+                                    server.inner.data.bayes_metadata.cleanup();
+                                    server.inner.data.bayes_trained.cleanup();
                                     server
                                         .inner
                                         .data
@@ -477,6 +540,80 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                     });
                                 }
                             }
+                            ActionClass::StatsdMetrics => {
+                                if let Some(statsd) = &server.core.metrics.statsd {
+                                    queue.schedule(
+                                        Instant::now() + statsd.interval,
+                                        ActionClass::StatsdMetrics,
+                                    );
+
+                                    let statsd = statsd.clone();
+
+                                    #[cfg(feature = "enterprise")]
+                                    let is_enterprise = server.is_enterprise_edition();
+
+                                    #[cfg(not(feature = "enterprise"))]
+                                    let is_enterprise = false;
+
+                                    tokio::spawn(async move {
+                                        statsd.push_metrics(is_enterprise).await;
+                                    });
+                                }
+                            }
+                            ActionClass::BayesFlush => {
+                                // Updates left pending after write-behind was disabled are
+                                // flushed one last time
+                                if let Some(write_behind) = &server.core.spam.bayes.write_behind {
+                                    queue.schedule(
+                                        Instant::now() + write_behind.interval,
+                                        ActionClass::BayesFlush,
+                                    );
+                                }
+
+                                let server = server.clone();
+                                tokio::spawn(async move {
+                                    if let Err(err) = server.bayes_flush().await {
+                                        trc::error!(
+                                            err.details("Failed to flush pending training updates")
+                                        );
+                                    }
+                                });
+                            }
+                            ActionClass::BayesDecay(model_id) => {
+                                let server = server.clone();
+                                tokio::spawn(async move {
+                                    match server.bayes_decay_scheduled(&model_id).await {
+                                        Ok(true) => {
+                                            schedule_bayes_decay(server, vec![model_id]);
+                                        }
+                                        Ok(false) => {}
+                                        Err(err) => {
+                                            trc::error!(err
+                                                .id(model_id.clone())
+                                                .details("Failed to decay spam filter model"));
+
+                                            // Retried at the next interval
+                                            if let Some(decay) =
+                                                &server.core.spam.bayes.model(&model_id).decay
+                                            {
+                                                let due = Instant::now()
+                                                    + decay.interval
+                                                    + decay_jitter(decay.jitter);
+                                                server
+                                                    .inner
+                                                    .ipc
+                                                    .housekeeper_tx
+                                                    .send(HousekeeperEvent::BayesDecayReschedule {
+                                                        model_id,
+                                                        due,
+                                                    })
+                                                    .await
+                                                    .ok();
+                                            }
+                                        }
+                                    }
+                                });
+                            }
                             ActionClass::CalculateMetrics => {
                                 // Calculate expensive metrics every 5 minutes
                                 queue.schedule(
@@ -662,6 +799,54 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
     });
 }
 
+// Models with a decay schedule, including the default model
+fn bayes_decay_models(server: &Server) -> Vec<String> {
+    let bayes = &server.core.spam.bayes;
+    bayes
+        .models
+        .iter()
+        .filter(|(_, model)| model.decay.is_some())
+        .map(|(model_id, _)| model_id.clone())
+        .chain(bayes.default.decay.is_some().then(String::new))
+        .collect()
+}
+
+// Schedules the next decay of each model once its last run was read from the store
+fn schedule_bayes_decay(server: Server, model_ids: Vec<String>) {
+    tokio::spawn(async move {
+        for model_id in model_ids {
+            let Some(decay) = server.core.spam.bayes.model(&model_id).decay.clone() else {
+                continue;
+            };
+            let due = match server.bayes_decay_due(&model_id).await {
+                Ok(Some(due)) => due,
+                Ok(None) => continue,
+                Err(err) => {
+                    trc::error!(err
+                        .id(model_id.clone())
+                        .details("Failed to obtain spam filter model decay schedule"));
+                    decay.interval
+                }
+            };
+
+            server
+                .inner
+                .ipc
+                .housekeeper_tx
+                .send(HousekeeperEvent::BayesDecayReschedule {
+                    due: Instant::now() + due + decay_jitter(decay.jitter),
+                    model_id,
+                })
+                .await
+                .ok();
+        }
+    });
+}
+
+fn decay_jitter(jitter: Duration) -> Duration {
+    Duration::from_millis(rand::thread_rng().gen_range(0..=jitter.as_millis() as u64))
+}
+
 impl Queue {
     pub fn schedule(&mut self, due: Instant, event: ActionClass) {
         trc::event!(
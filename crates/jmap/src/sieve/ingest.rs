@@ -7,7 +7,10 @@
 use std::borrow::Cow;
 
 use common::{
-    auth::AccessToken, listener::stream::NullIo, scripts::plugins::PluginContext, Server,
+    auth::AccessToken,
+    listener::stream::NullIo,
+    scripts::{plugins::PluginContext, ScriptModification},
+    Server,
 };
 use directory::{backend::internal::PrincipalField, QueryBy};
 use jmap_proto::types::{collection::Collection, id::Id, keyword::Keyword, property::Property};
@@ -406,6 +409,7 @@ impl SieveScriptIngest for Server {
                         input = false.into();
                     }
                     Event::Function { id, arguments } => {
+                        let mut modifications = Vec::new();
                         input = self
                             .core
                             .run_plugin(
@@ -414,12 +418,17 @@ impl SieveScriptIngest for Server {
                                     session_id,
                                     server: self,
                                     message: instance.message(),
-                                    modifications: &mut Vec::new(),
+                                    modifications: &mut modifications,
                                     access_token: access_token.into(),
                                     arguments,
                                 },
                             )
                             .await;
+                        for modification in modifications {
+                            if let ScriptModification::SetVariable { name, value } = modification {
+                                instance.set_global_variable(name, value);
+                            }
+                        }
                     }
                     Event::CreatedMessage { message, .. } => {
                         messages.push(SieveMessage {
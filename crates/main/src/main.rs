@@ -49,6 +49,11 @@ async fn main() -> std::io::Result<()> {
             trc::error!(err.details("Directory migration failed"));
             std::process::exit(1);
         }
+
+        // Restore the spam filter token cache
+        if let Err(err) = server.bayes_warm_load().await {
+            trc::error!(err.details("Failed to load spam filter token cache"));
+        }
     }
 
     // Spawn servers
@@ -88,6 +93,7 @@ async fn main() -> std::io::Result<()> {
     });
 
     // Spawn gossip
+    let inner = init.inner.clone();
     if let Some(gossiper) = gossiper {
         gossiper.spawn(init.inner, shutdown_rx.clone()).await;
     }
@@ -95,14 +101,25 @@ async fn main() -> std::io::Result<()> {
     // Wait for shutdown signal
     wait_for_shutdown().await;
 
-    // Shutdown collector
-    Collector::shutdown();
-
     // Stop services
     let _ = shutdown_tx.send(true);
 
     // Wait for services to finish
     tokio::time::sleep(Duration::from_secs(1)).await;
 
+    // Write pending spam filter training updates
+    let server = inner.build_server();
+    if let Err(err) = server.bayes_flush().await {
+        trc::error!(err.details("Failed to flush pending training updates"));
+    }
+
+    // Persist the spam filter token cache
+    if let Err(err) = server.bayes_warm_save().await {
+        trc::error!(err.details("Failed to save spam filter token cache"));
+    }
+
+    // Shutdown collector
+    Collector::shutdown();
+
     Ok(())
 }
@@ -0,0 +1,120 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+// Compares the storage size and read latency of token blocks against the row per
+// token layout, run with `cargo bench -p nlp --bench token_blocks`. Rows are kept
+// in ordered maps standing in for the backend indexes, while the per row overhead
+// of the backend is added to the size of each row.
+
+use std::{
+    collections::BTreeMap,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use nlp::bayes::{block::TokenBlock, TokenHash, Weights};
+
+// Bytes of metadata stored by a backend alongside each row (key length, sequence
+// numbers, row headers and so on), these depend on the backend
+const ROW_OVERHEADS: [usize; 3] = [0, 16, 48];
+const LOOKUPS: usize = 1_000_000;
+
+fn main() {
+    for vocabulary in [100_000, 1_000_000, 4_000_000] {
+        let tokens = tokens(vocabulary);
+        let rows = tokens
+            .iter()
+            .map(|(hash, weights)| (*hash, i64::from(*weights)))
+            .collect::<BTreeMap<_, _>>();
+        let blocks = TokenBlock::group(tokens.iter().copied())
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+
+        // Half of the lookups are for tokens unknown to the model
+        let lookups = (0..LOOKUPS)
+            .map(|i| {
+                let (hash, _) = tokens[(i * 7919) % tokens.len()];
+                if i % 2 == 0 {
+                    hash
+                } else {
+                    TokenHash {
+                        h1: hash.h1,
+                        h2: hash.h2.wrapping_add(1),
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let row_latency = measure(&lookups, |hash| {
+            rows.get(hash).map(|weights| Weights::from(*weights))
+        });
+        let block_latency = measure(&lookups, |hash| {
+            blocks
+                .get(&TokenBlock::prefix(hash))
+                .and_then(|block| TokenBlock::get(block, hash))
+        });
+
+        println!(
+            "{vocabulary} tokens, {} blocks ({:.1} tokens per block)",
+            blocks.len(),
+            vocabulary as f64 / blocks.len() as f64
+        );
+        for overhead in ROW_OVERHEADS {
+            let row_size = rows.len() * (16 + 8 + overhead);
+            let block_size = blocks
+                .values()
+                .map(|block| 2 + block.len() + overhead)
+                .sum::<usize>();
+            println!(
+                "  overhead {overhead:>2} bytes: rows {:>8.2} MB, blocks {:>8.2} MB ({:.0}%)",
+                row_size as f64 / 1_000_000.0,
+                block_size as f64 / 1_000_000.0,
+                block_size as f64 * 100.0 / row_size as f64
+            );
+        }
+        println!(
+            "  lookup: rows {:>6.0} ns, blocks {:>6.0} ns",
+            row_latency.as_nanos() as f64 / LOOKUPS as f64,
+            block_latency.as_nanos() as f64 / LOOKUPS as f64
+        );
+    }
+}
+
+fn measure(lookups: &[TokenHash], lookup: impl Fn(&TokenHash) -> Option<Weights>) -> Duration {
+    let time = Instant::now();
+    for hash in lookups {
+        black_box(lookup(black_box(hash)));
+    }
+    time.elapsed()
+}
+
+// Token counts follow a long tail, most tokens were seen in a few messages
+fn tokens(count: usize) -> Vec<(TokenHash, Weights)> {
+    let mut state = 0x2545_F491_4F6C_DD1Du64;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    (0..count)
+        .map(|_| {
+            let hash = TokenHash {
+                h1: next(),
+                h2: next(),
+            };
+            let spam = (next() % 1000) as u32;
+            let ham = (next() % 1000) as u32;
+            (
+                hash,
+                Weights {
+                    spam: 1000 / (spam + 1),
+                    ham: 1000 / (ham + 1),
+                },
+            )
+        })
+        .collect()
+}
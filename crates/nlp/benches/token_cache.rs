@@ -0,0 +1,83 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+// Measures the token cache throughput with concurrent classifications, run with
+// `cargo bench -p nlp --bench token_cache`.
+
+use std::{
+    sync::Barrier,
+    time::{Duration, Instant},
+};
+
+use nlp::bayes::{cache::BayesTokenCache, TokenHash, Weights};
+
+const CAPACITY: usize = 65536;
+const OPERATIONS: u64 = 200_000;
+
+fn main() {
+    let threads = std::thread::available_parallelism()
+        .map(|threads| threads.get())
+        .unwrap_or(4)
+        .max(2);
+
+    println!("{threads} threads, {OPERATIONS} operations per thread");
+    for shards in [1, 4, 16, 64] {
+        let elapsed = run(shards, threads);
+        println!(
+            "{shards:>3} shards: {:>8.2} ms, {:>6.2} M ops/s",
+            elapsed.as_secs_f64() * 1000.0,
+            (OPERATIONS * threads as u64) as f64 / elapsed.as_secs_f64() / 1_000_000.0
+        );
+    }
+}
+
+// Each thread looks up tokens, caching the misses as a classification would
+fn run(shards: usize, threads: usize) -> Duration {
+    let cache = BayesTokenCache::new(
+        CAPACITY,
+        shards,
+        Duration::from_secs(3600),
+        Duration::from_secs(3600),
+    );
+    let barrier = Barrier::new(threads + 1);
+
+    std::thread::scope(|scope| {
+        for thread in 0..threads as u64 {
+            let cache = &cache;
+            let barrier = &barrier;
+            scope.spawn(move || {
+                barrier.wait();
+                let mut state = thread.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+                for _ in 0..OPERATIONS {
+                    // Token popularity is skewed, most lookups hit a small vocabulary
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    let h1 = (state % (CAPACITY as u64 * 2)) % ((state >> 32) % 4096 + 1);
+                    let hash = TokenHash { h1, h2: h1 << 1 };
+                    if cache.get(&hash).is_none() {
+                        if h1 & 3 == 0 {
+                            cache.insert_negative(hash);
+                        } else {
+                            cache.insert_positive(
+                                hash,
+                                Weights {
+                                    spam: h1 as u32,
+                                    ham: 1,
+                                },
+                            );
+                        }
+                    }
+                }
+            });
+        }
+
+        // Threads are joined when the scope ends
+        barrier.wait();
+        Instant::now()
+    })
+    .elapsed()
+}
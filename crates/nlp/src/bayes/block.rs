@@ -0,0 +1,240 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::collections::BTreeMap;
+
+use super::{TokenHash, Weights};
+
+// Tokens sharing the first bytes of h1 are stored in the same block, which
+// only holds the remaining bytes of each hash
+pub const PREFIX_LEN: usize = 2;
+const SUFFIX_LEN: usize = 16 - PREFIX_LEN;
+
+// Compact encoding of the weights of many tokens, used by compact backups and
+// intended for backends where each row carries metadata well beyond the 24 bytes
+// of a token. Entries have a fixed width and are sorted by hash, so single tokens
+// are looked up with a binary search without decoding the block.
+//
+// A block starts with the width in bytes of each count (1, 2 or 4, the smallest
+// that fits all counts in the block), followed by the entries: the hash suffix
+// (big endian, so that byte order matches hash order) and the spam and ham counts
+// (little endian).
+pub struct TokenBlock;
+
+impl TokenBlock {
+    pub fn prefix(hash: &TokenHash) -> u16 {
+        (hash.h1 >> (64 - PREFIX_LEN * 8)) as u16
+    }
+
+    // Groups the tokens into blocks by prefix, tokens without weights are left out
+    // and the last weights of repeated tokens are kept
+    pub fn group(tokens: impl IntoIterator<Item = (TokenHash, Weights)>) -> Vec<(u16, Vec<u8>)> {
+        let mut blocks: BTreeMap<u16, BTreeMap<TokenHash, Weights>> = BTreeMap::new();
+        for (hash, weights) in tokens {
+            blocks
+                .entry(Self::prefix(&hash))
+                .or_default()
+                .insert(hash, weights);
+        }
+        blocks
+            .into_iter()
+            .map(|(prefix, tokens)| (prefix, Self::encode(tokens)))
+            .collect()
+    }
+
+    // Encodes tokens sharing the same prefix, which have to be sorted by hash
+    pub fn encode(tokens: impl IntoIterator<Item = (TokenHash, Weights)>) -> Vec<u8> {
+        let tokens = tokens
+            .into_iter()
+            .filter(|(_, weights)| *weights != Weights::default())
+            .collect::<Vec<_>>();
+        debug_assert!(tokens.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        debug_assert!(tokens
+            .windows(2)
+            .all(|pair| Self::prefix(&pair[0].0) == Self::prefix(&pair[1].0)));
+
+        let max_count = tokens
+            .iter()
+            .map(|(_, weights)| weights.spam.max(weights.ham))
+            .max()
+            .unwrap_or_default();
+        let width = if max_count <= u8::MAX as u32 {
+            1
+        } else if max_count <= u16::MAX as u32 {
+            2
+        } else {
+            4
+        };
+
+        let mut block = Vec::with_capacity(1 + tokens.len() * entry_len(width));
+        block.push(width as u8);
+        for (hash, weights) in tokens {
+            block.extend_from_slice(&suffix(&hash));
+            block.extend_from_slice(&weights.spam.to_le_bytes()[..width]);
+            block.extend_from_slice(&weights.ham.to_le_bytes()[..width]);
+        }
+        block
+    }
+
+    // Returns the weights of a token, or None if the block does not contain it
+    pub fn get(block: &[u8], hash: &TokenHash) -> Option<Weights> {
+        let (width, entries) = split(block)?;
+        let entry_len = entry_len(width);
+        let suffix = suffix(hash);
+
+        let (mut low, mut high) = (0, entries.len() / entry_len);
+        while low < high {
+            let mid = (low + high) / 2;
+            let entry = &entries[mid * entry_len..(mid + 1) * entry_len];
+            match entry[..SUFFIX_LEN].cmp(&suffix) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Some(weights(entry, width)),
+            }
+        }
+        None
+    }
+
+    pub fn iter(block: &[u8], prefix: u16) -> impl Iterator<Item = (TokenHash, Weights)> + '_ {
+        let (width, entries) = split(block).unwrap_or((1, &[]));
+        entries.chunks_exact(entry_len(width)).map(move |entry| {
+            let mut hash = [0u8; 16];
+            hash[..PREFIX_LEN].copy_from_slice(&prefix.to_be_bytes());
+            hash[PREFIX_LEN..].copy_from_slice(&entry[..SUFFIX_LEN]);
+            (
+                TokenHash {
+                    h1: u64::from_be_bytes(hash[..8].try_into().unwrap()),
+                    h2: u64::from_be_bytes(hash[8..].try_into().unwrap()),
+                },
+                weights(entry, width),
+            )
+        })
+    }
+
+    pub fn is_valid(block: &[u8]) -> bool {
+        split(block).is_some()
+    }
+
+    pub fn len(block: &[u8]) -> usize {
+        split(block).map_or(0, |(width, entries)| entries.len() / entry_len(width))
+    }
+
+    pub fn is_empty(block: &[u8]) -> bool {
+        Self::len(block) == 0
+    }
+}
+
+fn split(block: &[u8]) -> Option<(usize, &[u8])> {
+    let (width, entries) = block.split_first()?;
+    let width = *width as usize;
+    (matches!(width, 1 | 2 | 4) && entries.len() % entry_len(width) == 0)
+        .then_some((width, entries))
+}
+
+fn entry_len(width: usize) -> usize {
+    SUFFIX_LEN + width * 2
+}
+
+fn suffix(hash: &TokenHash) -> [u8; SUFFIX_LEN] {
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&hash.h1.to_be_bytes());
+    bytes[8..].copy_from_slice(&hash.h2.to_be_bytes());
+    bytes[PREFIX_LEN..].try_into().unwrap()
+}
+
+fn weights(entry: &[u8], width: usize) -> Weights {
+    let count = |bytes: &[u8]| {
+        let mut value = [0u8; 4];
+        value[..width].copy_from_slice(bytes);
+        u32::from_le_bytes(value)
+    };
+    Weights {
+        spam: count(&entry[SUFFIX_LEN..SUFFIX_LEN + width]),
+        ham: count(&entry[SUFFIX_LEN + width..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bayes::{TokenHash, Weights};
+
+    use super::TokenBlock;
+
+    #[test]
+    fn token_blocks() {
+        let mut tokens = Vec::new();
+        for i in 0..2000u64 {
+            let h1 = i.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+            tokens.push((
+                TokenHash { h1, h2: !h1 },
+                Weights {
+                    spam: (i % 7) as u32,
+                    ham: if i == 1234 { 70000 } else { (i % 5) as u32 },
+                },
+            ));
+        }
+
+        let blocks = TokenBlock::group(tokens.iter().copied());
+        let expected = tokens
+            .iter()
+            .filter(|(_, weights)| *weights != Weights::default())
+            .count();
+        assert_eq!(
+            blocks
+                .iter()
+                .map(|(_, block)| TokenBlock::len(block))
+                .sum::<usize>(),
+            expected
+        );
+
+        for (hash, weights) in &tokens {
+            let prefix = TokenBlock::prefix(hash);
+            let found = blocks
+                .iter()
+                .find(|(block_prefix, _)| *block_prefix == prefix)
+                .and_then(|(_, block)| TokenBlock::get(block, hash));
+            if *weights == Weights::default() {
+                assert_eq!(found, None);
+            } else {
+                assert_eq!(found, Some(*weights));
+            }
+
+            // Hashes sharing the prefix but absent from the block are not found
+            if let Some((_, block)) = blocks.iter().find(|(p, _)| *p == prefix) {
+                let missing = TokenHash {
+                    h1: hash.h1,
+                    h2: hash.h2 ^ 1,
+                };
+                assert_eq!(TokenBlock::get(block, &missing), None);
+            }
+        }
+
+        // Blocks are decoded back to the same tokens, in order
+        let mut decoded = blocks
+            .iter()
+            .flat_map(|(prefix, block)| TokenBlock::iter(block, *prefix))
+            .collect::<Vec<_>>();
+        let mut original = tokens
+            .into_iter()
+            .filter(|(_, weights)| *weights != Weights::default())
+            .collect::<Vec<_>>();
+        original.sort_unstable_by_key(|(hash, _)| *hash);
+        assert!(decoded.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        decoded.sort_unstable_by_key(|(hash, _)| *hash);
+        assert_eq!(decoded, original);
+
+        // Counts are stored using the narrowest width
+        let small =
+            TokenBlock::encode([(TokenHash { h1: 1, h2: 2 }, Weights { spam: 3, ham: 255 })]);
+        assert_eq!(small.len(), 1 + 14 + 2);
+        assert_eq!(TokenBlock::get(&[], &TokenHash::default()), None);
+        assert_eq!(TokenBlock::get(&[3, 0, 0], &TokenHash::default()), None);
+        assert!(TokenBlock::is_valid(&small));
+        assert!(TokenBlock::is_valid(&[1]));
+        assert!(!TokenBlock::is_valid(&[3, 0, 0]));
+        assert!(!TokenBlock::is_valid(&small[..small.len() - 1]));
+    }
+}
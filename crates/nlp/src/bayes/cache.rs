@@ -6,6 +6,7 @@
 
 use std::{
     hash::BuildHasherDefault,
+    sync::atomic::{AtomicU64, Ordering},
     time::{Duration, Instant},
 };
 
@@ -15,14 +16,28 @@ use parking_lot::Mutex;
 
 use super::{TokenHash, Weights};
 
+const GENERATION_STRIPES: usize = 64;
+
+type TokenLru<T> = LruCache<TokenHash, T, BuildHasherDefault<NoHashHasher<TokenHash>>>;
+
+// Tokens are partitioned into independently locked shards, each shard evicts
+// its own least recently used entries.
 #[derive(Debug)]
 pub struct BayesTokenCache {
-    positive: Mutex<LruCache<TokenHash, CacheItem, BuildHasherDefault<NoHashHasher<TokenHash>>>>,
-    negative: Mutex<LruCache<TokenHash, Instant, BuildHasherDefault<NoHashHasher<TokenHash>>>>,
+    shards: Box<[CacheShard]>,
+    // Bumped on each invalidation, values read from the store before an
+    // invalidation of the same stripe are not cached.
+    generations: [AtomicU64; GENERATION_STRIPES],
     ttl_negative: Duration,
     ttl_positive: Duration,
 }
 
+#[derive(Debug)]
+struct CacheShard {
+    positive: Mutex<TokenLru<CacheItem>>,
+    negative: Mutex<TokenLru<Instant>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct CacheItem {
     item: Weights,
@@ -30,18 +45,28 @@ pub struct CacheItem {
 }
 
 impl BayesTokenCache {
-    pub fn new(capacity: usize, ttl_positive: Duration, ttl_negative: Duration) -> Self {
+    pub fn new(
+        capacity: usize,
+        shards: usize,
+        ttl_positive: Duration,
+        ttl_negative: Duration,
+    ) -> Self {
+        let shards = shards.clamp(1, capacity.max(1));
+        let shard_capacity = capacity.div_ceil(shards);
         Self {
-            positive: Mutex::new(LruCache::with_hasher(capacity, Default::default())),
-            negative: Mutex::new(LruCache::with_hasher(capacity, Default::default())),
+            shards: (0..shards)
+                .map(|_| CacheShard::new(shard_capacity))
+                .collect(),
+            generations: std::array::from_fn(|_| AtomicU64::new(0)),
             ttl_negative,
             ttl_positive,
         }
     }
 
     pub fn get(&self, hash: &TokenHash) -> Option<Option<Weights>> {
+        let shard = self.shard(hash);
         {
-            let mut pos_cache = self.positive.lock();
+            let mut pos_cache = shard.positive.lock();
             if let Some(entry) = pos_cache.get_mut(hash) {
                 return if entry.valid_until >= Instant::now() {
                     Some(Some(entry.item))
@@ -52,7 +77,7 @@ impl BayesTokenCache {
             }
         }
         {
-            let mut neg_cache = self.negative.lock();
+            let mut neg_cache = shard.negative.lock();
             if let Some(entry) = neg_cache.get_mut(hash) {
                 return if *entry >= Instant::now() {
                     Some(None)
@@ -67,7 +92,7 @@ impl BayesTokenCache {
     }
 
     pub fn insert_positive(&self, hash: TokenHash, weights: Weights) {
-        self.positive.lock().insert(
+        self.shard(&hash).positive.lock().insert(
             hash,
             CacheItem {
                 item: weights,
@@ -77,14 +102,151 @@ impl BayesTokenCache {
     }
 
     pub fn insert_negative(&self, hash: TokenHash) {
-        self.negative
+        self.shard(&hash)
+            .negative
             .lock()
             .insert(hash, Instant::now() + self.ttl_negative);
     }
 
+    // Returns the generation to pass when caching a value read from the store
+    pub fn generation(&self, hash: &TokenHash) -> u64 {
+        self.generations[stripe(hash)].load(Ordering::Acquire)
+    }
+
+    // Caches a value read from the store, unless the token was invalidated since
+    // the read started. Returns false if the value was not cached.
+    pub fn insert_positive_at(&self, hash: TokenHash, weights: Weights, generation: u64) -> bool {
+        let mut pos_cache = self.shard(&hash).positive.lock();
+        if self.generation(&hash) == generation {
+            pos_cache.insert(
+                hash,
+                CacheItem {
+                    item: weights,
+                    valid_until: Instant::now() + self.ttl_positive,
+                },
+            );
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn insert_negative_at(&self, hash: TokenHash, generation: u64) -> bool {
+        let mut neg_cache = self.shard(&hash).negative.lock();
+        if self.generation(&hash) == generation {
+            neg_cache.insert(hash, Instant::now() + self.ttl_negative);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Returns up to max_entries unexpired positive entries, most recently used first.
+    // Recency is only tracked within a shard, so shards are interleaved.
+    pub fn export_positive(&self, max_entries: usize) -> Vec<(TokenHash, Weights)> {
+        let now = Instant::now();
+        let per_shard = self
+            .shards
+            .iter()
+            .map(|shard| {
+                shard
+                    .positive
+                    .lock()
+                    .iter()
+                    .rev()
+                    .filter(|(_, entry)| entry.valid_until >= now)
+                    .take(max_entries)
+                    .map(|(hash, entry)| (*hash, entry.item))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut entries = Vec::new();
+        let max_len = per_shard.iter().map(|shard| shard.len()).max().unwrap_or(0);
+        for idx in 0..max_len {
+            for shard in &per_shard {
+                if entries.len() == max_entries {
+                    return entries;
+                }
+                if let Some(entry) = shard.get(idx) {
+                    entries.push(*entry);
+                }
+            }
+        }
+        entries
+    }
+
+    // Restores entries returned by export_positive, which expire once the
+    // positive TTL has elapsed since they were exported.
+    pub fn import_positive(&self, entries: Vec<(TokenHash, Weights)>, age: Duration) -> usize {
+        let Some(ttl) = self
+            .ttl_positive
+            .checked_sub(age)
+            .filter(|ttl| !ttl.is_zero())
+        else {
+            return 0;
+        };
+        let valid_until = Instant::now() + ttl;
+        let mut count = 0;
+
+        // Inserted least recently used first to preserve the access order
+        for (hash, weights) in entries.into_iter().take(self.capacity()).rev() {
+            let mut pos_cache = self.shard(&hash).positive.lock();
+            if !pos_cache.contains_key(&hash) {
+                pos_cache.insert(
+                    hash,
+                    CacheItem {
+                        item: weights,
+                        valid_until,
+                    },
+                );
+                count += 1;
+            }
+        }
+
+        count
+    }
+
     pub fn invalidate(&self, hash: &TokenHash) {
-        if self.positive.lock().remove(hash).is_none() {
-            self.negative.lock().remove(hash);
+        self.generations[stripe(hash)].fetch_add(1, Ordering::AcqRel);
+        let shard = self.shard(hash);
+        if shard.positive.lock().remove(hash).is_none() {
+            shard.negative.lock().remove(hash);
+        }
+    }
+
+    // Removes all entries, reads in progress are not cached
+    pub fn clear(&self) {
+        for generation in &self.generations {
+            generation.fetch_add(1, Ordering::AcqRel);
+        }
+        for shard in self.shards.iter() {
+            shard.positive.lock().clear();
+            shard.negative.lock().clear();
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.positive.lock().capacity())
+            .sum()
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard(&self, hash: &TokenHash) -> &CacheShard {
+        &self.shards[((hash.h1 ^ hash.h2.rotate_left(32)) % self.shards.len() as u64) as usize]
+    }
+}
+
+impl CacheShard {
+    fn new(capacity: usize) -> Self {
+        Self {
+            positive: Mutex::new(LruCache::with_hasher(capacity, Default::default())),
+            negative: Mutex::new(LruCache::with_hasher(capacity, Default::default())),
         }
     }
 }
@@ -92,8 +254,8 @@ impl BayesTokenCache {
 impl Default for BayesTokenCache {
     fn default() -> Self {
         Self {
-            positive: Mutex::new(LruCache::with_hasher(1024, Default::default())),
-            negative: Mutex::new(LruCache::with_hasher(1024, Default::default())),
+            shards: vec![CacheShard::new(1024)].into_boxed_slice(),
+            generations: std::array::from_fn(|_| AtomicU64::new(0)),
             ttl_negative: Default::default(),
             ttl_positive: Default::default(),
         }
@@ -103,10 +265,143 @@ impl Default for BayesTokenCache {
 impl Clone for BayesTokenCache {
     fn clone(&self) -> Self {
         Self {
-            positive: Mutex::new(self.positive.lock().clone()),
-            negative: Mutex::new(self.negative.lock().clone()),
+            shards: self
+                .shards
+                .iter()
+                .map(|shard| CacheShard {
+                    positive: Mutex::new(shard.positive.lock().clone()),
+                    negative: Mutex::new(shard.negative.lock().clone()),
+                })
+                .collect(),
+            generations: std::array::from_fn(|idx| {
+                AtomicU64::new(self.generations[idx].load(Ordering::Acquire))
+            }),
             ttl_negative: self.ttl_negative,
             ttl_positive: self.ttl_positive,
         }
     }
 }
+
+fn stripe(hash: &TokenHash) -> usize {
+    ((hash.h1 ^ hash.h2) % GENERATION_STRIPES as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::bayes::{TokenHash, Weights};
+
+    use super::BayesTokenCache;
+
+    #[test]
+    fn cache_invalidation_race() {
+        let cache = BayesTokenCache::new(16, 1, Duration::from_secs(60), Duration::from_secs(60));
+        let hash = TokenHash { h1: 1, h2: 2 };
+        let weights = Weights { spam: 10, ham: 5 };
+
+        // A value read before a concurrent invalidation is not cached
+        let generation = cache.generation(&hash);
+        cache.invalidate(&hash);
+        assert!(!cache.insert_positive_at(hash, weights, generation));
+        assert!(!cache.insert_negative_at(hash, generation));
+        assert_eq!(cache.get(&hash), None);
+
+        // Reads started after the invalidation are cached
+        let generation = cache.generation(&hash);
+        assert!(cache.insert_positive_at(hash, weights, generation));
+        assert_eq!(cache.get(&hash), Some(Some(weights)));
+        cache.invalidate(&hash);
+        assert_eq!(cache.get(&hash), None);
+    }
+
+    #[test]
+    fn cache_export_import() {
+        let cache = BayesTokenCache::new(3, 1, Duration::from_secs(60), Duration::from_secs(60));
+        for h1 in 0..4 {
+            cache.insert_positive(
+                TokenHash { h1, h2: 0 },
+                Weights {
+                    spam: h1 as u32,
+                    ham: 1,
+                },
+            );
+        }
+        cache.insert_negative(TokenHash { h1: 10, h2: 0 });
+        cache.get(&TokenHash { h1: 1, h2: 0 });
+
+        // Most recently used first, negative entries are not exported
+        let exported = cache.export_positive(10);
+        assert_eq!(
+            exported.iter().map(|(hash, _)| hash.h1).collect::<Vec<_>>(),
+            vec![1, 3, 2]
+        );
+        assert_eq!(cache.export_positive(2).len(), 2);
+
+        let restored = BayesTokenCache::new(2, 1, Duration::from_secs(60), Duration::from_secs(60));
+        assert_eq!(
+            restored.import_positive(exported.clone(), Duration::from_secs(30)),
+            2
+        );
+        assert_eq!(
+            restored.get(&TokenHash { h1: 1, h2: 0 }),
+            Some(Some(Weights { spam: 1, ham: 1 }))
+        );
+        assert_eq!(restored.get(&TokenHash { h1: 2, h2: 0 }), None);
+
+        // Expired exports are discarded
+        let restored = BayesTokenCache::new(3, 1, Duration::from_secs(60), Duration::from_secs(60));
+        assert_eq!(
+            restored.import_positive(exported, Duration::from_secs(60)),
+            0
+        );
+    }
+
+    #[test]
+    fn cache_shards() {
+        let cache = BayesTokenCache::new(64, 8, Duration::from_secs(60), Duration::from_secs(60));
+        assert_eq!(cache.num_shards(), 8);
+        assert_eq!(cache.capacity(), 64);
+
+        let hashes = (0..32)
+            .map(|h1| TokenHash { h1, h2: h1 * 7 })
+            .collect::<Vec<_>>();
+        for hash in &hashes {
+            cache.insert_positive(
+                *hash,
+                Weights {
+                    spam: hash.h1 as u32,
+                    ham: 0,
+                },
+            );
+        }
+        assert_eq!(cache.export_positive(100).len(), hashes.len());
+        assert_eq!(cache.export_positive(10).len(), 10);
+
+        // Invalidations reach the shard holding the token
+        for hash in &hashes[..16] {
+            cache.invalidate(hash);
+        }
+        assert!(hashes[..16].iter().all(|hash| cache.get(hash).is_none()));
+        assert!(hashes[16..].iter().all(|hash| cache.get(hash)
+            == Some(Some(Weights {
+                spam: hash.h1 as u32,
+                ham: 0
+            }))));
+
+        // Clearing empties all shards and discards reads in progress
+        let generation = cache.generation(&hashes[0]);
+        cache.insert_negative(TokenHash { h1: 100, h2: 0 });
+        cache.clear();
+        assert!(hashes.iter().all(|hash| cache.get(hash).is_none()));
+        assert_eq!(cache.get(&TokenHash { h1: 100, h2: 0 }), None);
+        assert!(!cache.insert_positive_at(hashes[0], Weights::default(), generation));
+
+        // Shards are capped by the capacity
+        assert_eq!(
+            BayesTokenCache::new(2, 8, Duration::from_secs(60), Duration::from_secs(60))
+                .num_shards(),
+            2
+        );
+    }
+}
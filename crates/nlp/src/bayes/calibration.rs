@@ -0,0 +1,126 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use serde::{Deserialize, Serialize};
+
+// Maps raw classifier scores to calibrated probabilities using a monotonic
+// step function fitted with the pool adjacent violators algorithm.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IsotonicCalibration {
+    pub points: Vec<(f64, f64)>,
+}
+
+impl IsotonicCalibration {
+    // Fits a calibration from (raw score, is spam) samples
+    pub fn fit(samples: impl IntoIterator<Item = (f64, bool)>) -> Option<Self> {
+        let mut samples = samples
+            .into_iter()
+            .filter(|(score, _)| score.is_finite())
+            .collect::<Vec<_>>();
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Each block holds (sum of scores, sum of outcomes, number of samples)
+        let mut blocks: Vec<(f64, f64, f64)> = Vec::with_capacity(samples.len());
+        for (score, is_spam) in samples {
+            blocks.push((score, if is_spam { 1.0 } else { 0.0 }, 1.0));
+
+            // Merge blocks while they violate monotonicity
+            while blocks.len() > 1 {
+                let (score, outcome, count) = blocks[blocks.len() - 1];
+                let prev = blocks[blocks.len() - 2];
+                if prev.1 / prev.2 > outcome / count {
+                    blocks.pop();
+                    let prev = blocks.last_mut().unwrap();
+                    prev.0 += score;
+                    prev.1 += outcome;
+                    prev.2 += count;
+                } else {
+                    break;
+                }
+            }
+        }
+
+        Some(IsotonicCalibration {
+            points: blocks
+                .into_iter()
+                .map(|(score, outcome, count)| (score / count, outcome / count))
+                .collect(),
+        })
+    }
+
+    pub fn calibrate(&self, score: f64) -> f64 {
+        let (Some(first), Some(last)) = (self.points.first(), self.points.last()) else {
+            return score;
+        };
+
+        if score <= first.0 {
+            first.1
+        } else if score >= last.0 {
+            last.1
+        } else {
+            // Interpolate between the two surrounding points
+            let pos = self.points.partition_point(|(x, _)| *x <= score);
+            let (x0, y0) = self.points[pos - 1];
+            let (x1, y1) = self.points[pos];
+            if x1 > x0 {
+                y0 + (y1 - y0) * (score - x0) / (x1 - x0)
+            } else {
+                y0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IsotonicCalibration;
+
+    #[test]
+    fn isotonic_calibration() {
+        assert_eq!(IsotonicCalibration::fit([]), None);
+
+        let calibration = IsotonicCalibration::fit([
+            (0.1, false),
+            (0.2, false),
+            (0.3, true),
+            (0.4, false),
+            (0.7, true),
+            (0.8, false),
+            (0.9, true),
+            (0.95, true),
+        ])
+        .unwrap();
+
+        // Violators are pooled into monotonic blocks
+        assert_eq!(
+            calibration.points,
+            vec![
+                (0.1, 0.0),
+                (0.2, 0.0),
+                (0.35, 0.5),
+                (0.75, 0.5),
+                (0.9, 1.0),
+                (0.95, 1.0)
+            ]
+        );
+        assert!(calibration
+            .points
+            .windows(2)
+            .all(|w| w[0].0 <= w[1].0 && w[0].1 <= w[1].1));
+
+        // Scores are interpolated and clamped to the fitted range
+        assert_eq!(calibration.calibrate(0.0), 0.0);
+        assert_eq!(calibration.calibrate(0.5), 0.5);
+        assert_eq!(calibration.calibrate(0.99), 1.0);
+        assert!(calibration.calibrate(0.8375) > 0.5 && calibration.calibrate(0.8375) < 1.0);
+
+        // Without points raw scores are returned
+        assert_eq!(IsotonicCalibration::default().calibrate(0.42), 0.42);
+    }
+}
@@ -13,6 +13,11 @@ const FEATURE_WEIGHT: [f64; 8] = [1.0, 3125.0, 256.0, 27.0, 1.0, 0.0, 0.0, 0.0];
 
 // Credits: ported from RSpamd
 impl BayesClassifier {
+    // Whether the token was seen often enough to be considered by the classifier
+    pub fn is_match(&self, weights: &Weights) -> bool {
+        weights.spam + weights.ham >= self.min_token_hits.max(1)
+    }
+
     pub fn classify<T>(&self, tokens: T, ham_learns: u32, spam_learns: u32) -> Option<f64>
     where
         T: Iterator<Item = OsbToken<Weights>>,
@@ -21,16 +26,13 @@ impl BayesClassifier {
             return None;
         }
 
-        let mut processed_tokens = 0;
-        let mut total_spam_prob = 0.0;
-        let mut total_ham_prob = 0.0;
+        let mut probabilities = Vec::new();
 
         for token in tokens {
             let weights = token.inner;
-            let total_count = weights.spam + weights.ham;
 
-            if total_count >= self.min_token_hits {
-                let total_count = total_count as f64;
+            if self.is_match(&weights) {
+                let total_count = (weights.spam + weights.ham) as f64;
                 let spam_freq = weights.spam as f64 / f64::max(1.0, spam_learns as f64);
                 let ham_freq = weights.ham as f64 / f64::max(1.0, ham_learns as f64);
                 let spam_prob = spam_freq / (spam_freq + ham_freq);
@@ -44,17 +46,36 @@ impl BayesClassifier {
                     || (bayes_spam_prob < 0.5 && bayes_spam_prob > 0.5 - self.min_prob_strength))
                 {
                     let bayes_ham_prob = prob_combine(ham_prob, total_count, w, 0.5);
-                    total_spam_prob += bayes_spam_prob.ln();
-                    total_ham_prob += bayes_ham_prob.ln();
-                    processed_tokens += 1;
+                    probabilities.push((bayes_spam_prob, bayes_ham_prob));
                 }
             }
         }
 
-        if processed_tokens == 0 || self.min_tokens > 0 && processed_tokens < self.min_tokens {
+        // The minimum applies to the candidate tokens, a max_tokens below min_tokens
+        // would otherwise prevent any verdict
+        if probabilities.is_empty()
+            || self.min_tokens > 0 && (probabilities.len() as u32) < self.min_tokens
+        {
             return None;
         }
 
+        // Keep the tokens with the most extreme spam probabilities (SpamBayes-style)
+        let max_tokens = self.max_tokens as usize;
+        if max_tokens > 0 && probabilities.len() > max_tokens {
+            probabilities.select_nth_unstable_by(max_tokens - 1, |a, b| {
+                (b.0 - 0.5).abs().total_cmp(&(a.0 - 0.5).abs())
+            });
+            probabilities.truncate(max_tokens);
+        }
+
+        let processed_tokens = probabilities.len() as u32;
+        let mut total_spam_prob = 0.0;
+        let mut total_ham_prob = 0.0;
+        for (bayes_spam_prob, bayes_ham_prob) in probabilities {
+            total_spam_prob += bayes_spam_prob.ln();
+            total_ham_prob += bayes_ham_prob.ln();
+        }
+
         let (h, s) = if total_spam_prob > -300.0 && total_ham_prob > -300.0 {
             /* Fisher value is low enough to apply inv_chi_square */
             (
@@ -147,3 +168,110 @@ fn normalize_probability(x: f64, bias: f64) -> f64 {
 fn prob_combine(prob: f64, cnt: f64, weight: f64, assumed: f64) -> f64 {
     ((weight) * (assumed) + (cnt) * (prob)) / ((weight) + (cnt))
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{bayes::Weights, tokenizers::osb::OsbToken};
+
+    use super::BayesClassifier;
+
+    fn tokens(spam: u32, ham: u32, weak: u32) -> Vec<OsbToken<Weights>> {
+        let mut tokens = Vec::new();
+        for _ in 0..spam {
+            tokens.push(OsbToken {
+                inner: Weights { spam: 90, ham: 2 },
+                idx: 0,
+            });
+        }
+        for _ in 0..ham {
+            tokens.push(OsbToken {
+                inner: Weights { spam: 2, ham: 90 },
+                idx: 0,
+            });
+        }
+        for _ in 0..weak {
+            tokens.push(OsbToken {
+                inner: Weights { spam: 40, ham: 60 },
+                idx: 0,
+            });
+        }
+        tokens
+    }
+
+    #[test]
+    fn classify_top_tokens() {
+        let full = BayesClassifier {
+            min_tokens: 1,
+            ..BayesClassifier::new()
+        };
+        let top = BayesClassifier {
+            max_tokens: 5,
+            ..full.clone()
+        };
+
+        // Without enough strong tokens both classifiers agree
+        let strong = tokens(3, 0, 0);
+        assert_eq!(
+            full.classify(strong.clone().into_iter(), 200, 200),
+            top.classify(strong.into_iter(), 200, 200)
+        );
+
+        // Many weak ham tokens drown the strong spam tokens unless they are ignored
+        let noisy = tokens(4, 0, 60);
+        let full_score = full.classify(noisy.clone().into_iter(), 200, 200);
+        let top_score = top.classify(noisy.into_iter(), 200, 200).unwrap();
+        assert!(full_score.is_none_or(|score| score < 0.5), "{full_score:?}");
+        assert!(top_score > 0.9, "{top_score}");
+
+        // The most extreme tokens are selected regardless of their class
+        let mixed = tokens(1, 4, 60);
+        assert_eq!(
+            top.classify(mixed.into_iter(), 200, 200),
+            full.classify(tokens(1, 4, 0).into_iter(), 200, 200)
+        );
+
+        // Selecting at least as many tokens as available is the same as the full classification
+        let all = BayesClassifier {
+            max_tokens: 64,
+            ..full.clone()
+        };
+        let noisy = tokens(4, 0, 60);
+        assert_eq!(
+            full.classify(noisy.clone().into_iter(), 200, 200),
+            all.classify(noisy.into_iter(), 200, 200)
+        );
+    }
+
+    #[test]
+    fn classify_top_tokens_script_defaults() {
+        // Parameters passed by the spam filter scripts, [2, 11, 0.05, 200]
+        let classifier = BayesClassifier {
+            min_token_hits: 2,
+            min_tokens: 11,
+            min_prob_strength: 0.05,
+            min_learns: 200,
+            max_tokens: 5,
+        };
+
+        // Selecting fewer tokens than min_tokens still produces a verdict
+        let score = classifier
+            .classify(tokens(12, 0, 30).into_iter(), 200, 200)
+            .unwrap();
+        assert!(score > 0.9, "{score}");
+
+        // Messages with fewer candidate tokens than min_tokens produce no verdict
+        assert_eq!(
+            classifier.classify(tokens(10, 0, 0).into_iter(), 200, 200),
+            None
+        );
+
+        // Tokens seen fewer than min_token_hits times are not matched
+        assert!(classifier.is_match(&Weights { spam: 1, ham: 1 }));
+        assert!(!classifier.is_match(&Weights { spam: 1, ham: 0 }));
+        assert!(!BayesClassifier {
+            min_token_hits: 0,
+            ..classifier
+        }
+        .is_match(&Weights::default()));
+    }
+}
@@ -0,0 +1,121 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use super::Weights;
+
+// Avoids infinite divergences for tokens only seen in spam or ham
+const SMOOTHING: f64 = 0.001;
+
+// Computes the Kullback-Leibler divergence between the per-token spam probabilities
+// of two models over their shared vocabulary, weighted by how often each token
+// was seen in the first model.
+#[derive(Debug, Clone, Copy)]
+pub struct BayesDivergence {
+    learns: [(f64, f64); 2],
+    total_divergence: f64,
+    total_weight: f64,
+    tokens: u64,
+}
+
+impl BayesDivergence {
+    pub fn new(learns: Weights, other_learns: Weights) -> Self {
+        BayesDivergence {
+            learns: [
+                (learns.spam as f64, learns.ham as f64),
+                (other_learns.spam as f64, other_learns.ham as f64),
+            ],
+            total_divergence: 0.0,
+            total_weight: 0.0,
+            tokens: 0,
+        }
+    }
+
+    pub fn add(&mut self, weights: Weights, other_weights: Weights) {
+        let total_count = weights.spam + weights.ham;
+        if total_count == 0 || other_weights.spam + other_weights.ham == 0 {
+            return;
+        }
+
+        let p = spam_probability(weights, self.learns[0]);
+        let q = spam_probability(other_weights, self.learns[1]);
+        let divergence = p * (p / q).ln() + (1.0 - p) * ((1.0 - p) / (1.0 - q)).ln();
+
+        self.total_divergence += divergence * total_count as f64;
+        self.total_weight += total_count as f64;
+        self.tokens += 1;
+    }
+
+    pub fn divergence(&self) -> Option<f64> {
+        if self.total_weight > 0.0 {
+            Some(self.total_divergence / self.total_weight)
+        } else {
+            None
+        }
+    }
+
+    pub fn shared_tokens(&self) -> u64 {
+        self.tokens
+    }
+}
+
+fn spam_probability(weights: Weights, (spam_learns, ham_learns): (f64, f64)) -> f64 {
+    let spam_freq = weights.spam as f64 / f64::max(1.0, spam_learns);
+    let ham_freq = weights.ham as f64 / f64::max(1.0, ham_learns);
+    (spam_freq + SMOOTHING) / (spam_freq + ham_freq + (2.0 * SMOOTHING))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bayes::Weights;
+
+    use super::BayesDivergence;
+
+    #[test]
+    fn bayes_divergence() {
+        let learns = Weights { spam: 10, ham: 10 };
+        let tokens = [
+            Weights { spam: 8, ham: 1 },
+            Weights { spam: 0, ham: 5 },
+            Weights { spam: 3, ham: 3 },
+        ];
+
+        // Identical models do not diverge
+        let mut divergence = BayesDivergence::new(learns, learns);
+        for token in tokens {
+            divergence.add(token, token);
+        }
+        assert_eq!(divergence.shared_tokens(), 3);
+        assert!(divergence.divergence().unwrap().abs() < 1e-12);
+
+        // Opposite models diverge more than similar ones
+        let mut similar = BayesDivergence::new(learns, learns);
+        let mut opposite = BayesDivergence::new(learns, learns);
+        for token in tokens {
+            similar.add(
+                token,
+                Weights {
+                    spam: token.spam + 1,
+                    ham: token.ham,
+                },
+            );
+            opposite.add(
+                token,
+                Weights {
+                    spam: token.ham,
+                    ham: token.spam,
+                },
+            );
+        }
+        assert!(similar.divergence().unwrap() > 0.0);
+        assert!(opposite.divergence().unwrap() > similar.divergence().unwrap());
+
+        // Tokens missing from either model are not part of the shared vocabulary
+        let mut divergence = BayesDivergence::new(learns, learns);
+        divergence.add(tokens[0], Weights::default());
+        assert_eq!(divergence.shared_tokens(), 0);
+        assert_eq!(divergence.divergence(), None);
+    }
+}
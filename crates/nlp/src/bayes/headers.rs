@@ -0,0 +1,193 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use serde::{Deserialize, Serialize};
+
+// Headers whose values are turned into feature tokens instead of being tokenized
+// as text, which would split domains and verdicts into meaningless words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HeaderFeature {
+    Dkim,
+    AuthResults,
+}
+
+// Messages carrying more feature tokens are most likely crafted to flood the model
+const MAX_TOKENS: usize = 32;
+
+impl HeaderFeature {
+    pub const ALL: [HeaderFeature; 2] = [HeaderFeature::Dkim, HeaderFeature::AuthResults];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "dkim" => Some(HeaderFeature::Dkim),
+            "auth-results" => Some(HeaderFeature::AuthResults),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HeaderFeature::Dkim => "dkim",
+            HeaderFeature::AuthResults => "auth-results",
+        }
+    }
+
+    pub fn header_name(&self) -> &'static str {
+        match self {
+            HeaderFeature::Dkim => "DKIM-Signature",
+            HeaderFeature::AuthResults => "Authentication-Results",
+        }
+    }
+
+    // Returns the feature tokens of the enabled headers, each header is a (name, raw value)
+    // pair. Tokens contain a colon, which the tokenizer never emits, so they cannot
+    // collide with regular words. Authentication results added by the listed
+    // authserv-ids are skipped, as these are only present once the message was delivered.
+    pub fn tokens<'x>(
+        features: &[HeaderFeature],
+        headers: impl IntoIterator<Item = (&'x str, &'x str)>,
+        ignore_authserv_ids: &[String],
+    ) -> Vec<String> {
+        let mut tokens = Vec::new();
+        if features.is_empty() {
+            return tokens;
+        }
+
+        for (name, value) in headers {
+            let Some(feature) = features
+                .iter()
+                .find(|feature| name.eq_ignore_ascii_case(feature.header_name()))
+            else {
+                continue;
+            };
+            match feature {
+                HeaderFeature::Dkim => {
+                    if let Some(domain) = tags(value).find_map(|(tag, value)| {
+                        tag.eq_ignore_ascii_case("d")
+                            .then(|| value.trim_end_matches('.').to_lowercase())
+                    }) {
+                        if !domain.is_empty() {
+                            tokens.push(format!("dkim:{domain}"));
+                        }
+                    }
+                }
+                HeaderFeature::AuthResults => {
+                    let value = strip_comments(value);
+                    let mut results = value.split(';');
+                    let authserv_id = results
+                        .next()
+                        .and_then(|id| id.split_whitespace().next())
+                        .unwrap_or_default();
+                    if ignore_authserv_ids
+                        .iter()
+                        .any(|id| id.eq_ignore_ascii_case(authserv_id))
+                    {
+                        continue;
+                    }
+                    for result in results {
+                        if let Some((method, verdict)) = result
+                            .split_whitespace()
+                            .next()
+                            .and_then(|result| result.split_once('='))
+                        {
+                            // Method versions ("dkim/1") are ignored
+                            let method = method.split('/').next().unwrap_or_default().trim();
+                            if !method.is_empty() && !verdict.is_empty() {
+                                tokens.push(format!(
+                                    "auth:{}={}",
+                                    method.to_lowercase(),
+                                    verdict.to_lowercase()
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        tokens.sort_unstable();
+        tokens.dedup();
+        tokens.truncate(MAX_TOKENS);
+        tokens
+    }
+}
+
+fn tags(value: &str) -> impl Iterator<Item = (&str, &str)> {
+    value.split(';').filter_map(|tag| {
+        let (name, value) = tag.split_once('=')?;
+        Some((name.trim(), value.trim()))
+    })
+}
+
+// Removes the comments from a header value, which may contain semicolons
+fn strip_comments(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut depth = 0u32;
+    let mut escaped = false;
+    for ch in value.chars() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if depth > 0 => escaped = true,
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            _ if depth == 0 => result.push(ch),
+            _ => {}
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeaderFeature;
+
+    #[test]
+    fn header_features() {
+        let headers = [
+            (
+                "DKIM-Signature",
+                "v=1; a=rsa-sha256; c=relaxed/relaxed;\r\n d=Example.COM.; s=sel;\r\n h=from:to; bh=abc=; b=def=",
+            ),
+            ("dkim-signature", "v=1; d=esp.example.net; s=x; b=abc"),
+            (
+                "Authentication-Results",
+                "mx.example.org (Stalwart; version=1);\r\n dkim=pass (good signature) header.d=example.com;\r\n spf=SoftFail smtp.mailfrom=example.com; dmarc/1=none",
+            ),
+            ("Authentication-Results", "mail.local; spf=pass"),
+            ("Authentication-Results", "mx.example.org; none"),
+            ("Subject", "d=spam.example; spf=pass"),
+        ];
+
+        assert_eq!(
+            HeaderFeature::tokens(&HeaderFeature::ALL, headers, &["Mail.Local".to_string()]),
+            [
+                "auth:dkim=pass",
+                "auth:dmarc=none",
+                "auth:spf=softfail",
+                "dkim:esp.example.net",
+                "dkim:example.com"
+            ]
+        );
+        assert_eq!(
+            HeaderFeature::tokens(&[HeaderFeature::Dkim], headers, &[]),
+            ["dkim:esp.example.net", "dkim:example.com"]
+        );
+
+        // Absent or malformed headers produce no tokens
+        assert!(HeaderFeature::tokens(&HeaderFeature::ALL, [("From", "a@b.c")], &[]).is_empty());
+        assert!(HeaderFeature::tokens(
+            &HeaderFeature::ALL,
+            [
+                ("DKIM-Signature", "garbage"),
+                ("Authentication-Results", "")
+            ],
+            &[]
+        )
+        .is_empty());
+        assert!(HeaderFeature::tokens(&[], headers, &[]).is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use serde::{Deserialize, Serialize};
+
+const EPOCHS: usize = 1000;
+const LEARNING_RATE: f64 = 0.5;
+const L2_PENALTY: f64 = 0.001;
+
+// Logistic regression combining the Bayes score with other message features into
+// a single probability. Features are identified by name so that a model fitted
+// on an older feature vector keeps reading the same values.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LogisticModel {
+    pub features: Vec<String>,
+    pub weights: Vec<f64>,
+    pub bias: f64,
+}
+
+impl LogisticModel {
+    // Fits the weights from (feature values, is spam) samples using gradient descent
+    // with L2 regularization. Returns None unless both classes are represented.
+    pub fn fit(features: Vec<String>, samples: &[(Vec<f64>, bool)]) -> Option<Self> {
+        let num_features = features.len();
+        let samples = samples
+            .iter()
+            .filter(|(values, _)| {
+                values.len() == num_features && values.iter().all(|value| value.is_finite())
+            })
+            .collect::<Vec<_>>();
+        if num_features == 0
+            || !samples.iter().any(|(_, is_spam)| *is_spam)
+            || !samples.iter().any(|(_, is_spam)| !*is_spam)
+        {
+            return None;
+        }
+
+        // Features are standardized while fitting, so that a single learning rate
+        // suits features of different scales
+        let count = samples.len() as f64;
+        let mut mean = vec![0.0; num_features];
+        let mut scale = vec![0.0; num_features];
+        for (values, _) in &samples {
+            for (mean, value) in mean.iter_mut().zip(values) {
+                *mean += value / count;
+            }
+        }
+        for (values, _) in &samples {
+            for ((scale, mean), value) in scale.iter_mut().zip(&mean).zip(values) {
+                *scale += (value - mean).powi(2) / count;
+            }
+        }
+        for scale in &mut scale {
+            *scale = if *scale > f64::EPSILON {
+                scale.sqrt()
+            } else {
+                // Constant features carry no signal
+                f64::INFINITY
+            };
+        }
+
+        let mut weights = vec![0.0; num_features];
+        let mut bias = 0.0;
+        let mut gradient = vec![0.0; num_features];
+        for _ in 0..EPOCHS {
+            gradient.iter_mut().for_each(|gradient| *gradient = 0.0);
+            let mut bias_gradient = 0.0;
+            for (values, is_spam) in &samples {
+                let mut z = bias;
+                for (idx, value) in values.iter().enumerate() {
+                    z += weights[idx] * (value - mean[idx]) / scale[idx];
+                }
+                let error = sigmoid(z) - if *is_spam { 1.0 } else { 0.0 };
+                for (idx, value) in values.iter().enumerate() {
+                    gradient[idx] += error * (value - mean[idx]) / scale[idx];
+                }
+                bias_gradient += error;
+            }
+            for (weight, gradient) in weights.iter_mut().zip(&gradient) {
+                *weight -= LEARNING_RATE * (gradient / count + L2_PENALTY * *weight);
+            }
+            bias -= LEARNING_RATE * bias_gradient / count;
+        }
+
+        // Weights are rescaled so that predictions are made on the raw values
+        for ((weight, mean), scale) in weights.iter_mut().zip(&mean).zip(&scale) {
+            *weight /= scale;
+            bias -= *weight * mean;
+        }
+
+        Some(LogisticModel {
+            features,
+            weights,
+            bias,
+        })
+    }
+
+    // Returns the spam probability, values missing from the input are treated as zero
+    pub fn predict(&self, values: &[f64]) -> f64 {
+        sigmoid(
+            self.weights
+                .iter()
+                .zip(values.iter().chain(std::iter::repeat(&0.0)))
+                .fold(self.bias, |z, (weight, value)| z + weight * value),
+        )
+    }
+}
+
+fn sigmoid(z: f64) -> f64 {
+    1.0 / (1.0 + (-z).exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LogisticModel;
+
+    #[test]
+    fn logistic_fit() {
+        // Spam is mostly explained by the Bayes score, failed authentication adds to it
+        let mut samples = Vec::new();
+        for i in 0..100 {
+            let score = i as f64 / 100.0;
+            let auth = if i % 3 == 0 { 0.0 } else { 1.0 };
+            let is_spam = score + (1.0 - auth) * 0.3 > 0.6;
+            samples.push((vec![score, auth, 7.0], is_spam));
+        }
+        let features = vec![
+            "bayes_score".to_string(),
+            "auth_dkim".to_string(),
+            "constant".to_string(),
+        ];
+        let model = LogisticModel::fit(features.clone(), &samples).unwrap();
+        assert!(model.weights[0] > 0.0);
+        assert!(model.weights[1] < 0.0);
+        assert_eq!(model.weights[2], 0.0);
+
+        let errors = samples
+            .iter()
+            .filter(|(values, is_spam)| (model.predict(values) > 0.5) != *is_spam)
+            .count();
+        assert!(errors <= 5, "{errors} errors");
+        assert!(model.predict(&[0.5, 0.0, 7.0]) > model.predict(&[0.5, 1.0, 7.0]));
+
+        // Both classes are required
+        let ham = samples
+            .iter()
+            .filter(|(_, is_spam)| !is_spam)
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(LogisticModel::fit(features, &ham), None);
+        assert_eq!(LogisticModel::fit(vec![], &samples), None);
+    }
+}
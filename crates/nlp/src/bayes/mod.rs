@@ -11,8 +11,20 @@ use serde::{Deserialize, Serialize};
 
 use crate::tokenizers::osb::Gram;
 
+use self::{
+    calibration::IsotonicCalibration, headers::HeaderFeature, logistic::LogisticModel,
+    normalize::TokenClass, tokenize::CaseFolding,
+};
+
+pub mod block;
 pub mod cache;
+pub mod calibration;
 pub mod classify;
+pub mod divergence;
+pub mod headers;
+pub mod logistic;
+pub mod normalize;
+pub mod prune;
 pub mod tokenize;
 pub mod train;
 
@@ -23,15 +35,43 @@ pub struct BayesModel {
     pub ham_learns: u32,
 }
 
+// Settings a model was trained with, these have to be applied identically
+// when training and classifying in order to produce the same tokens.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct BayesMetadata {
+    pub case_folding: CaseFolding,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub normalize: Vec<TokenClass>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<HeaderFeature>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calibration: Option<IsotonicCalibration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logistic: Option<LogisticModel>,
+    // Sorted list of redundant tokens that are ignored when training and classifying
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pruned: Vec<TokenHash>,
+}
+
+impl BayesMetadata {
+    pub fn is_pruned(&self, hash: &TokenHash) -> bool {
+        !self.pruned.is_empty() && self.pruned.binary_search(hash).is_ok()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BayesClassifier {
     pub min_token_hits: u32,
     pub min_tokens: u32,
     pub min_prob_strength: f64,
     pub min_learns: u32,
+    // Only the most discriminative tokens are combined, zero combines all tokens
+    #[serde(default)]
+    pub max_tokens: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct TokenHash {
     pub h1: u64,
     pub h2: u64,
@@ -50,6 +90,7 @@ impl BayesClassifier {
             min_tokens: 11,
             min_prob_strength: 0.05,
             min_learns: 200,
+            max_tokens: 0,
         }
     }
 }
@@ -89,6 +130,16 @@ impl std::hash::Hash for TokenHash {
 
 impl nohash::IsEnabled for TokenHash {}
 
+impl TokenHash {
+    // Models share a single token cache, cached entries are keyed by model
+    pub fn for_model(&self, model_seed: u64) -> TokenHash {
+        TokenHash {
+            h1: self.h1 ^ model_seed,
+            h2: self.h2,
+        }
+    }
+}
+
 impl From<i64> for Weights {
     fn from(value: i64) -> Self {
         Weights {
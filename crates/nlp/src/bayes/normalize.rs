@@ -0,0 +1,290 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenClass {
+    Date,
+    Time,
+    Phone,
+    Currency,
+    Percentage,
+    Number,
+}
+
+const CURRENCY_SYMBOLS: &[char] = &['$', '€', '£', '¥', '₹', '₽', '₩', '₺', '₿'];
+const CURRENCY_CODES: &[&str] = &[
+    "usd", "eur", "gbp", "jpy", "chf", "cad", "aud", "cny", "inr", "btc",
+];
+
+impl TokenClass {
+    pub const ALL: [TokenClass; 6] = [
+        TokenClass::Date,
+        TokenClass::Time,
+        TokenClass::Phone,
+        TokenClass::Currency,
+        TokenClass::Percentage,
+        TokenClass::Number,
+    ];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "date" => Some(TokenClass::Date),
+            "time" => Some(TokenClass::Time),
+            "phone" => Some(TokenClass::Phone),
+            "currency" => Some(TokenClass::Currency),
+            "percentage" => Some(TokenClass::Percentage),
+            "number" => Some(TokenClass::Number),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenClass::Date => "date",
+            TokenClass::Time => "time",
+            TokenClass::Phone => "phone",
+            TokenClass::Currency => "currency",
+            TokenClass::Percentage => "percentage",
+            TokenClass::Number => "number",
+        }
+    }
+
+    // Class tokens contain characters the tokenizer never emits, so they cannot
+    // collide with regular words.
+    pub fn tag(&self) -> &'static str {
+        match self {
+            TokenClass::Date => "<DATE>",
+            TokenClass::Time => "<TIME>",
+            TokenClass::Phone => "<PHONE>",
+            TokenClass::Currency => "<CURRENCY>",
+            TokenClass::Percentage => "<PERCENT>",
+            TokenClass::Number => "<NUMBER>",
+        }
+    }
+
+    // Returns the first enabled class matching the beginning of the text, along
+    // with the length of the match in bytes.
+    pub fn find(text: &str, classes: &[TokenClass]) -> Option<(TokenClass, usize)> {
+        let first = text.chars().next()?;
+        if !first.is_ascii_digit() && first != '+' && !CURRENCY_SYMBOLS.contains(&first) {
+            return None;
+        }
+
+        // Classes are tried from the most to the least specific one
+        TokenClass::ALL
+            .iter()
+            .filter(|class| classes.contains(class))
+            .find_map(|class| {
+                match class {
+                    TokenClass::Date => match_date(text),
+                    TokenClass::Time => match_time(text),
+                    TokenClass::Phone => match_phone(text),
+                    TokenClass::Currency => match_currency(text),
+                    TokenClass::Percentage => match_percentage(text),
+                    TokenClass::Number => match_amount(text),
+                }
+                .filter(|len| is_boundary(&text[*len..]))
+                .map(|len| (*class, len))
+            })
+    }
+}
+
+fn match_date(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let first = count_digits(bytes);
+    let separator = *bytes.get(first)?;
+    if !matches!(separator, b'-' | b'/' | b'.') {
+        return None;
+    }
+    let second = count_digits(&bytes[first + 1..]);
+    let pos = first + 1 + second;
+    if bytes.get(pos) != Some(&separator) {
+        return None;
+    }
+    let third = count_digits(&bytes[pos + 1..]);
+
+    // YYYY-MM-DD or DD/MM/YYYY, DD.MM.YY and similar
+    if (first == 4 && (1..=2).contains(&second) && (1..=2).contains(&third))
+        || ((1..=2).contains(&first) && (1..=2).contains(&second) && matches!(third, 2 | 4))
+    {
+        Some(pos + 1 + third)
+    } else {
+        None
+    }
+}
+
+fn match_time(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let hours = count_digits(bytes);
+    if !(1..=2).contains(&hours) || bytes.get(hours) != Some(&b':') {
+        return None;
+    }
+    let mut pos = hours + 1;
+    if count_digits(&bytes[pos..]) != 2 {
+        return None;
+    }
+    pos += 2;
+    if bytes.get(pos) == Some(&b':') && count_digits(&bytes[pos + 1..]) == 2 {
+        pos += 3;
+    }
+
+    // Optional 12-hour clock suffix
+    let suffix_start = pos + usize::from(bytes.get(pos) == Some(&b' '));
+    if let Some(suffix) = bytes.get(suffix_start..suffix_start + 2) {
+        if (suffix.eq_ignore_ascii_case(b"am") || suffix.eq_ignore_ascii_case(b"pm"))
+            && is_boundary(&text[suffix_start + 2..])
+        {
+            pos = suffix_start + 2;
+        }
+    }
+
+    Some(pos)
+}
+
+fn match_phone(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    if bytes.first() != Some(&b'+') {
+        return None;
+    }
+
+    let mut digits = 0;
+    let mut end = 0;
+    for (pos, ch) in bytes.iter().enumerate().skip(1) {
+        match ch {
+            b'0'..=b'9' => {
+                digits += 1;
+                end = pos + 1;
+            }
+            b' ' | b'-' | b'.' | b'(' | b')' => {}
+            _ => break,
+        }
+    }
+
+    if (8..=15).contains(&digits) {
+        Some(end)
+    } else {
+        None
+    }
+}
+
+fn match_currency(text: &str) -> Option<usize> {
+    // Symbol followed by an amount, e.g. $4,999.00
+    if let Some(symbol) = text
+        .chars()
+        .next()
+        .filter(|ch| CURRENCY_SYMBOLS.contains(ch))
+    {
+        let start = symbol.len_utf8();
+        let start = start + usize::from(text.as_bytes().get(start) == Some(&b' '));
+        return match_amount(&text[start..]).map(|len| start + len);
+    }
+
+    // Amount followed by a symbol or a currency code, e.g. 20 € or 100USD
+    let amount = match_amount(text)?;
+    let start = amount + usize::from(text.as_bytes().get(amount) == Some(&b' '));
+    let rest = &text[start..];
+    if let Some(symbol) = rest
+        .chars()
+        .next()
+        .filter(|ch| CURRENCY_SYMBOLS.contains(ch))
+    {
+        Some(start + symbol.len_utf8())
+    } else {
+        CURRENCY_CODES
+            .iter()
+            .find(|code| {
+                rest.get(..3)
+                    .is_some_and(|prefix| prefix.eq_ignore_ascii_case(code))
+                    && is_boundary(&rest[3..])
+            })
+            .map(|_| start + 3)
+    }
+}
+
+fn match_percentage(text: &str) -> Option<usize> {
+    let amount = match_amount(text)?;
+    let start = amount + usize::from(text.as_bytes().get(amount) == Some(&b' '));
+    if text.as_bytes().get(start) == Some(&b'%') {
+        Some(start + 1)
+    } else {
+        None
+    }
+}
+
+// Digits with optional thousands and decimal separators, ending with a digit
+fn match_amount(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut end = count_digits(bytes);
+    if end == 0 {
+        return None;
+    }
+    while matches!(bytes.get(end), Some(b',' | b'.')) {
+        let digits = count_digits(&bytes[end + 1..]);
+        if digits == 0 {
+            break;
+        }
+        end += 1 + digits;
+    }
+    Some(end)
+}
+
+fn count_digits(bytes: &[u8]) -> usize {
+    bytes.iter().take_while(|ch| ch.is_ascii_digit()).count()
+}
+
+fn is_boundary(text: &str) -> bool {
+    !text.chars().next().is_some_and(|ch| ch.is_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bayes::tokenize::BayesTokenizer;
+
+    use super::TokenClass;
+
+    #[test]
+    fn token_classes() {
+        for (text, expected) in [
+            ("2024-06-01 and", Some((TokenClass::Date, 10))),
+            ("01/06/24", Some((TokenClass::Date, 8))),
+            ("10:30 pm today", Some((TokenClass::Time, 8))),
+            ("23:59:01", Some((TokenClass::Time, 8))),
+            ("+1 555 123 4567", Some((TokenClass::Phone, 15))),
+            ("$4,999.00!", Some((TokenClass::Currency, 9))),
+            ("€ 20", Some((TokenClass::Currency, 6))),
+            ("100 USD", Some((TokenClass::Currency, 7))),
+            ("20% off", Some((TokenClass::Percentage, 3))),
+            ("1,000,000 times", Some((TokenClass::Number, 9))),
+            ("100USDT", None),
+            ("4you", None),
+            ("+123", None),
+            ("hello", None),
+        ] {
+            assert_eq!(TokenClass::find(text, &TokenClass::ALL), expected, "{text}");
+        }
+
+        // Disabled classes fall back to the next matching one
+        assert_eq!(
+            TokenClass::find("$4,999.00", &[TokenClass::Number]),
+            None,
+            "currency symbols are not numbers"
+        );
+        assert_eq!(
+            TokenClass::find("2024-06-01", &[TokenClass::Number]),
+            Some((TokenClass::Number, 4))
+        );
+
+        assert_eq!(
+            BayesTokenizer::new("Only $4,999.00 until 2024-06-01, save 20% now")
+                .with_normalization(&TokenClass::ALL)
+                .collect::<Vec<_>>(),
+            ["<CURRENCY>", "<DATE>", "save", "<PERCENT>"]
+        );
+    }
+}
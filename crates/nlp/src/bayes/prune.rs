@@ -0,0 +1,169 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::collections::{HashMap, HashSet};
+
+use crate::tokenizers::osb::OsbToken;
+
+use super::TokenHash;
+
+// Position 0 represents Unigram weights, must match the classifier
+const FEATURE_WEIGHT: [f64; 8] = [1.0, 3125.0, 256.0, 27.0, 1.0, 0.0, 0.0, 0.0];
+
+// Finds OSB tokens produced from the same window that occur in (almost) the same
+// documents, one token of each such pair carries no additional signal.
+#[derive(Debug, Default)]
+pub struct CooccurrenceAnalyzer {
+    documents: u32,
+    occurrences: HashMap<TokenHash, (u32, usize)>,
+    pairs: HashMap<(TokenHash, TokenHash), u32>,
+}
+
+impl CooccurrenceAnalyzer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_document<T>(&mut self, tokens: T)
+    where
+        T: IntoIterator<Item = OsbToken<TokenHash>>,
+    {
+        let mut seen_tokens = HashSet::new();
+        let mut seen_pairs = HashSet::new();
+        let mut window: Vec<OsbToken<TokenHash>> = Vec::new();
+
+        for token in tokens {
+            // A new window starts with each unigram
+            if token.idx == 0 {
+                window.clear();
+            }
+            for other in &window {
+                if other.inner != token.inner {
+                    let pair = if other.inner < token.inner {
+                        (other.inner, token.inner)
+                    } else {
+                        (token.inner, other.inner)
+                    };
+                    if seen_pairs.insert(pair) {
+                        *self.pairs.entry(pair).or_default() += 1;
+                    }
+                }
+            }
+            if seen_tokens.insert(token.inner) {
+                let entry = self
+                    .occurrences
+                    .entry(token.inner)
+                    .or_insert((0, token.idx));
+                entry.0 += 1;
+            }
+            window.push(token);
+        }
+
+        self.documents += 1;
+    }
+
+    pub fn documents(&self) -> u32 {
+        self.documents
+    }
+
+    // Returns the tokens to prune, given the minimum Jaccard similarity of the
+    // documents both tokens appear in and the minimum number of documents.
+    pub fn redundant_tokens(&self, threshold: f64, min_documents: u32) -> Vec<TokenHash> {
+        let mut candidates = self
+            .pairs
+            .iter()
+            .filter_map(|((a, b), both)| {
+                let (count_a, idx_a) = self.occurrences.get(a)?;
+                let (count_b, idx_b) = self.occurrences.get(b)?;
+                if *both < min_documents {
+                    return None;
+                }
+                let similarity = *both as f64 / (count_a + count_b - both) as f64;
+                if similarity < threshold {
+                    return None;
+                }
+
+                // Keep the token with the highest feature weight
+                let (weight_a, weight_b) = (FEATURE_WEIGHT[*idx_a], FEATURE_WEIGHT[*idx_b]);
+                let (keep, prune) = if weight_a > weight_b || (weight_a == weight_b && a < b) {
+                    (*a, *b)
+                } else {
+                    (*b, *a)
+                };
+                Some((similarity, keep, prune))
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_unstable_by(|a, b| {
+            b.0.total_cmp(&a.0)
+                .then_with(|| (a.1, a.2).cmp(&(b.1, b.2)))
+        });
+
+        // Tokens that are kept because of a pair are never pruned afterwards
+        let mut kept = HashSet::new();
+        let mut pruned = HashSet::new();
+        for (_, keep, prune) in candidates {
+            if !pruned.contains(&keep) && !kept.contains(&prune) {
+                kept.insert(keep);
+                pruned.insert(prune);
+            }
+        }
+
+        let mut pruned = pruned.into_iter().collect::<Vec<_>>();
+        pruned.sort_unstable();
+        pruned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use crate::{
+        bayes::TokenHash,
+        tokenizers::osb::{OsbToken, OsbTokenizer},
+    };
+
+    use super::CooccurrenceAnalyzer;
+
+    #[test]
+    fn cooccurrence_pruning() {
+        let tokens = |text: &'static str| {
+            OsbTokenizer::<_, TokenHash>::new(text.split_whitespace().map(Cow::from), 5)
+                .collect::<Vec<_>>()
+        };
+        let mut analyzer = CooccurrenceAnalyzer::new();
+        for text in [
+            "viagra cheap online",
+            "buy viagra cheap today",
+            "viagra cheap pharmacy",
+            "meeting agenda today",
+            "cheap flights online",
+        ] {
+            analyzer.add_document(tokens(text));
+        }
+        assert_eq!(analyzer.documents(), 5);
+
+        // "viagra" and "viagra cheap" always appear together, only the bigram is kept
+        let viagra = tokens("viagra")[0].inner;
+        let viagra_cheap = tokens("viagra cheap")[1].inner;
+        let pruned = analyzer.redundant_tokens(0.95, 3);
+        assert_eq!(pruned, vec![viagra]);
+        assert!(!pruned.contains(&viagra_cheap));
+
+        // Nothing is pruned without enough support
+        assert!(analyzer.redundant_tokens(0.95, 4).is_empty());
+
+        // Lower thresholds also prune partially correlated tokens
+        let mut analyzer = CooccurrenceAnalyzer::new();
+        analyzer.add_document(vec![OsbToken {
+            inner: viagra,
+            idx: 0,
+        }]);
+        analyzer.add_document(tokens("viagra cheap"));
+        assert_eq!(analyzer.redundant_tokens(0.5, 1), vec![viagra]);
+        assert!(analyzer.redundant_tokens(0.6, 1).is_empty());
+    }
+}
@@ -6,7 +6,10 @@
 
 use std::borrow::Cow;
 
+use serde::{Deserialize, Serialize};
+
 use crate::{
+    bayes::normalize::TokenClass,
     language::{
         detect::{LanguageDetector, MIN_LANGUAGE_SCORE},
         stemmer::STEMMER_MAP,
@@ -25,6 +28,23 @@ pub struct BayesTokenizer<'x> {
     stemmer: Stemmer,
     stop_words: Option<&'static phf::Set<&'static str>>,
     tokens: Vec<Cow<'x, str>>,
+    language: Language,
+    case_folding: CaseFolding,
+    normalize: Vec<TokenClass>,
+    skip_until: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CaseFolding {
+    /// Unicode lowercase mapping
+    #[default]
+    Lowercase,
+    /// Full Unicode case folding
+    Unicode,
+    /// Full Unicode case folding with language specific rules (i.e. Turkish dotted and dotless i)
+    Locale,
+    /// Tokens are used as is
+    None,
 }
 
 enum Stemmer {
@@ -55,8 +75,26 @@ impl<'x> BayesTokenizer<'x> {
             },
             stop_words: STOP_WORDS[language as usize],
             tokens: vec![],
+            language,
+            case_folding: CaseFolding::default(),
+            normalize: vec![],
+            skip_until: 0,
         }
     }
+
+    pub fn with_case_folding(mut self, case_folding: CaseFolding) -> Self {
+        self.case_folding = case_folding;
+        self
+    }
+
+    pub fn with_normalization(mut self, classes: &[TokenClass]) -> Self {
+        self.normalize = classes.to_vec();
+        self
+    }
+
+    fn fold_case(&self, word: &'x str) -> Cow<'x, str> {
+        self.case_folding.fold(word, self.language)
+    }
 }
 
 impl<'x> Iterator for BayesTokenizer<'x> {
@@ -70,18 +108,30 @@ impl<'x> Iterator for BayesTokenizer<'x> {
         loop {
             let token = self.tokenizer.next()?;
 
+            // Replace numbers, dates and amounts with their class token
+            if token.from < self.skip_until {
+                continue;
+            } else if !self.normalize.is_empty() {
+                if let Some((class, len)) =
+                    TokenClass::find(&self.text[token.from..], &self.normalize)
+                {
+                    self.skip_until = token.from + len;
+                    return Some(class.tag().into());
+                }
+            }
+
             let word: Cow<str> = match token.word {
                 TokenType::Alphabetic(word) => {
-                    let word = word.to_lowercase();
+                    let word = self.fold_case(word);
                     if self
                         .stop_words
-                        .map_or(false, |sw| sw.contains(word.as_str()))
+                        .map_or(false, |sw| sw.contains(word.as_ref()))
                     {
                         continue;
                     }
                     match &self.stemmer {
                         Stemmer::IndoEuropean(stemmer) => match stemmer.stem(&word) {
-                            Cow::Borrowed(_) => word.into(),
+                            Cow::Borrowed(_) => word,
                             Cow::Owned(stemmed_word) => stemmed_word.into(),
                         },
                         Stemmer::Mandarin => {
@@ -108,29 +158,24 @@ impl<'x> Iterator for BayesTokenizer<'x> {
                                 continue;
                             }
                         }
-                        Stemmer::None => word.into(),
+                        Stemmer::None => word,
                     }
                 }
 
                 TokenType::Url(word) => {
                     if let Some((_, host)) = word.split_once("://") {
-                        host.split_once('/')
-                            .map_or(host, |(h, _)| h)
-                            .to_lowercase()
-                            .into()
+                        self.fold_case(host.split_once('/').map_or(host, |(h, _)| h))
                     } else {
                         continue;
                     }
                 }
                 TokenType::IpAddr(word) => word.into(),
-                TokenType::UrlNoScheme(word) => word
-                    .split_once('/')
-                    .map_or(word, |(h, _)| h)
-                    .to_lowercase()
-                    .into(),
+                TokenType::UrlNoScheme(word) => {
+                    self.fold_case(word.split_once('/').map_or(word, |(h, _)| h))
+                }
                 TokenType::Alphanumeric(word)
                 | TokenType::Email(word)
-                | TokenType::UrlNoHost(word) => word.to_lowercase().into(),
+                | TokenType::UrlNoHost(word) => self.fold_case(word),
                 TokenType::Other(ch) => {
                     if SYMBOLS.contains(&ch) {
                         (&self.text[token.from..token.to]).into()
@@ -150,6 +195,71 @@ impl<'x> Iterator for BayesTokenizer<'x> {
     }
 }
 
+impl CaseFolding {
+    pub fn fold<'x>(&self, word: &'x str, language: Language) -> Cow<'x, str> {
+        match self {
+            CaseFolding::Lowercase => word.to_lowercase().into(),
+            CaseFolding::Unicode => case_fold(word, false).into(),
+            CaseFolding::Locale => case_fold(word, language == Language::Turkish).into(),
+            CaseFolding::None => word.into(),
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "lowercase" => Some(CaseFolding::Lowercase),
+            "unicode" => Some(CaseFolding::Unicode),
+            "locale" => Some(CaseFolding::Locale),
+            "none" => Some(CaseFolding::None),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CaseFolding::Lowercase => "lowercase",
+            CaseFolding::Unicode => "unicode",
+            CaseFolding::Locale => "locale",
+            CaseFolding::None => "none",
+        }
+    }
+}
+
+// Full case folding (CaseFolding.txt, statuses C and F) for the characters where
+// it differs from the lowercase mapping, optionally with the Turkic (T) rules.
+fn case_fold(word: &str, is_turkic: bool) -> String {
+    let mut result = String::with_capacity(word.len());
+    for ch in word.chars() {
+        match ch {
+            'I' if is_turkic => result.push('ı'),
+            '\u{130}' if is_turkic => result.push('i'),
+            '\u{130}' => result.push_str("i\u{307}"),
+            'ß' | 'ẞ' => result.push_str("ss"),
+            'ς' => result.push('σ'),
+            'ſ' => result.push('s'),
+            'ϐ' => result.push('β'),
+            'ϑ' => result.push('θ'),
+            'ϕ' => result.push('φ'),
+            'ϖ' => result.push('π'),
+            'ϰ' => result.push('κ'),
+            'ϱ' => result.push('ρ'),
+            'ϵ' => result.push('ε'),
+            'ẛ' => result.push('ṡ'),
+            '\u{345}' | '\u{1FBE}' => result.push('\u{3B9}'),
+            '\u{149}' => result.push_str("\u{2BC}n"),
+            '\u{1F0}' => result.push_str("j\u{30C}"),
+            'ﬀ' => result.push_str("ff"),
+            'ﬁ' => result.push_str("fi"),
+            'ﬂ' => result.push_str("fl"),
+            'ﬃ' => result.push_str("ffi"),
+            'ﬄ' => result.push_str("ffl"),
+            'ﬅ' | 'ﬆ' => result.push_str("st"),
+            _ => result.extend(ch.to_lowercase()),
+        }
+    }
+    result
+}
+
 fn number_to_tag(prefix: &str, num: &str) -> String {
     format!(
         "{}_{}_{}",
@@ -1147,7 +1257,10 @@ pub static SYMBOLS: phf::Set<char> = phf::phf_set! {
 mod tests {
     use std::borrow::Cow;
 
-    use crate::bayes::tokenize::BayesTokenizer;
+    use crate::{
+        bayes::tokenize::{BayesTokenizer, CaseFolding},
+        language::Language,
+    };
 
     #[test]
     fn bayes_tokenizer() {
@@ -1239,4 +1352,75 @@ mod tests {
             assert_eq!(input, expect,);
         }
     }
+
+    #[test]
+    fn bayes_case_folding() {
+        for (word, language, case_folding, expect) in [
+            // Turkish dotted and dotless i
+            (
+                "İSTANBUL",
+                Language::Turkish,
+                CaseFolding::Locale,
+                "istanbul",
+            ),
+            ("ISPARTA", Language::Turkish, CaseFolding::Locale, "ısparta"),
+            ("ıspanak", Language::Turkish, CaseFolding::Locale, "ıspanak"),
+            ("ISPARTA", Language::English, CaseFolding::Locale, "isparta"),
+            (
+                "İSTANBUL",
+                Language::Turkish,
+                CaseFolding::Unicode,
+                "i\u{307}stanbul",
+            ),
+            (
+                "İSTANBUL",
+                Language::Turkish,
+                CaseFolding::Lowercase,
+                "i\u{307}stanbul",
+            ),
+            (
+                "ISPARTA",
+                Language::Turkish,
+                CaseFolding::Lowercase,
+                "isparta",
+            ),
+            // Full case folding
+            ("STRAẞE", Language::German, CaseFolding::Unicode, "strasse"),
+            ("Straße", Language::German, CaseFolding::Unicode, "strasse"),
+            ("Straße", Language::German, CaseFolding::Lowercase, "straße"),
+            ("ΣΟΦΟΣ", Language::Greek, CaseFolding::Unicode, "σοφοσ"),
+            ("ΣΟΦΟΣ", Language::Greek, CaseFolding::Lowercase, "σοφος"),
+            ("ﬁnance", Language::English, CaseFolding::Unicode, "finance"),
+            // No folding
+            ("FREE", Language::English, CaseFolding::None, "FREE"),
+        ] {
+            assert_eq!(
+                case_folding.fold(word, language),
+                expect,
+                "{word:?} {language:?} {case_folding:?}"
+            );
+        }
+
+        // Training and classification produce the same tokens regardless of case
+        let tokens = |text, case_folding| {
+            BayesTokenizer::new(text)
+                .with_case_folding(case_folding)
+                .collect::<Vec<_>>()
+        };
+        for case_folding in [
+            CaseFolding::Lowercase,
+            CaseFolding::Unicode,
+            CaseFolding::Locale,
+        ] {
+            assert_eq!(
+                tokens("Get your FREE Vouchers at WWW.EXAMPLE.COM", case_folding),
+                tokens("get your free vouchers at www.example.com", case_folding),
+                "{case_folding:?}"
+            );
+        }
+        assert_ne!(
+            tokens("Get your FREE vouchers", CaseFolding::None),
+            tokens("Get your free vouchers", CaseFolding::None),
+        );
+    }
 }
@@ -1,5 +1,6 @@
 pub mod bayes;
 pub mod language;
+pub mod lsh;
 pub mod tokenizers;
 
 #[cfg(test)]
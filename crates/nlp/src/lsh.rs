@@ -0,0 +1,118 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
+
+pub const LSH_BANDS: usize = 8;
+pub const LSH_ROWS: usize = 4;
+const SHINGLE_SIZE: usize = 3;
+
+// MinHash signature split into bands, two texts with a Jaccard similarity of s
+// share at least one band with probability 1 - (1 - s^LSH_ROWS)^LSH_BANDS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LshSignature {
+    pub bands: [u64; LSH_BANDS],
+}
+
+impl LshSignature {
+    pub fn new<T, I>(tokens: I) -> Option<Self>
+    where
+        T: AsRef<str>,
+        I: IntoIterator<Item = T>,
+    {
+        let tokens = tokens.into_iter().collect::<Vec<_>>();
+        if tokens.is_empty() {
+            return None;
+        }
+
+        let mut min_hashes = [u64::MAX; LSH_BANDS * LSH_ROWS];
+        for shingle in tokens.windows(SHINGLE_SIZE.min(tokens.len())) {
+            let mut hasher = Xxh3::new();
+            for token in shingle {
+                hasher.update(token.as_ref().as_bytes());
+                hasher.update(&[0]);
+            }
+            let hash = hasher.digest();
+
+            for (idx, min_hash) in min_hashes.iter_mut().enumerate() {
+                *min_hash = (*min_hash).min(permute(hash, idx as u64));
+            }
+        }
+
+        let mut bands = [0u64; LSH_BANDS];
+        for (band, rows) in bands.iter_mut().zip(min_hashes.chunks_exact(LSH_ROWS)) {
+            let mut bytes = [0u8; LSH_ROWS * 8];
+            for (chunk, row) in bytes.chunks_exact_mut(8).zip(rows) {
+                chunk.copy_from_slice(&row.to_be_bytes());
+            }
+            *band = xxh3_64(&bytes);
+        }
+
+        Some(LshSignature { bands })
+    }
+
+    pub fn cluster_id(&self) -> u64 {
+        self.bands[0]
+    }
+}
+
+// Universal hashing with odd multipliers derived from the hash function index
+fn permute(hash: u64, idx: u64) -> u64 {
+    let seed = splitmix(idx);
+    (hash ^ (seed >> 32))
+        .wrapping_mul(seed | 1)
+        .wrapping_add(splitmix(seed))
+}
+
+fn splitmix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LshSignature;
+
+    #[test]
+    fn lsh_signature() {
+        let words = |text: &'static str| text.split_whitespace();
+        let original = LshSignature::new(words(
+            "congratulations you have been selected to receive a free gift card \
+             worth five hundred dollars click the link below to claim your prize \
+             before the offer expires at the end of this week",
+        ))
+        .unwrap();
+        let variant = LshSignature::new(words(
+            "congratulations you have been selected to receive a free gift card \
+             worth five hundred dollars click the link below to claim your reward \
+             before the offer expires at the end of this week",
+        ))
+        .unwrap();
+        let unrelated = LshSignature::new(words(
+            "hi team the quarterly planning meeting has been moved to thursday \
+             afternoon please review the attached agenda and send me your updates",
+        ))
+        .unwrap();
+
+        assert!(original
+            .bands
+            .iter()
+            .zip(variant.bands.iter())
+            .any(|(a, b)| a == b));
+        assert!(!original
+            .bands
+            .iter()
+            .zip(unrelated.bands.iter())
+            .any(|(a, b)| a == b));
+        assert_eq!(
+            original,
+            LshSignature::new(words("congratulations you have been selected to receive a free gift card worth five hundred dollars click the link below to claim your prize before the offer expires at the end of this week")).unwrap()
+        );
+        assert_eq!(LshSignature::new(Vec::<&str>::new()), None);
+    }
+}
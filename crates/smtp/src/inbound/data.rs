@@ -542,8 +542,15 @@ impl<T: SessionStream> Session<T> {
                     .map(|s| (s, name))
             })
         {
+            // Spam filtering is skipped when all recipients opted out
+            let opt_outs = self.spam_filter_opt_outs().await;
             let params = self
                 .build_script_parameters("data")
+                .set_variable(
+                    "spam_opt_out.all",
+                    opt_outs > 0 && opt_outs == self.data.rcpt_to.len(),
+                )
+                .set_variable("spam_opt_out.any", opt_outs > 0)
                 .with_message(edited_message.as_ref().unwrap_or(&raw_message))
                 .with_auth_headers(&headers)
                 .set_variable(
@@ -622,6 +629,7 @@ impl<T: SessionStream> Session<T> {
                     ScriptModification::SetEnvelope { name, value } => {
                         self.data.apply_envelope_modification(name, value);
                     }
+                    ScriptModification::SetVariable { .. } => {}
                 }
             }
         }
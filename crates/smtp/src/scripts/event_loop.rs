@@ -128,6 +128,14 @@ impl RunScript for Server {
                                 },
                             )
                             .await;
+                        modifications.retain(|modification| {
+                            if let ScriptModification::SetVariable { name, value } = modification {
+                                instance.set_global_variable(name.clone(), value.clone());
+                                false
+                            } else {
+                                true
+                            }
+                        });
                     }
                     Event::Keep { message_id, .. } => {
                         keep_id = message_id;
@@ -121,6 +121,58 @@ impl<T: SessionStream> Session<T> {
         params
     }
 
+    // Returns the number of recipients that opted out of spam filtering. Each local
+    // recipient costs a directory lookup, the opt-out attribute itself is cached
+    // along with the access token of the account.
+    pub async fn spam_filter_opt_outs(&self) -> usize {
+        if !self.server.core.spam.opt_out {
+            return 0;
+        }
+        let Some(directory) = self
+            .server
+            .eval_if::<String, _>(
+                &self.server.core.smtp.session.rcpt.directory,
+                self,
+                self.data.session_id,
+            )
+            .await
+            .and_then(|name| self.server.get_directory(&name))
+        else {
+            return 0;
+        };
+
+        let mut opt_outs = 0;
+        for rcpt in &self.data.rcpt_to {
+            let account_id = match self
+                .server
+                .email_to_id(directory, &rcpt.address_lcase, self.data.session_id)
+                .await
+            {
+                Ok(Some(account_id)) => account_id,
+                Ok(None) => continue,
+                Err(err) => {
+                    trc::error!(err
+                        .span_id(self.data.session_id)
+                        .caused_by(trc::location!())
+                        .details("Failed to lookup recipient."));
+                    continue;
+                }
+            };
+            match self.server.get_cached_access_token(account_id).await {
+                Ok(access_token) if access_token.spam_filter_opt_out => opt_outs += 1,
+                Ok(_) => {}
+                Err(err) => {
+                    trc::error!(err
+                        .span_id(self.data.session_id)
+                        .caused_by(trc::location!())
+                        .details("Failed to obtain access token."));
+                }
+            }
+        }
+
+        opt_outs
+    }
+
     pub async fn run_script(
         &self,
         script_id: String,
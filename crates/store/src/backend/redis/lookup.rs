@@ -39,6 +39,31 @@ impl RedisStore {
         }
     }
 
+    pub async fn key_set_many(
+        &self,
+        values: Vec<(Vec<u8>, Vec<u8>)>,
+        expires: Option<u64>,
+    ) -> trc::Result<()> {
+        match &self.pool {
+            RedisPool::Single(pool) => {
+                self.key_set_many_(
+                    pool.get().await.map_err(into_error)?.as_mut(),
+                    values,
+                    expires,
+                )
+                .await
+            }
+            RedisPool::Cluster(pool) => {
+                self.key_set_many_(
+                    pool.get().await.map_err(into_error)?.as_mut(),
+                    values,
+                    expires,
+                )
+                .await
+            }
+        }
+    }
+
     pub async fn key_incr(
         &self,
         key: Vec<u8>,
@@ -67,6 +92,19 @@ impl RedisStore {
         }
     }
 
+    pub async fn key_incr_many(&self, updates: Vec<(Vec<u8>, i64)>) -> trc::Result<()> {
+        match &self.pool {
+            RedisPool::Single(pool) => {
+                self.key_incr_many_(pool.get().await.map_err(into_error)?.as_mut(), updates)
+                    .await
+            }
+            RedisPool::Cluster(pool) => {
+                self.key_incr_many_(pool.get().await.map_err(into_error)?.as_mut(), updates)
+                    .await
+            }
+        }
+    }
+
     pub async fn key_delete(&self, key: Vec<u8>) -> trc::Result<()> {
         match &self.pool {
             RedisPool::Single(pool) => {
@@ -166,6 +204,23 @@ impl RedisStore {
         }
     }
 
+    async fn key_set_many_(
+        &self,
+        conn: &mut impl AsyncCommands,
+        values: Vec<(Vec<u8>, Vec<u8>)>,
+        expires: Option<u64>,
+    ) -> trc::Result<()> {
+        let mut pipe = redis::pipe();
+        for (key, value) in &values {
+            if let Some(expires) = expires {
+                pipe.set_ex(key, value, expires).ignore();
+            } else {
+                pipe.set(key, value).ignore();
+            }
+        }
+        pipe.query_async::<()>(conn).await.map_err(into_error)
+    }
+
     async fn key_incr_(
         &self,
         conn: &mut impl AsyncCommands,
@@ -188,6 +243,18 @@ impl RedisStore {
         }
     }
 
+    async fn key_incr_many_(
+        &self,
+        conn: &mut impl AsyncCommands,
+        updates: Vec<(Vec<u8>, i64)>,
+    ) -> trc::Result<()> {
+        let mut pipe = redis::pipe();
+        for (key, value) in &updates {
+            pipe.incr(key, *value).ignore();
+        }
+        pipe.query_async::<()>(conn).await.map_err(into_error)
+    }
+
     async fn key_delete_(&self, conn: &mut impl AsyncCommands, key: Vec<u8>) -> trc::Result<()> {
         conn.del(key).await.map_err(into_error)
     }
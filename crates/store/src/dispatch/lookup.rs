@@ -81,6 +81,42 @@ impl LookupStore {
         .caused_by(trc::location!())
     }
 
+    pub async fn key_set_many(
+        &self,
+        values: Vec<(Vec<u8>, Vec<u8>)>,
+        expires: Option<u64>,
+    ) -> trc::Result<()> {
+        if values.is_empty() {
+            return Ok(());
+        }
+
+        match self {
+            LookupStore::Store(store) => {
+                let mut batch = BatchBuilder::new();
+                let expires = expires.map_or(u64::MAX, |expires| now() + expires);
+                for (key, value) in values {
+                    batch.ops.push(Operation::Value {
+                        class: ValueClass::Lookup(LookupClass::Key(key)),
+                        op: ValueOp::Set(
+                            KeySerializer::new(value.len() + U64_LEN)
+                                .write(expires)
+                                .write(value.as_slice())
+                                .finalize()
+                                .into(),
+                        ),
+                    });
+                }
+                store.write(batch.build()).await.map(|_| ())
+            }
+            #[cfg(feature = "redis")]
+            LookupStore::Redis(store) => store.key_set_many(values, expires).await,
+            LookupStore::Query(_) | LookupStore::Memory(_) => {
+                Err(trc::StoreEvent::NotSupported.into_err())
+            }
+        }
+        .caused_by(trc::location!())
+    }
+
     pub async fn counter_incr(
         &self,
         key: Vec<u8>,
@@ -131,6 +167,31 @@ impl LookupStore {
         .caused_by(trc::location!())
     }
 
+    pub async fn counter_incr_many(&self, updates: Vec<(Vec<u8>, i64)>) -> trc::Result<()> {
+        if updates.is_empty() {
+            return Ok(());
+        }
+
+        match self {
+            LookupStore::Store(store) => {
+                let mut batch = BatchBuilder::new();
+                for (key, value) in updates {
+                    batch.ops.push(Operation::Value {
+                        class: ValueClass::Lookup(LookupClass::Counter(key)),
+                        op: ValueOp::AtomicAdd(value),
+                    });
+                }
+                store.write(batch.build()).await.map(|_| ())
+            }
+            #[cfg(feature = "redis")]
+            LookupStore::Redis(store) => store.key_incr_many(updates).await,
+            LookupStore::Query(_) | LookupStore::Memory(_) => {
+                Err(trc::StoreEvent::NotSupported.into_err())
+            }
+        }
+        .caused_by(trc::location!())
+    }
+
     pub async fn key_delete(&self, key: Vec<u8>) -> trc::Result<()> {
         match self {
             LookupStore::Store(store) => {
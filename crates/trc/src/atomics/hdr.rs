@@ -0,0 +1,153 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::MetricType;
+
+use super::array::AtomicU64Array;
+
+// Log-linear buckets with 6 bits of sub-bucket precision (relative error below 1.6%),
+// tracking values up to u32::MAX.
+const SUB_BUCKET_BITS: u32 = 6;
+const SUB_BUCKET_HALF: u64 = 1 << SUB_BUCKET_BITS;
+const MAX_VALUE: u64 = u32::MAX as u64;
+const BUCKET_COUNT: usize = (SUB_BUCKET_HALF
+    * (u64::BITS - (MAX_VALUE.leading_zeros() + SUB_BUCKET_BITS) + 1) as u64)
+    as usize;
+
+pub const EXPORTED_QUANTILES: [f64; 3] = [0.5, 0.95, 0.99];
+
+pub struct AtomicHdrHistogram {
+    id: MetricType,
+    buckets: AtomicU64Array<BUCKET_COUNT>,
+    sum: AtomicU64,
+    count: AtomicU64,
+    max: AtomicU64,
+}
+
+impl AtomicHdrHistogram {
+    pub const fn new(id: MetricType) -> Self {
+        Self {
+            id,
+            buckets: AtomicU64Array::new(),
+            sum: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, value: u64) {
+        let value = value.min(MAX_VALUE);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.max.fetch_max(value, Ordering::Relaxed);
+        self.buckets.add(bucket_index(value), 1);
+    }
+
+    pub fn id(&self) -> MetricType {
+        self.id
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.sum.load(Ordering::Relaxed)
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn average(&self) -> f64 {
+        let sum = self.sum();
+        let count = self.count();
+        if count > 0 {
+            sum as f64 / count as f64
+        } else {
+            0.0
+        }
+    }
+
+    // Returns the highest value equivalent to the value at the given quantile (0.0 - 1.0)
+    pub fn percentile(&self, quantile: f64) -> Option<u64> {
+        let counts = self
+            .buckets
+            .inner()
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        let total = counts.iter().sum::<u64>();
+        if total == 0 {
+            return None;
+        }
+
+        let target = ((quantile.clamp(0.0, 1.0) * total as f64).ceil() as u64).max(1);
+        let mut cumulative = 0;
+        for (idx, count) in counts.into_iter().enumerate() {
+            cumulative += count;
+            if cumulative >= target {
+                return Some(bucket_upper_bound(idx).min(self.max.load(Ordering::Relaxed)));
+            }
+        }
+
+        None
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.count.load(Ordering::Relaxed) > 0
+    }
+}
+
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKET_HALF * 2 {
+        value as usize
+    } else {
+        let shift = u64::BITS - value.leading_zeros() - SUB_BUCKET_BITS - 1;
+        (SUB_BUCKET_HALF * (shift as u64 + 1) + ((value >> shift) - SUB_BUCKET_HALF)) as usize
+    }
+}
+
+fn bucket_upper_bound(idx: usize) -> u64 {
+    let idx = idx as u64;
+    if idx < SUB_BUCKET_HALF * 2 {
+        idx
+    } else {
+        let shift = idx / SUB_BUCKET_HALF - 1;
+        let sub_bucket = idx % SUB_BUCKET_HALF + SUB_BUCKET_HALF;
+        ((sub_bucket + 1) << shift) - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::MetricType;
+
+    use super::{bucket_index, bucket_upper_bound, AtomicHdrHistogram, BUCKET_COUNT, MAX_VALUE};
+
+    #[test]
+    fn hdr_histogram() {
+        // Bucket boundaries are contiguous
+        assert_eq!(bucket_index(MAX_VALUE), BUCKET_COUNT - 1);
+        for idx in 0..BUCKET_COUNT - 1 {
+            assert_eq!(bucket_index(bucket_upper_bound(idx)), idx);
+            assert_eq!(bucket_index(bucket_upper_bound(idx) + 1), idx + 1);
+        }
+
+        let histogram = AtomicHdrHistogram::new(MetricType::SpamClassifyLatencyMiss);
+        assert_eq!(histogram.percentile(0.5), None);
+        for value in 1..=10_000 {
+            histogram.observe(value);
+        }
+        for (quantile, expected) in [(0.5, 5_000u64), (0.95, 9_500), (0.99, 9_900)] {
+            let value = histogram.percentile(quantile).unwrap();
+            assert!(
+                value >= expected && value <= expected + expected / 50,
+                "p{quantile}: {value}"
+            );
+        }
+        assert_eq!(histogram.percentile(1.0), Some(10_000));
+        assert_eq!(histogram.count(), 10_000);
+    }
+}
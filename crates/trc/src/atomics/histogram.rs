@@ -41,7 +41,7 @@ impl<const N: usize> AtomicHistogram<N> {
 
         for (idx, upper_bound) in self.upper_bounds.iter().enumerate() {
             if value < *upper_bound {
-                self.buckets.add(idx, value);
+                self.buckets.add(idx, 1);
                 return;
             }
         }
@@ -203,4 +203,50 @@ impl<const N: usize> AtomicHistogram<N> {
             ],
         )
     }
+
+    pub const fn new_percentages(id: MetricType) -> AtomicHistogram<12> {
+        AtomicHistogram::new(
+            id,
+            [
+                5, // Almost certainly ham
+                10,
+                20,
+                30,
+                40,
+                50, // Undecided
+                60,
+                70,
+                80,
+                90,
+                95,       // Almost certainly spam
+                u64::MAX, // Catch-all for everything else
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_bucket_counts() {
+        let histogram = AtomicHistogram::<12>::new_percentages(MetricType::MessageSize);
+        for value in [1, 3, 7, 55, 55, 99] {
+            histogram.observe(value);
+        }
+
+        // Buckets hold the number of observations, not their sum
+        assert_eq!(
+            histogram.buckets_vec(),
+            vec![2, 1, 0, 0, 0, 0, 2, 0, 0, 0, 0, 1]
+        );
+        assert_eq!(
+            histogram.buckets_vec().iter().sum::<u64>(),
+            histogram.count()
+        );
+        assert_eq!(histogram.sum(), 220);
+        assert_eq!(histogram.min(), Some(1));
+        assert_eq!(histogram.max(), Some(99));
+    }
 }
@@ -8,4 +8,5 @@ pub mod array;
 pub mod bitset;
 pub mod counter;
 pub mod gauge;
+pub mod hdr;
 pub mod histogram;
@@ -1020,11 +1020,20 @@ impl SpamEvent {
             SpamEvent::PyzorError => "Pyzor error",
             SpamEvent::ListUpdated => "Spam list updated",
             SpamEvent::Train => "Training spam filter",
+            SpamEvent::Untrain => "Untraining spam filter",
             SpamEvent::TrainBalance => "Balancing spam filter training data",
             SpamEvent::TrainError => "Error training spam filter",
             SpamEvent::Classify => "Classifying message for spam",
             SpamEvent::ClassifyError => "Error classifying message for spam",
+            SpamEvent::ClassifyCacheHit => "Spam filter token cache hit",
+            SpamEvent::ClassifyCacheMiss => "Spam filter token cache miss",
+            SpamEvent::BackendError => "Spam filter backend unavailable",
             SpamEvent::NotEnoughTrainingData => "Not enough training data for spam filter",
+            SpamEvent::CacheSave => "Spam filter token cache saved",
+            SpamEvent::CacheLoad => "Spam filter token cache loaded",
+            SpamEvent::ClassifyThrottled => "Spam filter classification throttled",
+            SpamEvent::ModelEmpty => "Spam filter model was never trained",
+            SpamEvent::OutboundSpam => "Outgoing message classified as spam",
         }
     }
 
@@ -1033,13 +1042,32 @@ impl SpamEvent {
             SpamEvent::PyzorError => "An error occurred with Pyzor",
             SpamEvent::ListUpdated => "The spam list has been updated",
             SpamEvent::Train => "The spam filter is being trained with the message",
+            SpamEvent::Untrain => {
+                "A previously trained message is being removed from the spam filter"
+            }
             SpamEvent::TrainBalance => "The spam filter training data is being balanced",
             SpamEvent::TrainError => "An error occurred while training the spam filter",
             SpamEvent::Classify => "The message is being classified for spam",
             SpamEvent::ClassifyError => "An error occurred while classifying the message for spam",
+            SpamEvent::ClassifyCacheHit => "A token weight was obtained from the spam filter cache",
+            SpamEvent::ClassifyCacheMiss => {
+                "A token weight had to be fetched from the spam filter store"
+            }
+            SpamEvent::BackendError => "The spam filter store could not be reached after retrying",
             SpamEvent::NotEnoughTrainingData => {
                 "There is not enough training data for the spam filter"
             }
+            SpamEvent::CacheSave => "The spam filter token cache was saved to disk",
+            SpamEvent::CacheLoad => "The spam filter token cache was loaded from disk",
+            SpamEvent::ClassifyThrottled => {
+                "The sender exceeded the classification rate, a cached verdict was returned"
+            }
+            SpamEvent::ModelEmpty => {
+                "The Bayes model has no training data, messages have to be trained before they can be classified"
+            }
+            SpamEvent::OutboundSpam => {
+                "A message sent by an authenticated user scored above the outbound spam threshold"
+            }
         }
     }
 }
@@ -1181,6 +1209,7 @@ impl TelemetryEvent {
             TelemetryEvent::OtelExporterError => "OpenTelemetry exporter error",
             TelemetryEvent::OtelMetricsExporterError => "OpenTelemetry metrics exporter error",
             TelemetryEvent::PrometheusExporterError => "Prometheus exporter error",
+            TelemetryEvent::StatsdExporterError => "StatsD exporter error",
         }
     }
 
@@ -1199,6 +1228,7 @@ impl TelemetryEvent {
             TelemetryEvent::PrometheusExporterError => {
                 "An error occurred with the Prometheus exporter"
             }
+            TelemetryEvent::StatsdExporterError => "An error occurred with the StatsD exporter",
         }
     }
 }
@@ -336,14 +336,23 @@ impl EventType {
                 | SieveEvent::ActionReject => Level::Debug,
             },
             EventType::Spam(event) => match event {
-                SpamEvent::PyzorError | SpamEvent::TrainError | SpamEvent::ClassifyError => {
-                    Level::Warn
-                }
+                SpamEvent::PyzorError
+                | SpamEvent::TrainError
+                | SpamEvent::ClassifyError
+                | SpamEvent::BackendError
+                | SpamEvent::OutboundSpam => Level::Warn,
                 SpamEvent::Train
+                | SpamEvent::Untrain
                 | SpamEvent::Classify
+                | SpamEvent::ClassifyCacheHit
+                | SpamEvent::ClassifyCacheMiss
                 | SpamEvent::NotEnoughTrainingData
-                | SpamEvent::TrainBalance => Level::Debug,
-                SpamEvent::ListUpdated => Level::Info,
+                | SpamEvent::TrainBalance
+                | SpamEvent::ClassifyThrottled => Level::Debug,
+                SpamEvent::ListUpdated
+                | SpamEvent::CacheSave
+                | SpamEvent::CacheLoad
+                | SpamEvent::ModelEmpty => Level::Info,
             },
             EventType::Http(event) => match event {
                 HttpEvent::ConnectionStart | HttpEvent::ConnectionEnd => Level::Debug,
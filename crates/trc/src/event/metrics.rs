@@ -36,6 +36,10 @@ impl MetricType {
             Self::QueueCount => "queue.count",
             Self::UserCount => "user.count",
             Self::DomainCount => "domain.count",
+            Self::SpamClassifyTime => "spam.classify-time",
+            Self::SpamClassifyScore => "spam.classify-score",
+            Self::SpamClassifyLatencyHit => "spam.classify-latency-cache-hit",
+            Self::SpamClassifyLatencyMiss => "spam.classify-latency-cache-miss",
         }
     }
 
@@ -68,6 +72,14 @@ impl MetricType {
             Self::QueueCount => "Total number of messages in the queue",
             Self::UserCount => "Total number of users",
             Self::DomainCount => "Total number of domains",
+            Self::SpamClassifyTime => "Bayes classification time",
+            Self::SpamClassifyScore => "Bayes classification spam probability",
+            Self::SpamClassifyLatencyHit => {
+                "Bayes classification latency when all tokens were cached"
+            }
+            Self::SpamClassifyLatencyMiss => {
+                "Bayes classification latency when tokens were fetched from the store"
+            }
         }
     }
 
@@ -86,7 +98,8 @@ impl MetricType {
             | Self::ImapRequestTime
             | Self::Pop3RequestTime
             | Self::SmtpRequestTime
-            | Self::SieveRequestTime => "milliseconds",
+            | Self::SieveRequestTime
+            | Self::SpamClassifyTime => "milliseconds",
             Self::MessageSize
             | Self::MessageAuthSize
             | Self::ReportOutgoingSize
@@ -100,6 +113,8 @@ impl MetricType {
             Self::QueueCount => "messages",
             Self::UserCount => "users",
             Self::DomainCount => "domains",
+            Self::SpamClassifyScore => "percent",
+            Self::SpamClassifyLatencyHit | Self::SpamClassifyLatencyMiss => "microseconds",
         }
     }
 
@@ -132,6 +147,10 @@ impl MetricType {
             Self::QueueCount => 24,
             Self::UserCount => 25,
             Self::DomainCount => 26,
+            Self::SpamClassifyTime => 27,
+            Self::SpamClassifyScore => 28,
+            Self::SpamClassifyLatencyHit => 29,
+            Self::SpamClassifyLatencyMiss => 30,
         }
     }
 
@@ -164,6 +183,10 @@ impl MetricType {
             24 => Some(Self::QueueCount),
             25 => Some(Self::UserCount),
             26 => Some(Self::DomainCount),
+            27 => Some(Self::SpamClassifyTime),
+            28 => Some(Self::SpamClassifyScore),
+            29 => Some(Self::SpamClassifyLatencyHit),
+            30 => Some(Self::SpamClassifyLatencyMiss),
             _ => None,
         }
     }
@@ -197,6 +220,10 @@ impl MetricType {
             "queue.count" => Some(Self::QueueCount),
             "user.count" => Some(Self::UserCount),
             "domain.count" => Some(Self::DomainCount),
+            "spam.classify-time" => Some(Self::SpamClassifyTime),
+            "spam.classify-score" => Some(Self::SpamClassifyScore),
+            "spam.classify-latency-cache-hit" => Some(Self::SpamClassifyLatencyHit),
+            "spam.classify-latency-cache-miss" => Some(Self::SpamClassifyLatencyMiss),
             _ => None,
         }
     }
@@ -230,6 +257,10 @@ impl MetricType {
             Self::QueueCount,
             Self::UserCount,
             Self::DomainCount,
+            Self::SpamClassifyTime,
+            Self::SpamClassifyScore,
+            Self::SpamClassifyLatencyHit,
+            Self::SpamClassifyLatencyMiss,
         ]
     }
 }
@@ -140,6 +140,22 @@ impl Event<EventType> {
         self.inner == EventType::Store(StoreEvent::AssertValueFailed)
     }
 
+    #[inline(always)]
+    pub fn is_backend_error(&self) -> bool {
+        matches!(
+            self.inner,
+            EventType::Store(
+                StoreEvent::FoundationdbError
+                    | StoreEvent::MysqlError
+                    | StoreEvent::PostgresqlError
+                    | StoreEvent::RocksdbError
+                    | StoreEvent::SqliteError
+                    | StoreEvent::RedisError
+                    | StoreEvent::PoolError
+            )
+        )
+    }
+
     pub fn key(&self, key: Key) -> Option<&Value> {
         self.keys
             .iter()
@@ -6,7 +6,9 @@
 
 use std::sync::atomic::Ordering;
 
-use atomics::{array::AtomicU32Array, gauge::AtomicGauge, histogram::AtomicHistogram};
+use atomics::{
+    array::AtomicU32Array, gauge::AtomicGauge, hdr::AtomicHdrHistogram, histogram::AtomicHistogram,
+};
 use ipc::{
     collector::{Collector, GlobalInterests, EVENT_TYPES},
     subscriber::Interests,
@@ -45,6 +47,15 @@ static STORE_BLOB_WRITE_TIME: AtomicHistogram<12> =
 static DNS_LOOKUP_TIME: AtomicHistogram<12> =
     AtomicHistogram::<10>::new_short_durations(MetricType::DnsLookupTime);
 
+static SPAM_CLASSIFY_TIME: AtomicHistogram<12> =
+    AtomicHistogram::<10>::new_short_durations(MetricType::SpamClassifyTime);
+static SPAM_CLASSIFY_SCORE: AtomicHistogram<12> =
+    AtomicHistogram::<12>::new_percentages(MetricType::SpamClassifyScore);
+static SPAM_CLASSIFY_LATENCY_HIT: AtomicHdrHistogram =
+    AtomicHdrHistogram::new(MetricType::SpamClassifyLatencyHit);
+static SPAM_CLASSIFY_LATENCY_MISS: AtomicHdrHistogram =
+    AtomicHdrHistogram::new(MetricType::SpamClassifyLatencyMiss);
+
 static SERVER_MEMORY: AtomicGauge = AtomicGauge::new(MetricType::ServerMemory);
 static QUEUE_COUNT: AtomicGauge = AtomicGauge::new(MetricType::QueueCount);
 static USER_COUNT: AtomicGauge = AtomicGauge::new(MetricType::UserCount);
@@ -193,6 +204,14 @@ impl Collector {
             EventType::Store(StoreEvent::DataIterate) => {
                 STORE_DATA_READ_TIME.observe(elapsed);
             }
+            EventType::Spam(SpamEvent::Classify) => {
+                SPAM_CLASSIFY_TIME.observe(elapsed);
+                if let Some((_, Value::Float(score))) =
+                    keys.iter().find(|(key, _)| *key == Key::Result)
+                {
+                    SPAM_CLASSIFY_SCORE.observe((score.clamp(0.0, 1.0) * 100.0).round() as u64);
+                }
+            }
 
             _ => {}
         }
@@ -251,11 +270,15 @@ impl Collector {
             &STORE_BLOB_READ_TIME,
             &STORE_BLOB_WRITE_TIME,
             &DNS_LOOKUP_TIME,
+            &SPAM_CLASSIFY_TIME,
+            &SPAM_CLASSIFY_SCORE,
         ];
         static C_HISTOGRAMS: &[&AtomicHistogram<12>] = &[
             &MESSAGE_DELIVERY_TIME,
             &MESSAGE_INCOMING_SIZE,
             &MESSAGE_SUBMISSION_SIZE,
+            &SPAM_CLASSIFY_TIME,
+            &SPAM_CLASSIFY_SCORE,
         ];
 
         if is_enterprise {
@@ -269,6 +292,20 @@ impl Collector {
         .filter(|h| h.is_active())
     }
 
+    pub fn collect_percentiles() -> impl Iterator<Item = &'static AtomicHdrHistogram> {
+        [&SPAM_CLASSIFY_LATENCY_HIT, &SPAM_CLASSIFY_LATENCY_MISS]
+            .into_iter()
+            .filter(|h| h.is_active())
+    }
+
+    pub fn read_percentiles(metric_type: MetricType) -> Option<&'static AtomicHdrHistogram> {
+        match metric_type {
+            MetricType::SpamClassifyLatencyHit => Some(&SPAM_CLASSIFY_LATENCY_HIT),
+            MetricType::SpamClassifyLatencyMiss => Some(&SPAM_CLASSIFY_LATENCY_MISS),
+            _ => None,
+        }
+    }
+
     #[inline(always)]
     pub fn read_event_metric(metric_id: usize) -> u32 {
         EVENT_COUNTERS.get(metric_id)
@@ -315,6 +352,10 @@ impl Collector {
             MetricType::SieveRequestTime => CONNECTION_METRICS[CONN_SIEVE].elapsed.average(),
             MetricType::UserCount => USER_COUNT.get() as f64,
             MetricType::DomainCount => DOMAIN_COUNT.get() as f64,
+            MetricType::SpamClassifyTime => SPAM_CLASSIFY_TIME.average(),
+            MetricType::SpamClassifyScore => SPAM_CLASSIFY_SCORE.average(),
+            MetricType::SpamClassifyLatencyHit => SPAM_CLASSIFY_LATENCY_HIT.average(),
+            MetricType::SpamClassifyLatencyMiss => SPAM_CLASSIFY_LATENCY_MISS.average(),
         }
     }
 
@@ -339,6 +380,10 @@ impl Collector {
             MetricType::DeliveryTotalTime => MESSAGE_DELIVERY_TIME.observe(value),
             MetricType::DeliveryTime => CONNECTION_METRICS[CONN_SMTP_OUT].elapsed.observe(value),
             MetricType::DnsLookupTime => DNS_LOOKUP_TIME.observe(value),
+            MetricType::SpamClassifyTime => SPAM_CLASSIFY_TIME.observe(value),
+            MetricType::SpamClassifyScore => SPAM_CLASSIFY_SCORE.observe(value),
+            MetricType::SpamClassifyLatencyHit => SPAM_CLASSIFY_LATENCY_HIT.observe(value),
+            MetricType::SpamClassifyLatencyMiss => SPAM_CLASSIFY_LATENCY_MISS.observe(value),
             _ => {}
         }
     }
@@ -579,10 +624,17 @@ impl EventType {
                 SpamEvent::PyzorError
                 | SpamEvent::ListUpdated
                 | SpamEvent::Train
+                | SpamEvent::Untrain
                 | SpamEvent::TrainError
                 | SpamEvent::Classify
                 | SpamEvent::ClassifyError
-                | SpamEvent::NotEnoughTrainingData,
+                | SpamEvent::ClassifyCacheHit
+                | SpamEvent::ClassifyCacheMiss
+                | SpamEvent::BackendError
+                | SpamEvent::NotEnoughTrainingData
+                | SpamEvent::ClassifyThrottled
+                | SpamEvent::ModelEmpty
+                | SpamEvent::OutboundSpam,
             ) => true,
             EventType::PushSubscription(_) => true,
             EventType::Cluster(
@@ -675,6 +727,7 @@ impl EventType {
                 | TelemetryEvent::OtelExporterError
                 | TelemetryEvent::OtelMetricsExporterError
                 | TelemetryEvent::PrometheusExporterError
+                | TelemetryEvent::StatsdExporterError
                 | TelemetryEvent::JournalError,
             ) => true,
             _ => false,
@@ -604,11 +604,20 @@ pub enum SpamEvent {
     PyzorError,
     ListUpdated,
     Train,
+    Untrain,
     TrainBalance,
     TrainError,
     Classify,
     ClassifyError,
+    ClassifyCacheHit,
+    ClassifyCacheMiss,
+    BackendError,
     NotEnoughTrainingData,
+    CacheSave,
+    CacheLoad,
+    ClassifyThrottled,
+    ModelEmpty,
+    OutboundSpam,
 }
 
 #[event_type]
@@ -671,6 +680,7 @@ pub enum TelemetryEvent {
     OtelExporterError,
     OtelMetricsExporterError,
     PrometheusExporterError,
+    StatsdExporterError,
     JournalError,
 }
 
@@ -978,6 +988,10 @@ pub enum MetricType {
     SieveRequestTime,
     UserCount,
     DomainCount,
+    SpamClassifyTime,
+    SpamClassifyScore,
+    SpamClassifyLatencyHit,
+    SpamClassifyLatencyMiss,
 }
 
 pub const TOTAL_EVENT_COUNT: usize = total_event_count!();
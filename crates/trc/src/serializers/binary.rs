@@ -866,6 +866,16 @@ impl EventType {
             EventType::Security(SecurityEvent::ScanBan) => 558,
             EventType::Store(StoreEvent::AzureError) => 559,
             EventType::TlsRpt(TlsRptEvent::RecordNotFound) => 560,
+            EventType::Spam(SpamEvent::ClassifyCacheHit) => 561,
+            EventType::Spam(SpamEvent::ClassifyCacheMiss) => 562,
+            EventType::Telemetry(TelemetryEvent::StatsdExporterError) => 563,
+            EventType::Spam(SpamEvent::Untrain) => 564,
+            EventType::Spam(SpamEvent::BackendError) => 565,
+            EventType::Spam(SpamEvent::CacheSave) => 566,
+            EventType::Spam(SpamEvent::CacheLoad) => 567,
+            EventType::Spam(SpamEvent::ClassifyThrottled) => 568,
+            EventType::Spam(SpamEvent::ModelEmpty) => 569,
+            EventType::Spam(SpamEvent::OutboundSpam) => 570,
         }
     }
 
@@ -1472,6 +1482,16 @@ impl EventType {
             558 => Some(EventType::Security(SecurityEvent::ScanBan)),
             559 => Some(EventType::Store(StoreEvent::AzureError)),
             560 => Some(EventType::TlsRpt(TlsRptEvent::RecordNotFound)),
+            561 => Some(EventType::Spam(SpamEvent::ClassifyCacheHit)),
+            562 => Some(EventType::Spam(SpamEvent::ClassifyCacheMiss)),
+            563 => Some(EventType::Telemetry(TelemetryEvent::StatsdExporterError)),
+            564 => Some(EventType::Spam(SpamEvent::Untrain)),
+            565 => Some(EventType::Spam(SpamEvent::BackendError)),
+            566 => Some(EventType::Spam(SpamEvent::CacheSave)),
+            567 => Some(EventType::Spam(SpamEvent::CacheLoad)),
+            568 => Some(EventType::Spam(SpamEvent::ClassifyThrottled)),
+            569 => Some(EventType::Spam(SpamEvent::ModelEmpty)),
+            570 => Some(EventType::Spam(SpamEvent::OutboundSpam)),
             _ => None,
         }
     }
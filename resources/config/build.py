@@ -39,7 +39,8 @@ scripts = {
     ],
     "track-replies": [
                 "config.sieve",
-                "replies_out.sieve"
+                "replies_out.sieve",
+                "outbound.sieve"
     ],
     "greylist": [
                 "config.sieve",
@@ -65,7 +66,8 @@ maps = ["spam_config.map",
         "domains_disposable.list", 
         "domains_free.list", 
         "mime_types.map", 
-        "url_redirectors.list"]
+        "url_redirectors.list",
+        "trackers.list"]
 
 
 def read_and_concatenate(files):